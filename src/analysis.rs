@@ -0,0 +1,795 @@
+use crate::landlord::{Item, Request};
+// Clap is required so that baselines can be selected directly from the command line.
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Histogram of the reuse (stack) distances of every request in a trace.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReuseDistance {
+    // The number of requests that were the first reference to their item. These have an infinite
+    // reuse distance and will miss in a cache of any size.
+    cold: u32,
+    // The number of requests with each reuse distance. The index into this vector is the number of
+    // distinct items referenced since the last request to the same item.
+    histogram: Vec<u32>,
+}
+
+impl ReuseDistance {
+    // Computes the reuse distance of each request using the standard LRU stack algorithm. We keep
+    // a stack of labels ordered from most to least recently used; the depth at which we find a
+    // request's label is its reuse distance and we then move that label to the top of the stack.
+    pub fn new(trace: &VecDeque<Request>) -> Self {
+        let mut stack: VecDeque<&String> = VecDeque::new();
+        let mut cold = 0;
+        let mut histogram = Vec::new();
+        for request in trace.iter() {
+            match stack.iter().position(|n| *n == request.get_label()) {
+                Some(depth) => {
+                    if histogram.len() <= depth {
+                        histogram.resize(depth + 1, 0);
+                    }
+                    histogram[depth] += 1;
+                    stack.remove(depth);
+                }
+                None => cold += 1,
+            }
+            stack.push_front(request.get_label());
+        }
+        Self { cold, histogram }
+    }
+
+    /// Gets the number of requests that had no previous reference to their item.
+    pub fn get_cold(&self) -> u32 {
+        self.cold
+    }
+
+    /// Gets the number of requests with the given reuse distance.
+    pub fn get_count(&self, distance: usize) -> u32 {
+        self.histogram.get(distance).copied().unwrap_or(0)
+    }
+
+    /// Gets the hit ratio an LRU cache holding `lines` unit-size items would achieve on the trace.
+    /// A request hits in such a cache exactly when its reuse distance is less than `lines`.
+    pub fn lru_hit_ratio(&self, lines: u32) -> f32 {
+        let total = self.cold + self.histogram.iter().sum::<u32>();
+        if total == 0 {
+            return 0.0;
+        }
+        let hits = self.histogram.iter().take(lines as usize).sum::<u32>();
+        hits as f32 / total as f32
+    }
+}
+
+/// The miss ratio of a unit-size LRU cache at one cache size.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MissRatioPoint {
+    lines: u32,
+    miss_ratio: f32,
+}
+
+/// The LRU miss ratio curve of a trace, giving the miss ratio of an LRU cache holding each number
+/// of unit-size items. Every size from one line up to the largest reuse distance plus one is
+/// listed; larger caches only miss on cold requests and so have the same miss ratio as the last
+/// point.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MissRatioCurve {
+    points: Vec<MissRatioPoint>,
+}
+
+impl MissRatioCurve {
+    // A request hits in an LRU cache of a given number of lines exactly when its reuse distance is
+    // less than that number, so the curve falls out of a running sum over the reuse distance
+    // histogram without simulating any cache.
+    pub fn new(reuse_distance: &ReuseDistance) -> Self {
+        let total = reuse_distance.cold + reuse_distance.histogram.iter().sum::<u32>();
+        let mut hits = 0;
+        let points = reuse_distance
+            .histogram
+            .iter()
+            .enumerate()
+            .map(|(distance, count)| {
+                hits += count;
+                MissRatioPoint {
+                    lines: distance as u32 + 1,
+                    miss_ratio: 1.0 - hits as f32 / total as f32,
+                }
+            })
+            .collect();
+        Self { points }
+    }
+
+    /// Gets the miss ratio of an LRU cache holding `lines` unit-size items.
+    pub fn miss_ratio(&self, lines: u32) -> f32 {
+        match self.points.iter().rev().find(|n| n.lines <= lines) {
+            Some(point) => point.miss_ratio,
+            None => 1.0,
+        }
+    }
+}
+
+/// A Zipf distribution fitted to how often each item in a trace is requested. Ranking the items
+/// from most to least requested, a Zipf workload requests the item of rank r in proportion to
+/// r^-exponent, which is a line of slope -exponent on a log-log plot of request count against rank.
+/// The coefficient of determination says how well that line fits, with 1 a perfect fit.
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+pub struct ZipfFit {
+    exponent: f64,
+    r_squared: f64,
+    // The number of distinct items the fit was made over.
+    items: u32,
+}
+
+impl ZipfFit {
+    // Fits the exponent by least squares on the log of each item's request count against the log
+    // of its rank. Returns None if fewer than two distinct items are requested, since there is no
+    // line to fit through a single point.
+    pub fn new(trace: &VecDeque<Request>) -> Option<Self> {
+        let mut counts: BTreeMap<&String, u32> = BTreeMap::new();
+        for request in trace.iter() {
+            *counts.entry(request.get_label()).or_insert(0) += 1;
+        }
+        let mut counts: Vec<u32> = counts.into_values().collect();
+        if counts.len() < 2 {
+            return None;
+        }
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        let xs: Vec<f64> = (1..=counts.len()).map(|rank| (rank as f64).ln()).collect();
+        let ys: Vec<f64> = counts.iter().map(|n| f64::from(*n).ln()).collect();
+        let n = xs.len() as f64;
+        let mean_x = xs.iter().sum::<f64>() / n;
+        let mean_y = ys.iter().sum::<f64>() / n;
+        let (mut cov, mut var_x) = (0.0, 0.0);
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            cov += (x - mean_x) * (y - mean_y);
+            var_x += (x - mean_x) * (x - mean_x);
+        }
+        // Every item being requested equally often is fit exactly by a flat line, which is the
+        // only case in which the correlation is undefined.
+        let r_squared = pearson(&xs, &ys).map_or(1.0, |r| r * r);
+        // Counts only fall with rank, so the slope is never positive and its magnitude is the
+        // exponent.
+        Some(Self {
+            exponent: (cov / var_x).abs(),
+            r_squared,
+            items: counts.len() as u32,
+        })
+    }
+
+    /// Gets the fitted Zipf exponent.
+    pub fn get_exponent(&self) -> f64 {
+        self.exponent
+    }
+}
+
+/// The Shannon entropy in bits of how the trace's requests are spread over its items, where each
+/// item's probability is the fraction of requests for it. The normalized entropy divides this by
+/// its largest possible value, the log of the number of distinct items, so a trace requesting every
+/// item equally often has a normalized entropy of 1 and one requesting a single item has 0. The
+/// lower it is, the more skewed and so the more cacheable the workload.
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+pub struct AccessEntropy {
+    entropy: f64,
+    normalized: f64,
+    // The number of distinct items requested.
+    items: u32,
+}
+
+impl AccessEntropy {
+    // Computes the entropy from how many times each item is requested. Returns None for an empty
+    // trace, which has no distribution to measure.
+    pub fn new(trace: &VecDeque<Request>) -> Option<Self> {
+        let mut counts: BTreeMap<&String, u32> = BTreeMap::new();
+        for request in trace.iter() {
+            *counts.entry(request.get_label()).or_insert(0) += 1;
+        }
+        if counts.is_empty() {
+            return None;
+        }
+        let total = trace.len() as f64;
+        let entropy = counts
+            .values()
+            .map(|n| {
+                let p = f64::from(*n) / total;
+                -p * p.log2()
+            })
+            .sum::<f64>()
+            // A single item sums to -0.0
+            .abs();
+        let max = (counts.len() as f64).log2();
+        Some(Self {
+            entropy,
+            normalized: if max > 0.0 { entropy / max } else { 0.0 },
+            items: counts.len() as u32,
+        })
+    }
+}
+
+/// The Che approximation of an LRU cache's hit ratio, which assumes the trace follows the
+/// independent reference model, where every request picks each item with a fixed probability. An
+/// LRU cache then holds an item exactly when it was requested within the last `characteristic_time`
+/// requests, the time at which the expected size of the items requested in that window fills the
+/// cache. The characteristic time is left out when the whole catalog fits, since nothing is ever
+/// evicted. The predicted hit ratio is set against the hit ratio the full cache actually had.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CheApproximation {
+    size: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    characteristic_time: Option<f64>,
+    hit_ratio: f64,
+    simulated_hit_ratio: f64,
+    // The predicted hit ratio less the simulated one.
+    error: f64,
+    // The predicted probability that a request for each item hits.
+    items: BTreeMap<String, f64>,
+}
+
+// How many halvings of the bracket around the characteristic time we do before settling on it.
+const CHE_ITERATIONS: u32 = 100;
+
+impl CheApproximation {
+    // Estimates each item's request rate from how often the trace requests it, then solves for the
+    // characteristic time by bisection. The expected size of the items requested within a window
+    // only grows with the window, so there is a single time at which it fills the cache.
+    pub fn new(trace: &VecDeque<Request>, size: u32) -> Self {
+        let mut counts: BTreeMap<&String, (u32, u32)> = BTreeMap::new();
+        for request in trace.iter() {
+            counts
+                .entry(request.get_label())
+                .or_insert((0, request.get_size()))
+                .0 += 1;
+        }
+        let total = trace.len().max(1) as f64;
+        let rates: Vec<(&String, f64, f64)> = counts
+            .into_iter()
+            .map(|(label, (count, item_size))| {
+                (label, f64::from(count) / total, f64::from(item_size))
+            })
+            .collect();
+        let occupancy = |time: f64| {
+            rates
+                .iter()
+                .map(|(_, rate, item_size)| item_size * (1.0 - (-rate * time).exp()))
+                .sum::<f64>()
+        };
+        let capacity = f64::from(size);
+        let characteristic_time = if rates.iter().map(|(_, _, n)| n).sum::<f64>() <= capacity {
+            None
+        } else {
+            let mut hi = 1.0;
+            while occupancy(hi) < capacity {
+                hi *= 2.0;
+            }
+            let mut lo = 0.0;
+            for _ in 0..CHE_ITERATIONS {
+                let mid = lo + (hi - lo) / 2.0;
+                if occupancy(mid) < capacity {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+            Some(lo + (hi - lo) / 2.0)
+        };
+        let items: BTreeMap<String, f64> = rates
+            .iter()
+            .map(|(label, rate, _)| {
+                let hit = characteristic_time.map_or(1.0, |time| 1.0 - (-rate * time).exp());
+                (label.to_string(), hit)
+            })
+            .collect();
+        let hit_ratio = rates
+            .iter()
+            .map(|(label, rate, _)| rate * items[*label])
+            .sum();
+        Self {
+            size,
+            characteristic_time,
+            hit_ratio,
+            simulated_hit_ratio: 0.0,
+            error: 0.0,
+            items,
+        }
+    }
+
+    /// Sets the hit ratio the full cache had in simulation to compare the prediction against.
+    pub fn compare(&mut self, simulated_hit_ratio: f64) {
+        self.simulated_hit_ratio = simulated_hit_ratio;
+        self.error = self.hit_ratio - simulated_hit_ratio;
+    }
+
+    /// Drops the predictions for every item not in the given set.
+    pub fn retain_items(&mut self, keep: impl Fn(&String) -> bool) {
+        self.items.retain(|label, _| keep(label));
+    }
+}
+
+/// A simple cache whose cost over the trace is worked out alongside the run to compare against. An
+/// infinite cache never evicts, so it only pays for each item on its first request. No cache can
+/// pay less than these compulsory misses, which makes it a quick lower bound on cost. An optimal
+/// offline cache is too expensive to work out alongside every run, so it can only be imported from
+/// decisions another tool made.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Baseline {
+    Infinite,
+    #[value(skip)]
+    Opt,
+}
+
+impl Baseline {
+    /// Every baseline, in the order they should be listed to users.
+    pub fn variants() -> Vec<Self> {
+        vec![Self::Infinite, Self::Opt]
+    }
+    /// How to compare against this baseline from the command line. OPT is imported from a file
+    /// rather than selected by name.
+    pub fn usage(&self) -> &'static str {
+        match self {
+            Self::Infinite => "--baseline infinite",
+            Self::Opt => "--opt-decisions <OPT FILE>",
+        }
+    }
+    /// A short description of the cache this baseline stands for.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Infinite => "never evicts, so it only pays for the first request for each item",
+            Self::Opt => "an optimal offline cache, imported from the decisions another tool made",
+        }
+    }
+}
+
+/// What an optimal offline cache did on every request of the trace, as worked out by another tool.
+/// Either whether it hit each request, e.g. `hits = [false, true]`, or what it paid for each, e.g.
+/// `costs = [3, 0]`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OptDecisions {
+    Hits(Vec<bool>),
+    Costs(Vec<i64>),
+}
+
+/// What a baseline cache paid over the trace and how many requests it missed on. The full ratio is
+/// the full cache's cost divided by the baseline's, and is left out if the baseline paid nothing.
+/// The misses are left out for a baseline imported as costs alone.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct BaselineCost {
+    baseline: Baseline,
+    cost: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    misses: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    full_ratio: Option<f64>,
+}
+
+impl BaselineCost {
+    // Works out what the baseline cache pays for the trace. The infinite cache only needs to know
+    // which items it has already seen, paying each one's cost on its first request.
+    pub fn new(baseline: Baseline, trace: &VecDeque<Request>) -> Self {
+        match baseline {
+            Baseline::Infinite => {
+                let mut seen = BTreeSet::new();
+                let (mut cost, mut misses) = (0, 0);
+                for request in trace.iter() {
+                    if seen.insert(request.get_label()) {
+                        cost += request.get_cost().0 as i64;
+                        misses += 1;
+                    }
+                }
+                Self {
+                    baseline,
+                    cost,
+                    misses: Some(misses),
+                    full_ratio: None,
+                }
+            }
+            Baseline::Opt => {
+                unreachable!("An OPT baseline can only be imported from its decisions")
+            }
+        }
+    }
+
+    /// Works out what an optimal offline cache paid for the trace from its imported decisions. A
+    /// miss pays the request's cost, just as it does in our caches. Returns an error if there is
+    /// not exactly one decision per request.
+    pub fn from_opt(decisions: &OptDecisions, trace: &VecDeque<Request>) -> Result<Self, String> {
+        let len = match decisions {
+            OptDecisions::Hits(hits) => hits.len(),
+            OptDecisions::Costs(costs) => costs.len(),
+        };
+        if len != trace.len() {
+            return Err(format!(
+                "The OPT decisions cover {} requests but the trace has {}",
+                len,
+                trace.len()
+            ));
+        }
+        let (cost, misses) = match decisions {
+            OptDecisions::Hits(hits) => {
+                let missed = trace.iter().zip(hits.iter()).filter(|(_, hit)| !**hit);
+                let (cost, misses) = missed.fold((0, 0), |(cost, misses), (request, _)| {
+                    (cost + request.get_cost().0 as i64, misses + 1)
+                });
+                (cost, Some(misses))
+            }
+            OptDecisions::Costs(costs) => (costs.iter().sum(), None),
+        };
+        Ok(Self {
+            baseline: Baseline::Opt,
+            cost,
+            misses,
+            full_ratio: None,
+        })
+    }
+
+    /// Sets the cost the full cache paid to compare the baseline against.
+    pub fn compare(&mut self, full_cost: i64) {
+        self.full_ratio = (self.cost > 0).then(|| full_cost as f64 / self.cost as f64);
+    }
+}
+
+/// Gets the Pearson correlation coefficient between two equally long series, or None if either
+/// series is constant or they are empty, since the coefficient is undefined there.
+pub fn pearson(xs: &[f64], ys: &[f64]) -> Option<f64> {
+    let n = xs.len().min(ys.len());
+    if n == 0 {
+        return None;
+    }
+    let mean_x = xs[..n].iter().sum::<f64>() / n as f64;
+    let mean_y = ys[..n].iter().sum::<f64>() / n as f64;
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in xs[..n].iter().zip(ys[..n].iter()) {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x) * (x - mean_x);
+        var_y += (y - mean_y) * (y - mean_y);
+    }
+    if var_x == 0.0 || var_y == 0.0 {
+        return None;
+    }
+    Some(cov / (var_x * var_y).sqrt())
+}
+
+/// One bucket of a histogram, counting the values between `min` and `max` inclusive.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Bucket {
+    min: i64,
+    max: i64,
+    count: u32,
+}
+
+/// Histograms of the sizes and costs of the items in a catalog. Positive values are bucketed by
+/// powers of two, so each bucket spans from a power of two up to just below the next. Negative
+/// values are bucketed the same way by magnitude and zero has a bucket of its own. Only buckets
+/// holding at least one item are kept.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CatalogHistograms {
+    sizes: Vec<Bucket>,
+    costs: Vec<Bucket>,
+}
+
+impl CatalogHistograms {
+    pub fn new(items: &[Item]) -> Self {
+        Self {
+            sizes: Self::histogram(items.iter().map(|n| n.get_size() as i64)),
+            costs: Self::histogram(items.iter().map(|n| n.get_cost().0 as i64)),
+        }
+    }
+
+    // Gets the inclusive bounds of the power-of-two bucket a value falls into.
+    fn bucket(value: i64) -> (i64, i64) {
+        if value == 0 {
+            return (0, 0);
+        }
+        let magnitude = value.unsigned_abs();
+        let low = 1u64 << (63 - magnitude.leading_zeros());
+        let high = low.saturating_mul(2) - 1;
+        if value > 0 {
+            (low as i64, high.min(i64::MAX as u64) as i64)
+        } else {
+            (-(high.min(i64::MAX as u64) as i64), -(low as i64))
+        }
+    }
+
+    fn histogram(values: impl Iterator<Item = i64>) -> Vec<Bucket> {
+        let mut counts: BTreeMap<(i64, i64), u32> = BTreeMap::new();
+        for value in values {
+            *counts.entry(Self::bucket(value)).or_insert(0) += 1;
+        }
+        counts
+            .into_iter()
+            .map(|((min, max), count)| Bucket { min, max, count })
+            .collect()
+    }
+
+    /// Gets the number of items whose size falls in the bucket starting at `min`.
+    pub fn get_size_count(&self, min: i64) -> u32 {
+        self.sizes
+            .iter()
+            .find(|n| n.min == min)
+            .map(|n| n.count)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::landlord::{HitPolicy, Landlord, RequestResult, TiebreakingPolicy};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::sync::Arc;
+
+    fn items(labels: &str) -> Vec<Item> {
+        labels
+            .chars()
+            .map(|label| Item::new(label.to_string(), 1, 1))
+            .collect()
+    }
+
+    // Builds a trace requesting the items with the given labels in order.
+    fn trace<'a>(items: &'a [Item], labels: &str) -> VecDeque<Request<'a>> {
+        labels
+            .chars()
+            .map(|label| {
+                let item = items
+                    .iter()
+                    .find(|n| *n.get_label() == label.to_string())
+                    .unwrap();
+                Request::new(item, None)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reuse_distances_and_miss_ratios_of_a_known_trace() {
+        let items = items("abc");
+        // a and b are cold, then a at distance 0, a at 1, c cold, b at 2 and a at 2
+        let trace = trace(&items, "aabacba");
+        let reuse = ReuseDistance::new(&trace);
+        assert_eq!(reuse.get_cold(), 3);
+        assert_eq!(
+            (0..4).map(|n| reuse.get_count(n)).collect::<Vec<_>>(),
+            vec![1, 1, 2, 0]
+        );
+        let curve = MissRatioCurve::new(&reuse);
+        let expected = [(0, 1.0), (1, 6.0 / 7.0), (2, 5.0 / 7.0), (3, 3.0 / 7.0)];
+        for (lines, miss_ratio) in expected {
+            assert!((curve.miss_ratio(lines) - miss_ratio).abs() < 1e-6);
+        }
+        // Larger caches only miss on the cold requests
+        assert!((curve.miss_ratio(100) - 3.0 / 7.0).abs() < 1e-6);
+        // Unit costs and sizes make Landlord with LRU tiebreaking plain LRU, which the curve
+        // should predict at every size.
+        for lines in 1..=4 {
+            let mut cache =
+                Landlord::new(lines, TiebreakingPolicy::Lru, Arc::new(HitPolicy::Lru)).with_seed(0);
+            let hits = trace
+                .iter()
+                .filter(|n| matches!(cache.request(**n), RequestResult::Hit))
+                .count();
+            assert!((reuse.lru_hit_ratio(lines) - hits as f32 / 7.0).abs() < 1e-6);
+            assert!((curve.miss_ratio(lines) - (1.0 - hits as f32 / 7.0)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn miss_ratio_curve_predicts_lru_at_every_size() {
+        let items: Vec<Item> = (0..16).map(|n| Item::new(n.to_string(), 1, 1)).collect();
+        let mut rng = StdRng::seed_from_u64(3);
+        // Skewed towards the low items so that the curve falls gradually rather than all at once
+        let trace: VecDeque<Request> = (0..400)
+            .map(|_| {
+                let index = rng.random_range(0..16).min(rng.random_range(0..16));
+                Request::new(&items[index], None)
+            })
+            .collect();
+        let curve = MissRatioCurve::new(&ReuseDistance::new(&trace));
+        let mut previous = 1.0;
+        for lines in [1, 2, 4, 6, 8, 12, 16] {
+            let mut cache =
+                Landlord::new(lines, TiebreakingPolicy::Lru, Arc::new(HitPolicy::Lru)).with_seed(0);
+            let misses = trace
+                .iter()
+                .filter(|n| !matches!(cache.request(**n), RequestResult::Hit))
+                .count();
+            let simulated = misses as f32 / trace.len() as f32;
+            assert!(
+                (curve.miss_ratio(lines) - simulated).abs() < 1e-6,
+                "{} lines: curve gave {} but LRU missed {}",
+                lines,
+                curve.miss_ratio(lines),
+                simulated
+            );
+            assert!(simulated <= previous);
+            previous = simulated;
+        }
+        // Once every item fits only the first request for each misses
+        assert!((previous - 16.0 / 400.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn zipf_fit_recovers_the_exponent_of_a_sampled_trace() {
+        let items: Vec<Item> = (0..20).map(|n| Item::new(n.to_string(), 1, 1)).collect();
+        for exponent in [0.7, 1.0, 1.3] {
+            // Samples ranks in proportion to rank^-exponent by inverting the cumulative weights
+            let weights: Vec<f64> = (1..=20)
+                .map(|rank| f64::powf(rank as f64, -exponent))
+                .collect();
+            let total: f64 = weights.iter().sum();
+            let mut rng = StdRng::seed_from_u64(1);
+            let trace: VecDeque<Request> = (0..50000)
+                .map(|_| {
+                    let mut target = rng.random::<f64>() * total;
+                    let rank = weights
+                        .iter()
+                        .position(|weight| {
+                            target -= weight;
+                            target <= 0.0
+                        })
+                        .unwrap_or(19);
+                    Request::new(&items[rank], None)
+                })
+                .collect();
+            let fit = ZipfFit::new(&trace).unwrap();
+            assert!(
+                (fit.get_exponent() - exponent).abs() < 0.05,
+                "Recovered {} for {}",
+                fit.get_exponent(),
+                exponent
+            );
+            assert!(fit.r_squared > 0.98);
+            assert_eq!(fit.items, 20);
+        }
+        // A single item has no line to fit
+        let trace: VecDeque<Request> = [Request::new(&items[0], None)].into();
+        assert!(ZipfFit::new(&trace).is_none());
+    }
+
+    #[test]
+    fn che_approximation_predicts_lru_on_an_independent_trace() {
+        let items: Vec<Item> = (0..50).map(|n| Item::new(n.to_string(), 1, 1)).collect();
+        // Every request picks an item independently with Zipf probabilities
+        let weights: Vec<f64> = (1..=50).map(|rank| 1.0 / rank as f64).collect();
+        let total: f64 = weights.iter().sum();
+        let mut rng = StdRng::seed_from_u64(3);
+        let trace: VecDeque<Request> = (0..50000)
+            .map(|_| {
+                let mut target = rng.random::<f64>() * total;
+                let rank = weights
+                    .iter()
+                    .position(|weight| {
+                        target -= weight;
+                        target <= 0.0
+                    })
+                    .unwrap_or(49);
+                Request::new(&items[rank], None)
+            })
+            .collect();
+        for size in [2, 5, 10, 20] {
+            let mut cache =
+                Landlord::new(size, TiebreakingPolicy::Lru, Arc::new(HitPolicy::Lru)).with_seed(0);
+            let hits = trace
+                .iter()
+                .filter(|n| matches!(cache.request(**n), RequestResult::Hit))
+                .count();
+            let mut che = CheApproximation::new(&trace, size);
+            che.compare(hits as f64 / trace.len() as f64);
+            assert!(
+                che.error.abs() < 0.01,
+                "{} lines: predicted {} but LRU hit {}",
+                size,
+                che.hit_ratio,
+                che.simulated_hit_ratio
+            );
+            assert!(che.characteristic_time.is_some());
+        }
+        // Once the whole catalog fits, every request is predicted to hit
+        let che = CheApproximation::new(&trace, 50);
+        assert!(che.characteristic_time.is_none());
+        assert!((che.hit_ratio - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn entropy_is_log2_of_the_items_for_a_uniform_trace() {
+        let items = items("abcdefgh");
+        for n in [2, 3, 5, 8] {
+            // Every one of the first n items is requested four times
+            let uniform = AccessEntropy::new(&trace(&items, &"abcdefgh"[..n].repeat(4))).unwrap();
+            assert!((uniform.entropy - (n as f64).log2()).abs() < 1e-12);
+            assert!((uniform.normalized - 1.0).abs() < 1e-12);
+            assert_eq!(uniform.items, n as u32);
+        }
+        // A single item is entirely predictable, however often it is requested
+        let single = AccessEntropy::new(&trace(&items, "aaaaaa")).unwrap();
+        assert_eq!(
+            (single.entropy, single.normalized, single.items),
+            (0.0, 0.0, 1)
+        );
+        assert!(single.entropy.is_sign_positive());
+        // A skewed trace lies in between
+        let skewed = AccessEntropy::new(&trace(&items, "aaaaaaab")).unwrap();
+        assert!(skewed.entropy > 0.0 && skewed.entropy < 1.0);
+        assert!(AccessEntropy::new(&VecDeque::new()).is_none());
+    }
+
+    #[test]
+    fn infinite_baseline_pays_each_distinct_item_once() {
+        let items: Vec<Item> = [("a", 2), ("b", 3), ("c", 5), ("d", 7)]
+            .iter()
+            .map(|(label, cost)| Item::new(label.to_string(), *cost, 1))
+            .collect();
+        // d is in the catalog but never requested, so it is never paid for
+        let trace = trace(&items, "abcacbbaac");
+        let infinite = BaselineCost::new(Baseline::Infinite, &trace);
+        assert_eq!((infinite.cost, infinite.misses), (2 + 3 + 5, Some(3)));
+        // A cache large enough for everything requested pays exactly the same
+        let mut cache =
+            Landlord::new(3, TiebreakingPolicy::Lru, Arc::new(HitPolicy::Lru)).with_seed(0);
+        let paid: i64 = trace
+            .iter()
+            .filter(|n| !matches!(cache.request(**n), RequestResult::Hit))
+            .map(|n| n.get_cost().0 as i64)
+            .sum();
+        assert_eq!(paid, infinite.cost);
+    }
+
+    #[test]
+    fn imported_opt_decisions_give_the_baseline_ratios() {
+        let items: Vec<Item> = [("a", 2), ("b", 3), ("c", 5)]
+            .iter()
+            .map(|(label, cost)| Item::new(label.to_string(), *cost, 1))
+            .collect();
+        let trace = trace(&items, "abcabc");
+        let path = std::env::temp_dir().join(format!("csim-opt-{}", std::process::id()));
+        let import = |data: &str| {
+            std::fs::write(&path, data).unwrap();
+            crate::input::read_opt_decisions(&path).unwrap()
+        };
+        let hits = import("hits = [false, false, false, true, false, true]");
+        let costs = import("costs = [2, 3, 5, 0, 3, 0]");
+        let short = import("hits = [false, true]");
+        std::fs::remove_file(&path).unwrap();
+
+        // Missing on b the second time costs 3 on top of the compulsory 10
+        let mut from_hits = BaselineCost::from_opt(&hits, &trace).unwrap();
+        assert_eq!((from_hits.cost, from_hits.misses), (13, Some(4)));
+        from_hits.compare(26);
+        assert_eq!(from_hits.full_ratio, Some(2.0));
+        // Costs give the same total, but not how many requests missed
+        let mut from_costs = BaselineCost::from_opt(&costs, &trace).unwrap();
+        assert_eq!((from_costs.cost, from_costs.misses), (13, None));
+        from_costs.compare(13);
+        assert_eq!(from_costs.full_ratio, Some(1.0));
+        // A baseline that paid nothing has no ratio
+        let mut free = BaselineCost::from_opt(&OptDecisions::Costs(vec![0; 6]), &trace).unwrap();
+        free.compare(13);
+        assert_eq!(free.full_ratio, None);
+
+        assert!(BaselineCost::from_opt(&short, &trace)
+            .unwrap_err()
+            .contains("cover 2 requests but the trace has 6"));
+    }
+
+    #[test]
+    fn bimodal_catalog_fills_two_size_buckets() {
+        let catalog: Vec<Item> = [(1, 1), (1, 0), (1, -3), (64, 5), (80, 5), (127, 9)]
+            .iter()
+            .enumerate()
+            .map(|(n, (size, cost))| Item::new(n.to_string(), *cost, *size))
+            .collect();
+        let histograms = CatalogHistograms::new(&catalog);
+        let bounds = |buckets: &[Bucket]| -> Vec<(i64, i64, u32)> {
+            buckets.iter().map(|n| (n.min, n.max, n.count)).collect()
+        };
+        // Small and large items each land in one bucket, with nothing in between
+        assert_eq!(bounds(&histograms.sizes), vec![(1, 1, 3), (64, 127, 3)]);
+        assert_eq!(histograms.get_size_count(64), 3);
+        assert_eq!(histograms.get_size_count(2), 0);
+        // Zero has its own bucket and negative costs are bucketed by magnitude
+        assert_eq!(
+            bounds(&histograms.costs),
+            vec![(-3, -2, 1), (0, 0, 1), (1, 1, 1), (4, 7, 2), (8, 15, 1)]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+
+// Everything that can go wrong while setting up or running a simulation. Errors are reported to
+// the user by printing them, so each variant carries enough context to say what went wrong where.
+#[derive(Debug)]
+pub enum SimError {
+    // A file could not be read or written.
+    IoError(std::io::Error),
+    // The input file was malformed. Line and column are 1-indexed and point at the start of the
+    // offending TOML or log field.
+    ParseError {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    // A SQLite database could not be queried.
+    Database(rusqlite::Error),
+    // The items table declared the same label more than once.
+    DuplicateItem(String),
+    // An alias pointed at a label that is not in the items table.
+    UnknownAlias {
+        alias: String,
+        label: String,
+    },
+    // An alias was also the label of an item, so requests for it would be ambiguous.
+    AliasShadowsItem(String),
+    // A request in the trace, counted from 0, was for a label that is neither an item nor an alias.
+    UnknownItem {
+        index: usize,
+        label: String,
+    },
+    // The caches could not be set up as asked, such as when the preloaded items do not fit.
+    Setup(String),
+    // What a run logged did not add up for the trace it ran over.
+    Reconcile(String),
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimError::IoError(e) => write!(f, "I/O error: {}", e),
+            SimError::ParseError {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Could not parse input at line {}, column {}: {}",
+                line, column, message
+            ),
+            SimError::Database(e) => write!(f, "Could not read database: {}", e),
+            SimError::DuplicateItem(label) => {
+                write!(
+                    f,
+                    "Item {} is declared more than once in the items table",
+                    label
+                )
+            }
+            SimError::UnknownAlias { alias, label } => write!(
+                f,
+                "Alias {} refers to item {} which is not in the items table",
+                alias, label
+            ),
+            SimError::AliasShadowsItem(alias) => {
+                write!(f, "Alias {} is also the label of an item", alias)
+            }
+            SimError::UnknownItem { index, label } => write!(
+                f,
+                "Request {} is for item {} which is not in the items table",
+                index, label
+            ),
+            SimError::Setup(msg) | SimError::Reconcile(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SimError {}
+
+impl From<std::io::Error> for SimError {
+    fn from(e: std::io::Error) -> Self {
+        SimError::IoError(e)
+    }
+}
+
+impl From<rusqlite::Error> for SimError {
+    fn from(e: rusqlite::Error) -> Self {
+        SimError::Database(e)
+    }
+}
+
+impl SimError {
+    // Builds a parse error from a TOML deserialization error, working out the line and column of
+    // the error's span in the source it was parsing.
+    pub fn from_toml(e: toml::de::Error, source: &str) -> Self {
+        let offset = e
+            .span()
+            .map(|span| span.start)
+            .unwrap_or(0)
+            .min(source.len());
+        let before = &source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map(|n| n + 1).unwrap_or(0) + 1;
+        SimError::ParseError {
+            line,
+            column,
+            message: e.message().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TraceInfo;
+
+    // Gets the line and column a TOML trace failed to parse at, along with the error as printed.
+    fn parse_error_at(data: &str) -> (usize, usize, String) {
+        let e = toml::from_str::<TraceInfo>(data).unwrap_err();
+        match SimError::from_toml(e, data) {
+            error @ SimError::ParseError { line, column, .. } => (line, column, error.to_string()),
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn toml_errors_point_at_the_offending_line() {
+        let items = "items = [\n  { label = 'a', cost = 1, size = 1 },\n";
+        // A value of the wrong type
+        let data = format!(
+            "{}  {{ label = 'b', cost = 'x', size = 1 }},\n]\ntrace = ['a']",
+            items
+        );
+        let (line, column, printed) = parse_error_at(&data);
+        assert_eq!(line, 3);
+        assert!(column > 1);
+        assert!(printed.contains("line 3"), "{}", printed);
+        // Malformed syntax
+        let data = format!("{}]\ntrace = ['a'\n", items);
+        assert_eq!(parse_error_at(&data).0, 5);
+        // A missing field is reported against the table it is missing from
+        let data = "items = [\n  { label = 'a', cost = 1 },\n]\ntrace = []";
+        assert_eq!(parse_error_at(data).0, 2);
+    }
+}
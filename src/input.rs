@@ -0,0 +1,529 @@
+use crate::analysis::OptDecisions;
+use crate::error::SimError;
+use crate::landlord::{Item, Request};
+use crate::{MatrixInfo, TraceEntry, TraceInfo};
+// Clap is required so that input formats can be selected directly from the command line.
+use clap::ValueEnum;
+use rusqlite::Connection;
+use serde::de::{self, Deserializer, Visitor};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// The formats we can read a trace from. TOML is the original format described in the README.
+// SQLite databases hold the catalog in an `items` table with `label`, `cost` and `size` columns and
+// the trace in a `requests` table with `seq` and `label` columns, where requests are serviced in
+// ascending `seq` order. Web logs are access logs with one request per line made up of a timestamp,
+// an object id and the object's size, separated by whitespace. They have no items table, so the
+// catalog is built from the objects the log requests.
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum InputFormat {
+    #[default]
+    Toml,
+    Sqlite,
+    Weblog,
+}
+
+// Which size an object in a web log is given when its reported size changes between requests.
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum WeblogSize {
+    #[default]
+    First,
+    Max,
+}
+
+// The cost given to every object read from a web log. Logs do not say what a miss costs, so each
+// miss is counted once.
+const WEBLOG_COST: i32 = 1;
+
+// The suffixes a size may be written with and the number of bytes each stands for. Binary units
+// are powers of 1024 and decimal units powers of 1000.
+const SIZE_UNITS: [(&str, f64); 7] = [
+    ("B", 1.0),
+    ("KiB", 1024.0),
+    ("MiB", 1048576.0),
+    ("GiB", 1073741824.0),
+    ("KB", 1e3),
+    ("MB", 1e6),
+    ("GB", 1e9),
+];
+
+// Parses a human-readable size such as `500MB` or `1.5GiB` into a number of bytes. A bare integer
+// is taken as is. Fractional sizes with a unit are rounded down to a whole number of bytes.
+pub fn parse_size(text: &str) -> Result<u32, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = (&text[..split], text[split..].trim_start());
+    if number.is_empty() {
+        return Err(format!("{} is not a valid size", text));
+    }
+    if unit.is_empty() {
+        return number
+            .parse()
+            .map_err(|_| format!("{} is not a valid size", text));
+    }
+    let multiplier = SIZE_UNITS
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .map(|(_, multiplier)| *multiplier)
+        .ok_or_else(|| format!("{} has unknown size unit {}", text, unit))?;
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("{} is not a valid size", text))?;
+    let bytes = (value * multiplier).floor();
+    if bytes > u32::MAX as f64 {
+        return Err(format!(
+            "{} is larger than the largest size {}",
+            text,
+            u32::MAX
+        ));
+    }
+    Ok(bytes as u32)
+}
+
+// Deserializes an item size written either as an integer or as a string with a unit suffix.
+pub fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    struct SizeVisitor;
+
+    impl Visitor<'_> for SizeVisitor {
+        type Value = u32;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a size such as 4096 or \"4KiB\"")
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<u32, E> {
+            u32::try_from(value).map_err(|_| E::custom(format!("{} is not a valid size", value)))
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<u32, E> {
+            u32::try_from(value).map_err(|_| E::custom(format!("{} is not a valid size", value)))
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<u32, E> {
+            parse_size(value).map_err(E::custom)
+        }
+    }
+
+    deserializer.deserialize_any(SizeVisitor)
+}
+
+// Reads the items and trace out of a TOML file, pointing at the offending line if it is malformed.
+pub fn read_toml(path: &Path) -> Result<TraceInfo, SimError> {
+    let data = fs::read_to_string(path)?;
+    let trace: TraceInfo = toml::from_str(&data).map_err(|e| SimError::from_toml(e, &data))?;
+    check_duplicates(&trace.items)?;
+    check_aliases(&trace)?;
+    Ok(trace)
+}
+
+// Reads the cache sizes and policies of a matrix of runs out of a TOML file.
+pub fn read_matrix(path: &Path) -> Result<MatrixInfo, SimError> {
+    let data = fs::read_to_string(path)?;
+    toml::from_str(&data).map_err(|e| SimError::from_toml(e, &data))
+}
+
+// Reads the decisions an optimal offline cache made for every request out of a TOML file.
+pub fn read_opt_decisions(path: &Path) -> Result<OptDecisions, SimError> {
+    let data = fs::read_to_string(path)?;
+    toml::from_str(&data).map_err(|e| SimError::from_toml(e, &data))
+}
+
+// Reads a cost model out of a TOML file mapping item labels to the costs they should have in
+// place of their catalog costs, e.g. `A = 7`.
+pub fn read_cost_override(path: &Path) -> Result<BTreeMap<String, i32>, SimError> {
+    let data = fs::read_to_string(path)?;
+    toml::from_str(&data).map_err(|e| SimError::from_toml(e, &data))
+}
+
+// Gives every item named in a cost model its cost from the model. Items the model does not name
+// keep their catalog cost. Returns the labels in the model that are not in the catalog.
+pub fn apply_cost_override(items: &mut [Item], costs: &BTreeMap<String, i32>) -> Vec<String> {
+    for item in items.iter_mut() {
+        if let Some(cost) = costs.get(item.get_label()) {
+            item.set_cost(*cost);
+        }
+    }
+    costs
+        .keys()
+        .filter(|label| !items.iter().any(|n| n.get_label() == *label))
+        .cloned()
+        .collect()
+}
+
+// Makes sure no two items in the catalog share a label. Requests are matched to items by label, so
+// a duplicate would make it ambiguous which item's cost and size a request gets.
+fn check_duplicates(items: &[Item]) -> Result<(), SimError> {
+    let mut labels = BTreeSet::new();
+    for item in items.iter() {
+        if !labels.insert(item.get_label()) {
+            return Err(SimError::DuplicateItem(item.get_label().clone()));
+        }
+    }
+    Ok(())
+}
+
+// Makes sure every alias refers to an item in the catalog and is not an item's label itself.
+fn check_aliases(trace: &TraceInfo) -> Result<(), SimError> {
+    let labels: BTreeSet<&String> = trace.items.iter().map(|n| n.get_label()).collect();
+    for (alias, label) in trace.aliases.iter() {
+        if labels.contains(alias) {
+            return Err(SimError::AliasShadowsItem(alias.clone()));
+        }
+        if !labels.contains(label) {
+            return Err(SimError::UnknownAlias {
+                alias: alias.clone(),
+                label: label.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Reads the items and trace out of a SQLite database.
+pub fn read_sqlite(path: &Path) -> Result<TraceInfo, SimError> {
+    let conn = Connection::open(path)?;
+    read_sqlite_connection(&conn)
+}
+
+// Reads the items and trace out of an open SQLite connection. Requests are streamed row by row out
+// of a prepared statement rather than fetched all at once.
+pub fn read_sqlite_connection(conn: &Connection) -> Result<TraceInfo, SimError> {
+    let mut items = Vec::new();
+    let mut stmt = conn.prepare("SELECT label, cost, size FROM items")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        items.push(Item::new(row.get(0)?, row.get(1)?, row.get(2)?));
+    }
+    let mut trace = Vec::new();
+    let mut stmt = conn.prepare("SELECT label FROM requests ORDER BY seq")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        trace.push(TraceEntry::Label(row.get(0)?));
+    }
+    check_duplicates(&items)?;
+    Ok(TraceInfo {
+        items,
+        trace,
+        aliases: BTreeMap::new(),
+    })
+}
+
+// Reads a web access log, building the catalog from the distinct objects it requests. Blank lines
+// and lines starting with `#` are skipped. Any further columns after the size are ignored. The
+// timestamps are kept as each request's arrival time in seconds.
+pub fn read_weblog(path: &Path, size_rule: WeblogSize) -> Result<TraceInfo, SimError> {
+    let data = fs::read_to_string(path)?;
+    // Items are kept in the order the log first requests them, with an index to find each by label
+    let mut sizes: Vec<(String, u32)> = Vec::new();
+    let mut positions: BTreeMap<String, usize> = BTreeMap::new();
+    let mut trace = Vec::new();
+    for (index, line) in data.lines().enumerate() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let parse_error = |field: usize, message: &str| {
+            // Pointing at the start of the offending field, or the end of the line if it is missing
+            let column = fields
+                .get(field)
+                .map(|n| n.as_ptr() as usize - line.as_ptr() as usize)
+                .unwrap_or(line.len())
+                + 1;
+            SimError::ParseError {
+                line: index + 1,
+                column,
+                message: message.to_string(),
+            }
+        };
+        if fields.len() < 3 {
+            return Err(parse_error(
+                fields.len(),
+                "expected a timestamp, object id and size",
+            ));
+        }
+        let timestamp = fields[0]
+            .parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .ok_or_else(|| parse_error(0, "expected a numeric timestamp"))?;
+        let label = fields[1].to_string();
+        let size = parse_size(fields[2]).map_err(|e| parse_error(2, &e))?;
+        match positions.get(&label) {
+            Some(&position) => {
+                if let WeblogSize::Max = size_rule {
+                    let seen = &mut sizes[position].1;
+                    *seen = (*seen).max(size);
+                }
+            }
+            None => {
+                positions.insert(label.clone(), sizes.len());
+                sizes.push((label.clone(), size));
+            }
+        }
+        trace.push(TraceEntry::Override {
+            label,
+            cost: None,
+            write: false,
+            timestamp: Some(timestamp),
+        });
+    }
+    let items = sizes
+        .into_iter()
+        .map(|(label, size)| Item::new(label, WEBLOG_COST, size))
+        .collect();
+    Ok(TraceInfo {
+        items,
+        trace,
+        aliases: BTreeMap::new(),
+    })
+}
+
+// Writes a resolved trace out as a web log with one `timestamp object-id size` line per request,
+// the line format read by read_weblog and consumed by other simulators such as webcachesim. The
+// request's index stands in for its timestamp if it does not have one. Costs are not part of the
+// format and are dropped, so reading the log back in gives the same requests with every cost set
+// to WEBLOG_COST.
+pub fn write_weblog(path: &Path, trace: &VecDeque<Request>) -> Result<(), SimError> {
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    for (index, request) in trace.iter().enumerate() {
+        if request.get_label().chars().any(char::is_whitespace) {
+            return Err(SimError::IoError(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "label {} cannot be exported because it contains whitespace",
+                    request.get_label()
+                ),
+            )));
+        }
+        match request.get_timestamp() {
+            Some(timestamp) => write!(writer, "{}", timestamp)?,
+            None => write!(writer, "{}", index)?,
+        }
+        writeln!(writer, " {} {}", request.get_label(), request.get_size())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::landlord::{HitPolicy, Landlord, TiebreakingPolicy};
+    use crate::logger::Logger;
+    use std::sync::Arc;
+
+    // Writes data to a file of its own in the temporary directory.
+    fn temp_file(name: &str, data: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("csim-{}-{}", name, std::process::id()));
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    // Gets the line and column a web log failed to parse at.
+    fn parse_error_at(data: &str) -> (usize, usize) {
+        let path = temp_file("error", data);
+        let result = read_weblog(&path, WeblogSize::First);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(SimError::ParseError { line, column, .. }) => (line, column),
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sqlite_database_is_read_in_sequence_order() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE items (label TEXT, cost INTEGER, size INTEGER);
+             CREATE TABLE requests (seq INTEGER, label TEXT);
+             INSERT INTO items VALUES ('a', 3, 1), ('b', -1, 2);
+             INSERT INTO requests VALUES (2, 'b'), (1, 'a'), (3, 'a');",
+        )
+        .unwrap();
+        let info = read_sqlite_connection(&conn).unwrap();
+        let items: Vec<(&str, f32, u32)> = info
+            .items
+            .iter()
+            .map(|n| (n.get_label().as_str(), n.get_cost().0, n.get_size()))
+            .collect();
+        assert_eq!(items, vec![("a", 3.0, 1), ("b", -1.0, 2)]);
+        let labels: Vec<&String> = info.trace.iter().map(|n| n.get_label()).collect();
+        assert_eq!(labels, vec!["a", "b", "a"]);
+
+        conn.execute("INSERT INTO items VALUES ('a', 1, 1)", [])
+            .unwrap();
+        assert!(matches!(
+            read_sqlite_connection(&conn),
+            Err(SimError::DuplicateItem(label)) if label == "a"
+        ));
+    }
+
+    #[test]
+    fn sizes_parse_with_and_without_units() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("2KB"), Ok(2000));
+        assert_eq!(parse_size("2KiB"), Ok(2048));
+        assert_eq!(parse_size("1.5 MiB"), Ok(1572864));
+        assert_eq!(parse_size("0.5B"), Ok(0));
+        assert_eq!(parse_size(" 3GB "), Ok(3000000000));
+        for invalid in ["", "MB", "1.5", "-1", "4TB", "1.2.3KB", "5GB"] {
+            assert!(parse_size(invalid).is_err(), "{} should not parse", invalid);
+        }
+    }
+
+    #[test]
+    fn weblog_builds_the_catalog_from_its_requests() {
+        let path = temp_file(
+            "catalog",
+            "# ts id size\n0 a 1\n1.5 b 2KB GET 200\n\n3 a 4\n",
+        );
+        let first = read_weblog(&path, WeblogSize::First).unwrap();
+        let max = read_weblog(&path, WeblogSize::Max).unwrap();
+        fs::remove_file(&path).unwrap();
+        let sizes = |info: &TraceInfo| -> Vec<(String, u32)> {
+            info.items
+                .iter()
+                .map(|n| (n.get_label().clone(), n.get_size()))
+                .collect()
+        };
+        assert_eq!(sizes(&first), vec![("a".into(), 1), ("b".into(), 2000)]);
+        assert_eq!(sizes(&max), vec![("a".into(), 4), ("b".into(), 2000)]);
+        assert!(first
+            .items
+            .iter()
+            .all(|n| n.get_cost().0 == WEBLOG_COST as f32));
+        let requests: Vec<(&str, Option<f64>)> = first
+            .trace
+            .iter()
+            .map(|n| match n {
+                TraceEntry::Override {
+                    label, timestamp, ..
+                } => (label.as_str(), *timestamp),
+                TraceEntry::Label(label) => (label.as_str(), None),
+            })
+            .collect();
+        assert_eq!(
+            requests,
+            vec![("a", Some(0.0)), ("b", Some(1.5)), ("a", Some(3.0))]
+        );
+    }
+
+    #[test]
+    fn weblog_errors_point_at_the_offending_column() {
+        // A missing size points just past the end of the line
+        assert_eq!(parse_error_at("0 a 1\n1 b\n"), (2, 4));
+        assert_eq!(parse_error_at("# header\nnow a 1\n"), (2, 1));
+        assert_eq!(parse_error_at("0  a  big\n"), (1, 7));
+        assert_eq!(parse_error_at("0 a 1\n\n2 b 1XB\n"), (3, 5));
+    }
+
+    #[test]
+    fn weblog_round_trips_through_the_exporter() {
+        let path = temp_file("read", "0.5 a 4KiB\n# comment\n1 b 2\n2 a 8 extra\n");
+        let info = read_weblog(&path, WeblogSize::Max).unwrap();
+        let sizes: Vec<(&str, u32)> = info
+            .items
+            .iter()
+            .map(|n| (n.get_label().as_str(), n.get_size()))
+            .collect();
+        assert_eq!(sizes, vec![("a", 4096), ("b", 2)]);
+        let first = read_weblog(&path, WeblogSize::First).unwrap();
+        assert_eq!(first.items[0].get_size(), 4096);
+
+        // The index stands in for a missing timestamp
+        let trace: VecDeque<Request> = [(0, Some(0.5)), (1, None), (0, Some(2.0))]
+            .into_iter()
+            .map(|(index, timestamp)| {
+                Request::new(&info.items[index], None).with_timestamp(timestamp)
+            })
+            .collect();
+        write_weblog(&path, &trace).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "0.5 a 4096\n1 b 2\n2 a 4096\n"
+        );
+        let read = read_weblog(&path, WeblogSize::First).unwrap();
+        fs::remove_file(&path).unwrap();
+        let labels: Vec<&String> = read.trace.iter().map(|n| n.get_label()).collect();
+        assert_eq!(labels, vec!["a", "b", "a"]);
+        assert!(read
+            .items
+            .iter()
+            .all(|n| n.get_cost().0 == WEBLOG_COST as f32));
+    }
+
+    #[test]
+    fn item_sizes_accept_the_same_units_as_the_cache_size() {
+        let data = "items = [
+            { label = 'a', cost = 1, size = '2KiB' },
+            { label = 'b', cost = 1, size = '500MB' },
+            { label = 'c', cost = 1, size = 3 },
+        ]
+        trace = ['a', 'b', 'c']";
+        let info: TraceInfo = toml::from_str(data).unwrap();
+        let sizes: Vec<u32> = info.items.iter().map(|n| n.get_size()).collect();
+        assert_eq!(sizes, vec![2048, 500_000_000, 3]);
+        assert_eq!(parse_size("1GiB"), Ok(1 << 30));
+        assert_eq!(parse_size("7MiB"), Ok(7 << 20));
+
+        assert!(parse_size("2XB")
+            .unwrap_err()
+            .contains("unknown size unit XB"));
+        let error = toml::from_str::<TraceInfo>(&data.replace("2KiB", "2XB")).unwrap_err();
+        assert!(error.to_string().contains("unknown size unit XB"));
+    }
+
+    #[test]
+    fn duplicated_labels_are_rejected_by_name() {
+        let data = "items = [
+            { label = 'a', cost = 1, size = 1 },
+            { label = 'b', cost = 2, size = 1 },
+            { label = 'a', cost = 3, size = 2 },
+        ]
+        trace = ['a', 'b', 'a']";
+        let path = temp_file("duplicate", data);
+        let duplicated = read_toml(&path);
+        fs::write(
+            &path,
+            data.replacen("label = 'a', cost = 3", "label = 'c', cost = 3", 1),
+        )
+        .unwrap();
+        let distinct = read_toml(&path);
+        fs::remove_file(&path).unwrap();
+        let error = duplicated.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Item a is declared more than once"));
+        assert!(matches!(error, SimError::DuplicateItem(label) if label == "a"));
+        // Once the second a is renamed, the catalog reads as it was written
+        assert_eq!(distinct.unwrap().items.len(), 3);
+    }
+
+    #[test]
+    fn overridden_costs_drive_charging_and_credit() {
+        let path = temp_file("costs", "a = 7\nz = 3\n");
+        let costs = read_cost_override(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut items = vec![
+            Item::new("a".to_string(), 1, 1),
+            Item::new("b".to_string(), 2, 1),
+        ];
+        // z is not in the catalog, and b keeps its catalog cost
+        assert_eq!(apply_cost_override(&mut items, &costs), vec!["z"]);
+        let costs: Vec<f32> = items.iter().map(|n| n.get_cost().0).collect();
+        assert_eq!(costs, vec![7.0, 2.0]);
+
+        let trace: VecDeque<Request> = items.iter().map(|n| Request::new(n, None)).collect();
+        let cache = Landlord::new(2, TiebreakingPolicy::Lru, Arc::new(HitPolicy::Lru)).with_seed(0);
+        let result = Landlord::run(trace.clone(), 0, None, cache, Logger::new(&trace), |_| {});
+        assert_eq!(result.logger.get_full_cost(0), 7);
+        assert_eq!(result.logger.get_full_cost(1), 2);
+        let state = result.full.get_cache_state();
+        assert_eq!((state["a"].0, state["b"].0), (7.0, 2.0));
+    }
+}
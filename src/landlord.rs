@@ -1,24 +1,63 @@
+use crate::policy::{HitAction, HitContext, ReplacementPolicy};
 use crate::Logger;
 use ordered_float::OrderedFloat;
 // Rand is required for the rand hit/tiebreaking policy
 use rand::prelude::*;
+// Clap is required so that modes can be selected directly from the command line.
+use clap::ValueEnum;
 // Serde is required for serializing cost/pressure information and deserializing trace information.
 use serde::Deserialize;
 // Standard collections
-use std::collections::{BTreeMap, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
 
 // Constant to accomodate for floating point rounding errors in minimum credit estimation. If for
 // whatever reason you wanted more exact credit measurements than this, you could use f64 instead
 // of f32 and decrease EPSILON. However, for the sake of performance, I use f32.
 const EPSILON: f32 = 5e-7;
 
+// The number of labels a second-hit admission policy remembers having turned away. Once full, the
+// oldest label is forgotten and its next request is treated as a first reference again.
+const ADMISSION_HISTORY: usize = 1024;
+
+// Mixed into the seed of a cache to derive the seed of its tiebreaking policy's random stream.
+const TIEBREAK_STREAM: u64 = 0x9e3779b97f4a7c15;
+
+// Mixed into the seed of a cache to derive the seed of its probabilistic admission's random stream.
+const ADMISSION_STREAM: u64 = 0xbf58476d1ce4e5b9;
+
+// The number of rows in the frequency sketch of a probabilistic admission policy and the number of
+// counters in each row. Once the sketch has recorded SKETCH_SAMPLE_FACTOR times as many requests as
+// a row has counters, every counter is halved, so that the sketch tracks recent popularity.
+const SKETCH_DEPTH: usize = 4;
+const SKETCH_WIDTH: usize = 1024;
+const SKETCH_SAMPLE_FACTOR: u32 = 10;
+
+// How far the global offset of a credit queue may grow before it is subtracted back out of every
+// credit. Credits in a queue are stored with the offset added, so the larger it gets the less
+// precision is left in an f32 for the credit itself. At this offset that is still about 2e-4.
+const QUEUE_OFFSET_LIMIT: f32 = 4096.0;
+
+// How many entries a credit queue may hold per resident item before its stale entries are swept
+// out. Every change to an item's credit pushes a new entry and leaves the old one behind.
+const QUEUE_SLACK: usize = 4;
+
+// The class that items are placed in if they do not specify one in the TOML file.
+pub const DEFAULT_CLASS: &str = "default";
+
 // This is an enum to hold whether or not a request was a hit or a fault. This is used for both the
 // full trace cache and the suffix. The data in the fault field is the pressure increase on that
-// fault.
+// fault. A partial hit happens when only some of a chunked item's chunks were resident; it holds
+// the fraction of the item that was missing followed by the pressure increase from fetching it.
 #[derive(Debug)]
 pub enum RequestResult {
     Hit,
     Fault(f32),
+    PartialHit(f32, f32),
+    VictimHit(f32, f32),
 }
 
 // True means hit, false means fault.
@@ -29,75 +68,584 @@ pub enum RequestFullOrSuffix {
 
 // These are the keys for our cache map. The label is the name of the item and it is also what we
 // are comparing against while iterating through our trace. Cost and size are pretty self
-// explanatory.
+// explanatory, except that a cost may be negative to model a benefit such as a prefetch or
+// sponsored content. A negative cost is charged as is on every fault, lowering the total cost, but
+// the item's credit never goes below zero, so it is always among the first candidates for eviction.
 #[derive(Debug, PartialOrd, PartialEq, Eq, Ord, Deserialize)]
 pub struct Item {
     label: String,
-    cost: u32,
+    cost: i32,
+    // Sizes may be written with a unit suffix, e.g. "4KiB", in the TOML file.
+    #[serde(deserialize_with = "crate::input::deserialize_size")]
     size: u32,
+    // The class or category the item belongs to, e.g. images or API responses. This is optional
+    // in the TOML file and items without one fall into the default class.
+    class: Option<String>,
+    // The number of equal chunks the item is cached in. Chunks are evicted individually so a
+    // request may find only part of the item resident. Items without this field are one chunk.
+    chunks: Option<u32>,
+    // How many requests after being brought into cache the item is exempt from eviction, in place
+    // of the cache's own minimum residency. This is optional in the TOML file.
+    min_residency: Option<u32>,
+}
+
+// A single request in a trace. Requests usually pay their item's catalog cost, but a trace may
+// override the cost of an individual request, e.g. when origin load varies over time. The override
+// is used both for the miss charge and for refreshing credit. A request is a read unless the trace
+// marks it as a write, in which case the cache's write policy decides what it pays. Requests
+// dereference to their item so they can be used anywhere an item is expected.
+#[derive(Debug, Clone, Copy)]
+pub struct Request<'a> {
+    item: &'a Item,
+    cost: Option<i32>,
+    write: bool,
+    timestamp: Option<f64>,
 }
 
 // Wrapper for the cache. The contents are stored as a BTreeMap where each key-value pair is an
 // item and its associated normalized credit. These credits are stored as floats to allow for
 // fractional credits. In particular, we wrap our floats in the OrderedFloat struct so that we can
 // more easily find our minimum-credit element in cache when we must evict something. This is
-// because the Ord trait is not implemented for f32 in Rust in compliance with IEEE 754. The policy
-// is our hit policy which we check in a match statement later when we have a hit. The size of our
-// cache is the number of cache lines available. Meanwhile, occupied is the number of cache lines
-// that currently have items in them.
+// because the Ord trait is not implemented for f32 in Rust in compliance with IEEE 754. If the
+// cache evicts from a credit queue, its contents hold each item's credit in the queue's terms
+// instead of the credit itself.
 #[derive(Debug)]
 struct Cache<'a> {
     contents: BTreeMap<&'a Item, OrderedFloat<f32>>,
-    policy: HitPolicy,
+    // The number of chunks of each resident item that are currently in cache.
+    chunks: BTreeMap<&'a Item, u32>,
+    // Our hit policy, which decides what happens to an item's credit when we hit on it.
+    policy: Arc<dyn ReplacementPolicy>,
+    // How credit is taken from each item on eviction.
+    pressure_mode: PressureMode,
+    // The number of cache lines available and how many of them currently have items in them.
+    // Margin is how far past its size the cache may temporarily overflow before it has to evict,
+    // and the overflow penalty is the cost charged per request for each line it is over its size.
     size: u32,
+    margin: u32,
+    overflow_penalty: f32,
     occupied: u32,
+    // The number of requests the cache has serviced, and how many times and when each item was
+    // last requested, whether or not it is still resident. Requested cost totals what every
+    // request for each item cost, at the cost that request was charged.
+    clock: u64,
+    accesses: BTreeMap<&'a Item, u32>,
+    last_access: BTreeMap<&'a Item, u64>,
+    requested_cost: BTreeMap<&'a Item, f32>,
+    // The reason for every eviction since the logger last collected them.
+    evictions: Vec<(&'a Item, EvictionReason)>,
+    // Each item's LRFU score as of its last access. This is only kept up to date under LRFU.
+    crf: BTreeMap<&'a Item, f32>,
+    // The most recently evicted items, newest first, up to a total size of the ghost capacity. It
+    // only remembers which items were evicted, and ghost hits counts the faults on items it still
+    // remembered, which would have been hits in a cache that much larger.
+    ghost: VecDeque<&'a Item>,
+    ghost_capacity: Option<u32>,
+    ghost_hits: u32,
+    // The most recently evicted items, held the same way as the ghost up to the victim capacity. A
+    // fault on an item it holds promotes the item back into cache for victim_cost times its cost,
+    // and victim hits counts how many times that happened.
+    victim: VecDeque<&'a Item>,
+    victim_capacity: Option<u32>,
+    victim_cost: f32,
+    victim_hits: u32,
+    // Whether a faulting item is brought into cache at all. Seen holds the most recent items it
+    // turned away, newest first, sketch estimates how often each item has been requested, and
+    // deferred counts how many times it turned an item away.
+    admission: AdmissionPolicy,
+    eviction: EvictionPolicy,
+    seen: VecDeque<&'a Item>,
+    sketch: Option<FrequencySketch>,
+    deferred: u32,
+    // Items larger than this are never brought into cache, and bypassed counts the requests for
+    // them.
+    max_cacheable_size: Option<u32>,
+    bypassed: u32,
+    // With a decay half-life, the credit an item is given halves every that many requests after
+    // its first access, so content that has been around longer is cheaper to lose.
+    decay_half_life: Option<f32>,
+    first_access: BTreeMap<&'a Item, u64>,
+    write_policy: WritePolicy,
+    cost_rounding: CostRounding,
+    // The items a write-back cache has written to but not yet written back to the origin, and the
+    // cost of writing back the dirty items evicted since it was last collected.
+    dirty: BTreeSet<&'a Item>,
+    writebacks: i32,
+    // With a cost clip, no single request or writeback is charged more than clip_cost and clipped
+    // totals the cost that was cut off. If clip_credit is set, the credit items are given is
+    // capped the same way.
+    clip_cost: Option<i32>,
+    clip_credit: bool,
+    clipped: i64,
+    // With a minimum residency, an item cannot be evicted for that many requests after the time
+    // it was admitted, even once it runs out of credit. Items may set their own. Exemptions counts
+    // how many times an item was passed over for eviction because of it.
+    min_residency: Option<u32>,
+    admitted: BTreeMap<&'a Item, u64>,
+    exemptions: u32,
+    queue: Option<CreditQueue<'a>>,
+}
+
+// A count-min sketch estimating how many times each item has been requested in a fixed amount of
+// space, as TinyLFU does. Each request increments one counter in every row, picked by hashing the
+// item's label along with the row, and an item's estimate is the least of its counters, so
+// collisions can only make it overcount. Samples counts the requests recorded since the counters
+// were last halved.
+#[derive(Debug)]
+struct FrequencySketch {
+    rows: Vec<Vec<u32>>,
+    samples: u32,
+}
+
+impl FrequencySketch {
+    fn new() -> Self {
+        Self {
+            rows: vec![vec![0; SKETCH_WIDTH]; SKETCH_DEPTH],
+            samples: 0,
+        }
+    }
+    // Picks the counter an item maps to in a row. The default hasher is keyed the same way on
+    // every run, so the sketch is deterministic.
+    fn index(row: usize, item: &Item) -> usize {
+        let mut hasher = DefaultHasher::new();
+        row.hash(&mut hasher);
+        item.get_label().hash(&mut hasher);
+        (hasher.finish() % SKETCH_WIDTH as u64) as usize
+    }
+    // Records a request for an item, halving every counter once enough requests have been
+    // recorded since they were last halved.
+    fn record(&mut self, item: &Item) {
+        for (row, counters) in self.rows.iter_mut().enumerate() {
+            let counter = &mut counters[Self::index(row, item)];
+            *counter = counter.saturating_add(1);
+        }
+        self.samples += 1;
+        if self.samples >= SKETCH_WIDTH as u32 * SKETCH_SAMPLE_FACTOR {
+            for counter in self.rows.iter_mut().flatten() {
+                *counter /= 2;
+            }
+            self.samples = 0;
+        }
+    }
+    // Estimates how many times an item has been requested.
+    fn estimate(&self, item: &Item) -> u32 {
+        self.rows
+            .iter()
+            .enumerate()
+            .map(|(row, counters)| counters[Self::index(row, item)])
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+// A min-heap of resident items keyed on their normalized credit, which lets a cache find the item
+// with the least credit without scanning every resident item. Rather than decrementing every
+// item's credit on eviction, the queue raises a global offset by the same amount, the same way
+// Greedy-Dual raises L. Each item's key is its normalized credit plus the offset at the time the
+// credit was set, so keys never change once pushed. Entries are never removed when an item's credit
+// changes or it leaves cache; stale entries are recognized by no longer matching the cache's
+// contents and skipped. This only works when every item loses credit at a fixed rate, so it cannot
+// be used with the recency or frequency pressure modes. Like L, the offset only ever grows, so once
+// it passes offset_limit it is subtracted back out of every key, which leaves their order alone.
+#[derive(Debug)]
+struct CreditQueue<'a> {
+    heap: BinaryHeap<Reverse<(OrderedFloat<f32>, &'a Item)>>,
+    offset: f32,
+    offset_limit: f32,
 }
 
 // Wrapper for the tiebreaking order. This maintains a VecDeque which stores the order that, if
 // there were to be a credit tie, which order we should evict our cache items. Items closer to the
 // front will be evicted sooner, items at the back will be evicted later. Our policy is just our
-// tiebreaking policy which we match against when we must evict something. Size and occupied are
-// identical to what we had in the Cache struct.
+// tiebreaking policy which we match against when we must evict something. Occupied is identical to
+// what we had in the Cache struct. Insertions maps each resident item to the sequence number it was
+// given when it was brought into cache, which FIFO tiebreaking uses so that it never depends on the
+// order items were accessed in.
 #[derive(Debug)]
 struct Tiebreaker<'a> {
     order: VecDeque<&'a Item>,
+    insertions: BTreeMap<&'a Item, u64>,
+    next_insertion: u64,
     policy: TiebreakingPolicy,
-    size: u32,
     occupied: u32,
 }
 
-// Hit policies. The first four have a default behavior implemented. Any after that will then defer
-// the hit policy to whatever function you decide to assign to the enum. This can be anything and
-// you don't need to keep the name 'custom'.
+// The built-in hit policies, each of which is a ReplacementPolicy. Other policies can be added by
+// implementing that trait and registering them instead of extending this enum.
+// Static differs from FIFO in that a hit is invisible to the cache altogether: FIFO leaves the
+// item's credit alone but still lets the tiebreaking policy see the access, whereas Static leaves
+// the tiebreaking order untouched too, so credit only ever decreases through pressure.
+// LRFU refreshes credit in proportion to the item's combined recency and frequency (CRF) score
+// relative to the highest score in cache. The decay λ in [0, 1] moves it between LFU at 0, where
+// the score is just the access count, and LRU at 1, where the latest access dominates the score.
 #[derive(Debug, Clone, Copy)]
 pub enum HitPolicy {
     Lru,
     Fifo,
     Rand,
     Half,
+    Static,
+    Lrfu(f32),
 }
 
-// Tiebreaking policies. The first four have a default behavior implemented. Any after that will
+// The decay LRFU uses when none is given on the command line.
+pub const DEFAULT_LRFU_LAMBDA: f32 = 0.5;
+
+// The fraction of an item's cost charged for promoting it back out of the victim cache when none is
+// given on the command line.
+pub const DEFAULT_VICTIM_COST: f32 = 0.1;
+
+// Tiebreaking policies. The first five have a default behavior implemented. Any after that will
 // then defer the hit policy to whatever function you decide to assign to the enum. This can be
 // anything and you don't need to keep the name 'custom'.
+// LFU evicts whichever tied item has been requested the fewest times, falling back on recency
+// among items requested equally often.
+// ExpectedValue keeps whichever tied items would save the most if they stayed resident. How often
+// an item has been requested so far stands in for how often it will be requested again, so an
+// item's expected savings are the total cost of its requests so far, each at the cost that request
+// was charged, and the item with the least is evicted. Like LFU, it falls back on recency among
+// items with equal expected savings.
 #[derive(Debug, Clone, Copy)]
 pub enum TiebreakingPolicy {
     Lru,
     Fifo,
     Rand,
+    Lfu,
+    ExpectedValue,
+}
+
+// How credit is decremented when the cache is under pressure. Uniform is classic Landlord where
+// every item loses credit in proportion to its size. Recency additionally scales each item's
+// decrement by how many requests ago it was last accessed, and frequency divides it by how many
+// times the item has been accessed, so stale or rarely used items drain faster.
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum PressureMode {
+    #[default]
+    Uniform,
+    Recency,
+    Frequency,
+}
+
+// Why an item was evicted. Capacity evictions make room in a cache that is at its size, soft limit
+// evictions make room in a cache that has used up its overflow margin and chunk evictions only drop
+// some of a chunked item's chunks, leaving the rest resident.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EvictionReason {
+    Capacity,
+    SoftLimit,
+    Chunk,
+}
+
+impl EvictionReason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Capacity => "capacity",
+            Self::SoftLimit => "soft_limit",
+            Self::Chunk => "chunk",
+        }
+    }
+}
+
+// What a cache did on a single request: the pressure it applied, the items it evicted and why, and
+// how many items were resident and how much space they took up afterward.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheDecision<'e> {
+    pub pressure: f32,
+    pub evicted: &'e [(&'e Item, EvictionReason)],
+    pub resident: usize,
+    pub occupied: u32,
+}
+
+// How both caches fared on a single request, handed to the observer of a run as it happens. The
+// suffix hit and decision are None for requests before the suffix starts, and the hit ratios are
+// running ratios over every request each cache has serviced so far.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestEvent<'e> {
+    pub index: usize,
+    pub label: &'e str,
+    pub full_hit: bool,
+    pub full_cost: i32,
+    pub full_hit_ratio: f32,
+    pub full: CacheDecision<'e>,
+    pub suff_hit: Option<bool>,
+    pub suff_cost: i32,
+    pub suff_hit_ratio: f32,
+    pub suff: Option<CacheDecision<'e>>,
+}
+
+// Whether an item that faults is brought into cache. Always admits every item, like classic
+// Landlord. SecondHit turns an item away on the first reference it has not seen recently and only
+// admits it on the next one, so that items requested once never pollute the cache. Probabilistic
+// estimates how often the item has been requested with a frequency sketch, as TinyLFU does, and
+// admits an item estimated at n requests, counting this one, with probability 1 - 1/n. Items
+// requested once are never admitted, and the more popular an item, the likelier it gets in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum AdmissionPolicy {
+    #[default]
+    Always,
+    SecondHit,
+    Probabilistic,
+}
+
+// The algorithm a cache evicts with. Landlord takes credit from every item in proportion to its
+// size, while Greedy-Dual, the algorithm Landlord generalizes, takes the same amount of credit from
+// every item regardless of size and so evicts the item with the least credit outright. Taking that
+// amount from every item is the same as Greedy-Dual's usual global inflation value L rising by it,
+// so the pressure of a Greedy-Dual cache is how much L rises. The two coincide when every item has
+// unit size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum EvictionPolicy {
+    #[default]
+    Landlord,
+    GreedyDual,
+}
+
+// What a write request costs. Through writes every write to the origin as it happens, so every
+// write pays its cost whether or not it hits. Back only writes to the cache and marks the item
+// dirty, paying the item's cost once the dirty item is evicted. A write that a write-back cache
+// does not hold onto, because it bypasses the cache or is not admitted, goes straight to the origin
+// and pays its cost. Items still dirty at the end of the trace are never charged. Reads cost the
+// same under both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum WritePolicy {
+    #[default]
+    Through,
+    Back,
+}
+
+// How a fractional cost, such as the share of an item's cost paid for its missing chunks, is turned
+// into the whole number of cost units that is charged. Truncate rounds toward zero, which is what
+// the cost accounting always did, while the others round down, up or to the nearest whole number,
+// with halves rounded away from zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum CostRounding {
+    #[default]
+    Truncate,
+    Floor,
+    Ceil,
+    Nearest,
+}
+
+impl CostRounding {
+    pub fn round(self, cost: f32) -> i32 {
+        match self {
+            Self::Truncate => cost.trunc() as i32,
+            Self::Floor => cost.floor() as i32,
+            Self::Ceil => cost.ceil() as i32,
+            Self::Nearest => cost.round() as i32,
+        }
+    }
+}
+
+// The result of running our two Landlord instances over a trace. This bundles the filled logger,
+// the final states of both caches and the hit/fault counts of each so that callers do not have to
+// recover them from the logger themselves. There is no suffix cache if the run only simulated the
+// full cache.
+#[derive(Debug)]
+pub struct RunResult<'a> {
+    pub logger: Logger,
+    pub full: Landlord<'a>,
+    pub suff: Option<Landlord<'a>>,
+    pub full_hits: u32,
+    pub full_faults: u32,
+    pub suff_hits: u32,
+    pub suff_faults: u32,
+}
+
+impl RunResult<'_> {
+    // Gets the fraction of requests that hit in the full trace cache.
+    pub fn full_hit_ratio(&self) -> f32 {
+        let total = self.full_hits + self.full_faults;
+        if total == 0 {
+            return 0.0;
+        }
+        self.full_hits as f32 / total as f32
+    }
+    // Gets the fraction of suffix requests that hit in the suffix cache.
+    pub fn suff_hit_ratio(&self) -> f32 {
+        let total = self.suff_hits + self.suff_faults;
+        if total == 0 {
+            return 0.0;
+        }
+        self.suff_hits as f32 / total as f32
+    }
 }
 
 // The primary struct. We have a cache and a tiebreaker which implements our tiebreaking policy.
+// The random hit and tiebreaking policies and probabilistic admission each draw from their own rng,
+// so that the choices one of them makes never depend on whether the others are random too.
 #[derive(Debug)]
 pub struct Landlord<'a> {
     cache: Cache<'a>,
     tiebreaker: Tiebreaker<'a>,
+    hit_rng: StdRng,
+    tiebreak_rng: StdRng,
+    admission_rng: StdRng,
 }
 
 // IMPLEMENTATING STRUCTS
 // -----------------------------------------------------------------------------
 
+impl HitPolicy {
+    // Every hit policy, in the order they should be listed to users.
+    pub fn variants() -> Vec<Self> {
+        vec![
+            Self::Lru,
+            Self::Fifo,
+            Self::Rand,
+            Self::Half,
+            Self::Static,
+            Self::Lrfu(DEFAULT_LRFU_LAMBDA),
+        ]
+    }
+    // The name used to select this policy from the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Lru => "LRU",
+            Self::Fifo => "FIFO",
+            Self::Rand => "RAND",
+            Self::Half => "HALF",
+            Self::Static => "STATIC",
+            Self::Lrfu(_) => "LRFU",
+        }
+    }
+    // Other names that also select this policy from the command line.
+    pub fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            Self::Static => &["NONE"],
+            _ => &[],
+        }
+    }
+    // A short description of how this policy refreshes credit on a hit.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Lru => "refreshes credit to the item's full cost",
+            Self::Fifo => "leaves credit where it is",
+            Self::Rand => "refreshes credit to a random value between its current credit and cost",
+            Self::Half => "refreshes credit halfway between its current credit and cost",
+            Self::Static => {
+                "ignores hits entirely, leaving both credit and tiebreaking order alone"
+            }
+            Self::Lrfu(_) => {
+                "refreshes credit in proportion to the item's combined recency and frequency score"
+            }
+        }
+    }
+    // The command-line options that tune this policy, each with what it sets.
+    pub fn parameters(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::Lrfu(_) => &[(
+                "--lrfu-lambda <LAMBDA>",
+                "the decay between 0 (LFU) and 1 (LRU) of its scores",
+            )],
+            _ => &[],
+        }
+    }
+}
+
+impl TiebreakingPolicy {
+    // Every tiebreaking policy, in the order they should be listed to users.
+    pub fn variants() -> Vec<Self> {
+        vec![
+            Self::Lru,
+            Self::Fifo,
+            Self::Rand,
+            Self::Lfu,
+            Self::ExpectedValue,
+        ]
+    }
+    // The name used to select this policy from the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Lru => "LRU",
+            Self::Fifo => "FIFO",
+            Self::Rand => "RAND",
+            Self::Lfu => "LFU",
+            Self::ExpectedValue => "EV",
+        }
+    }
+    // A short description of which zero-credit item this policy evicts.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Lru => "evicts the least recently requested item",
+            Self::Fifo => "evicts the item that was inserted into cache first",
+            Self::Rand => "evicts items in a randomly shuffled order",
+            Self::Lfu => "evicts the least frequently requested item",
+            Self::ExpectedValue => {
+                "evicts the item whose requests so far cost the least, its expected savings"
+            }
+        }
+    }
+    // Finds the policy with the given name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::variants()
+            .into_iter()
+            .find(|n| n.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl<'a> Request<'a> {
+    pub fn new(item: &'a Item, cost: Option<i32>) -> Self {
+        Self {
+            item,
+            cost,
+            write: false,
+            timestamp: None,
+        }
+    }
+    // Marks whether this request is a write.
+    pub fn with_write(mut self, write: bool) -> Self {
+        self.write = write;
+        self
+    }
+    // Sets when this request arrived, in seconds.
+    pub fn with_timestamp(mut self, timestamp: Option<f64>) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+    pub fn is_write(&self) -> bool {
+        self.write
+    }
+    pub fn get_timestamp(&self) -> Option<f64> {
+        self.timestamp
+    }
+    // Getters.
+    pub fn get_item(&self) -> &'a Item {
+        self.item
+    }
+    // Gets the cost of this request, which is the item's cost unless it was overridden.
+    pub fn get_cost(&self) -> OrderedFloat<f32> {
+        match self.cost {
+            Some(cost) => OrderedFloat(cost as f32),
+            None => self.item.get_cost(),
+        }
+    }
+}
+
+impl Deref for Request<'_> {
+    type Target = Item;
+    fn deref(&self) -> &Item {
+        self.item
+    }
+}
+
 impl Item {
+    // Creates an item with no class that is cached as a single chunk. Items are usually
+    // deserialized from TOML, but other input formats build them directly.
+    pub fn new(label: String, cost: i32, size: u32) -> Self {
+        Self {
+            label,
+            cost,
+            size,
+            class: None,
+            chunks: None,
+            min_residency: None,
+        }
+    }
+    // Replaces the item's catalog cost, such as with one from a separate cost model.
+    pub fn set_cost(&mut self, cost: i32) {
+        self.cost = cost;
+    }
     // Getters.
     pub fn get_label(&self) -> &String {
         &self.label
@@ -109,28 +657,419 @@ impl Item {
     pub fn get_size(&self) -> u32 {
         self.size
     }
+    pub fn get_class(&self) -> &str {
+        self.class.as_deref().unwrap_or(DEFAULT_CLASS)
+    }
+    pub fn get_min_residency(&self) -> Option<u32> {
+        self.min_residency
+    }
+    pub fn get_chunks(&self) -> u32 {
+        self.chunks.unwrap_or(1).max(1)
+    }
+    // Gets the space taken up by the given number of this item's chunks. All of the chunks together
+    // take up exactly the item's size.
+    pub fn chunk_size(&self, chunks: u32) -> u32 {
+        (self.size as u64 * chunks as u64 / self.get_chunks() as u64) as u32
+    }
 }
 
 impl<'a> Landlord<'a> {
     // Creates a new Landlord instance with the specified size, tiebreaking policy and hit policy.
-    pub fn new(size: u32, tiebreak_policy: TiebreakingPolicy, hit_policy: HitPolicy) -> Self {
+    pub fn new(
+        size: u32,
+        tiebreak_policy: TiebreakingPolicy,
+        hit_policy: Arc<dyn ReplacementPolicy>,
+    ) -> Self {
         Self {
             cache: {
                 Cache {
                     contents: BTreeMap::new(),
+                    chunks: BTreeMap::new(),
                     policy: hit_policy,
+                    pressure_mode: PressureMode::default(),
                     size,
+                    margin: 0,
+                    overflow_penalty: 0.0,
                     occupied: 0,
+                    clock: 0,
+                    accesses: BTreeMap::new(),
+                    last_access: BTreeMap::new(),
+                    requested_cost: BTreeMap::new(),
+                    evictions: Vec::new(),
+                    crf: BTreeMap::new(),
+                    ghost: VecDeque::new(),
+                    ghost_capacity: None,
+                    ghost_hits: 0,
+                    victim: VecDeque::new(),
+                    victim_capacity: None,
+                    victim_cost: DEFAULT_VICTIM_COST,
+                    victim_hits: 0,
+                    admission: AdmissionPolicy::default(),
+                    eviction: EvictionPolicy::default(),
+                    seen: VecDeque::new(),
+                    sketch: None,
+                    deferred: 0,
+                    max_cacheable_size: None,
+                    bypassed: 0,
+                    decay_half_life: None,
+                    first_access: BTreeMap::new(),
+                    write_policy: WritePolicy::default(),
+                    cost_rounding: CostRounding::default(),
+                    dirty: BTreeSet::new(),
+                    writebacks: 0,
+                    clip_cost: None,
+                    clip_credit: false,
+                    clipped: 0,
+                    min_residency: None,
+                    admitted: BTreeMap::new(),
+                    exemptions: 0,
+                    queue: None,
                 }
             },
             tiebreaker: {
                 Tiebreaker {
                     order: VecDeque::new(),
+                    insertions: BTreeMap::new(),
+                    next_insertion: 0,
                     policy: tiebreak_policy,
-                    size,
                     occupied: 0,
                 }
             },
+            hit_rng: StdRng::from_os_rng(),
+            tiebreak_rng: StdRng::from_os_rng(),
+            admission_rng: StdRng::from_os_rng(),
+        }
+    }
+
+    // Seeds the random number generators so that the random policies make the same choices on
+    // every run. The hit policy's stream is seeded with the seed itself and the tiebreaking
+    // policy's and admission's with seeds derived from it.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.hit_rng = StdRng::seed_from_u64(seed);
+        self.tiebreak_rng = StdRng::seed_from_u64(seed ^ TIEBREAK_STREAM);
+        self.admission_rng = StdRng::seed_from_u64(seed ^ ADMISSION_STREAM);
+        self
+    }
+
+    // Sets how credit is decremented when this cache evicts.
+    pub fn with_pressure_mode(mut self, pressure_mode: PressureMode) -> Self {
+        self.cache.pressure_mode = pressure_mode;
+        self
+    }
+
+    // Lets the cache overflow its size by up to margin cache lines before evicting, charging the
+    // given penalty per request for every line it is over its size.
+    pub fn with_soft_limit(mut self, margin: u32, overflow_penalty: f32) -> Self {
+        self.cache.margin = margin;
+        self.cache.overflow_penalty = overflow_penalty;
+        self
+    }
+
+    // Sets which algorithm the cache evicts with.
+    pub fn with_eviction_policy(mut self, eviction: EvictionPolicy) -> Self {
+        self.cache.eviction = eviction;
+        self
+    }
+
+    // Sets whether faulting items are admitted into cache.
+    pub fn with_admission(mut self, admission: AdmissionPolicy) -> Self {
+        self.cache.admission = admission;
+        self.cache.sketch =
+            (admission == AdmissionPolicy::Probabilistic).then(FrequencySketch::new);
+        self
+    }
+
+    // Makes the cache refuse items larger than the given size, so every request for them misses.
+    pub fn with_max_cacheable_size(mut self, max_cacheable_size: u32) -> Self {
+        self.cache.max_cacheable_size = Some(max_cacheable_size);
+        self
+    }
+
+    // Makes the cache find the item to evict with a priority queue instead of scanning every
+    // resident item. This must only be used when items lose credit at a fixed rate, i.e. under
+    // uniform pressure or Greedy-Dual.
+    pub fn with_credit_queue(mut self) -> Self {
+        self.cache.queue = Some(CreditQueue {
+            heap: BinaryHeap::new(),
+            offset: 0.0,
+            offset_limit: QUEUE_OFFSET_LIMIT,
+        });
+        self
+    }
+
+    // Sets how the cache pays for write requests.
+    pub fn with_write_policy(mut self, write_policy: WritePolicy) -> Self {
+        self.cache.write_policy = write_policy;
+        self
+    }
+
+    // Sets how fractional costs are rounded to the whole cost that is charged.
+    pub fn with_cost_rounding(mut self, cost_rounding: CostRounding) -> Self {
+        self.cache.cost_rounding = cost_rounding;
+        self
+    }
+
+    // Rounds a cost to the whole number of cost units charged for it.
+    fn round_cost(&self, cost: f32) -> i32 {
+        self.cache.cost_rounding.round(cost)
+    }
+
+    // Works out what a request that was just serviced costs, given what it would cost as a read.
+    // Writes are charged according to the write policy, and a write-back cache also adds the cost
+    // of writing back every dirty item it evicted while servicing the request.
+    fn charge(&mut self, request: &Request<'a>, read_cost: i32) -> i32 {
+        let item = request.get_item();
+        let cost = match (request.is_write(), self.cache.write_policy) {
+            (false, _) => read_cost,
+            (true, WritePolicy::Through) => self.round_cost(request.get_cost().0),
+            (true, WritePolicy::Back) => {
+                if self.cache.contents.contains_key(item) {
+                    self.cache.dirty.insert(item);
+                    0
+                } else {
+                    self.round_cost(request.get_cost().0)
+                }
+            }
+        };
+        self.clip(cost) + std::mem::take(&mut self.cache.writebacks)
+    }
+
+    // Caps the cost charged for any one request or writeback at the given value. If clip_credit is
+    // set, the credit items are given on a request is capped at it too.
+    pub fn with_clip_cost(mut self, clip_cost: i32, clip_credit: bool) -> Self {
+        self.cache.clip_cost = Some(clip_cost);
+        self.cache.clip_credit = clip_credit;
+        self
+    }
+
+    // Clips a cost to the cache's cap, keeping track of how much was cut off.
+    fn clip(&mut self, cost: i32) -> i32 {
+        match self.cache.clip_cost {
+            Some(cap) if cost > cap => {
+                self.cache.clipped += i64::from(cost - cap);
+                cap
+            }
+            _ => cost,
+        }
+    }
+
+    // Gets the total cost the cache did not charge because it was over the clip, or None if the
+    // cache does not clip costs.
+    pub fn clipped_cost(&self) -> Option<i64> {
+        self.cache.clip_cost.map(|_| self.cache.clipped)
+    }
+
+    // Makes the credit items are given on a request decay as they age, halving every half_life
+    // requests after each item is first requested. The cost charged on a miss is unaffected.
+    pub fn with_decay_half_life(mut self, half_life: f32) -> Self {
+        self.cache.decay_half_life = Some(half_life);
+        self
+    }
+
+    // Gets the fraction of its cost an item is currently worth after popularity decay, which is
+    // one if the cache does not decay credit.
+    fn decay(&self, item: &Item) -> f32 {
+        let Some(half_life) = self.cache.decay_half_life else {
+            return 1.0;
+        };
+        let first = self.cache.first_access.get(item).copied().unwrap_or(0);
+        0.5f32.powf((self.cache.clock - first) as f32 / half_life)
+    }
+
+    // Gets the number of requests that bypassed the cache because their item was too large, or
+    // None if the cache accepts items of any size.
+    pub fn bypassed(&self) -> Option<u32> {
+        self.cache.max_cacheable_size.map(|_| self.cache.bypassed)
+    }
+
+    // Checks whether an item is too large for the cache to ever hold.
+    fn is_bypassed(&self, item: &Item) -> bool {
+        self.cache
+            .max_cacheable_size
+            .is_some_and(|max| item.get_size() > max)
+    }
+
+    // Gets the number of faults on which the item was not admitted, or None if the cache admits
+    // every item.
+    pub fn deferred_admissions(&self) -> Option<u32> {
+        match self.cache.admission {
+            AdmissionPolicy::Always => None,
+            AdmissionPolicy::SecondHit | AdmissionPolicy::Probabilistic => {
+                Some(self.cache.deferred)
+            }
+        }
+    }
+
+    // Decides whether a faulting item is brought into cache. Under second-hit admission, an item
+    // is only admitted if it was turned away recently, and is otherwise remembered and turned away.
+    // Under probabilistic admission, it is admitted with a probability that grows with how often
+    // the sketch estimates it has been requested.
+    fn admit(&mut self, item: &'a Item) -> bool {
+        if self.cache.admission == AdmissionPolicy::Always {
+            return true;
+        }
+        if let Some(sketch) = &self.cache.sketch {
+            let frequency = sketch.estimate(item).max(1);
+            let admitted = self
+                .admission_rng
+                .random_bool(1.0 - 1.0 / f64::from(frequency));
+            if !admitted {
+                self.cache.deferred += 1;
+            }
+            return admitted;
+        }
+        if let Some(index) = self.cache.seen.iter().position(|n| *n == item) {
+            self.cache.seen.remove(index);
+            return true;
+        }
+        self.cache.seen.push_front(item);
+        self.cache.seen.truncate(ADMISSION_HISTORY);
+        self.cache.deferred += 1;
+        false
+    }
+
+    // Remembers up to capacity cache lines worth of evicted items so that faults on them can be
+    // counted as ghost hits.
+    pub fn with_ghost(mut self, capacity: u32) -> Self {
+        self.cache.ghost_capacity = Some(capacity);
+        self
+    }
+
+    // Gets the number of faults that would have been hits with the ghost's capacity added to the
+    // cache, or None if the cache has no ghost.
+    pub fn ghost_hits(&self) -> Option<u32> {
+        self.cache.ghost_capacity.map(|_| self.cache.ghost_hits)
+    }
+
+    // Keeps up to capacity cache lines worth of evicted items in a victim cache. A fault on an item
+    // still in the victim cache promotes it back for the given fraction of its cost.
+    pub fn with_victim(mut self, capacity: u32, cost: f32) -> Self {
+        self.cache.victim_capacity = Some(capacity);
+        self.cache.victim_cost = cost;
+        self
+    }
+
+    // Gets the number of faults the victim cache caught, or None if the cache has no victim cache.
+    pub fn victim_hits(&self) -> Option<u32> {
+        self.cache.victim_capacity.map(|_| self.cache.victim_hits)
+    }
+
+    // Adds an evicted item to the front of the ghost and the victim cache, dropping the oldest
+    // evictions from each once it is over capacity.
+    fn remember_eviction(&mut self, item: &'a Item) {
+        fn push<'a>(list: &mut VecDeque<&'a Item>, item: &'a Item, capacity: u32) {
+            list.push_front(item);
+            while list.iter().map(|n| n.get_size()).sum::<u32>() > capacity {
+                list.pop_back();
+            }
+        }
+        if let Some(capacity) = self.cache.ghost_capacity {
+            push(&mut self.cache.ghost, item, capacity);
+        }
+        if let Some(capacity) = self.cache.victim_capacity {
+            push(&mut self.cache.victim, item, capacity);
+        }
+    }
+
+    // Exempts items from eviction for the given number of requests after they are admitted. Items
+    // with a minimum residency of their own use it instead.
+    pub fn with_min_residency(mut self, min_residency: u32) -> Self {
+        self.cache.min_residency = Some(min_residency);
+        self
+    }
+
+    // Gets how many times an item was passed over for eviction because it had not been resident
+    // for its minimum residency yet, or None if the cache has no minimum residency.
+    pub fn residency_exemptions(&self) -> Option<u32> {
+        self.cache.min_residency.map(|_| self.cache.exemptions)
+    }
+
+    // Whether an item was admitted too recently to be evicted.
+    fn is_exempt(&self, item: &Item) -> bool {
+        let Some(default) = self.cache.min_residency else {
+            return false;
+        };
+        let min_residency = item.get_min_residency().unwrap_or(default);
+        let admitted = self.cache.admitted.get(item).copied().unwrap_or(0);
+        self.cache.clock - admitted <= u64::from(min_residency)
+    }
+
+    // Gets the most space the cache may occupy before it has to evict something.
+    fn capacity(&self) -> u32 {
+        self.cache.size + self.cache.margin
+    }
+
+    // Gets the penalty for how far the cache is currently over its size.
+    pub fn overflow_penalty(&self) -> f32 {
+        self.cache.occupied.saturating_sub(self.cache.size) as f32 * self.cache.overflow_penalty
+    }
+
+    // Gets the fraction of the cache's size taken up by resident items. This can exceed one while
+    // a soft-limited cache is overflowing.
+    pub fn utilization(&self) -> f32 {
+        self.cache.occupied as f32 / self.cache.size as f32
+    }
+
+    // Hands over every item evicted since this was last called, along with why it was evicted.
+    pub fn take_evictions(&mut self) -> Vec<(&'a Item, EvictionReason)> {
+        std::mem::take(&mut self.cache.evictions)
+    }
+
+    // Describes what this cache did on the request it just serviced, given the pressure it applied
+    // and the items it evicted.
+    fn decision<'e>(
+        &self,
+        pressure: f32,
+        evicted: &'e [(&'e Item, EvictionReason)],
+    ) -> CacheDecision<'e> {
+        CacheDecision {
+            pressure,
+            evicted,
+            resident: self.cache.contents.len(),
+            occupied: self.cache.occupied,
+        }
+    }
+
+    // Gets how much faster than the uniform rate an item loses credit under our pressure mode.
+    fn pressure_weight(&self, item: &Item) -> f32 {
+        // Greedy-Dual takes credit from every item equally, which cancels out the size that
+        // Landlord scales each item's decrement by.
+        if self.cache.eviction == EvictionPolicy::GreedyDual {
+            return 1.0 / item.get_size() as f32;
+        }
+        match self.cache.pressure_mode {
+            PressureMode::Uniform => 1.0,
+            PressureMode::Recency => {
+                let last = self.cache.last_access.get(item).copied().unwrap_or(0);
+                (self.cache.clock - last + 1) as f32
+            }
+            PressureMode::Frequency => {
+                1.0 / self.cache.accesses.get(item).copied().unwrap_or(1).max(1) as f32
+            }
+        }
+    }
+
+    // Gets an item's LRFU score at the current time, decaying the score from its last access by
+    // 2^(-λ) for every request since.
+    fn crf(&self, item: &Item, lambda: f32) -> f32 {
+        let last = self.cache.last_access.get(item).copied().unwrap_or(0);
+        let score = self.cache.crf.get(item).copied().unwrap_or(0.0);
+        score * 2f32.powf(-lambda * (self.cache.clock - last) as f32)
+    }
+
+    // Gets the fraction of its cost an item's credit is refreshed to under LRFU, which is its score
+    // relative to the highest score of any item in cache.
+    fn crf_weight(&self, item: &Item, lambda: f32) -> f32 {
+        let max = self
+            .cache
+            .contents
+            .keys()
+            .map(|resident| self.crf(resident, lambda))
+            .fold(0.0, f32::max);
+        if max == 0.0 {
+            1.0
+        } else {
+            self.crf(item, lambda) / max
         }
     }
 
@@ -139,6 +1078,55 @@ impl<'a> Landlord<'a> {
         item.1 / OrderedFloat(item.0.get_size() as f32)
     }
 
+    // Gets how much of an item's credit one unit of normalized credit is worth. This is the item's
+    // size under Landlord and one under Greedy-Dual.
+    fn credit_scale(&self, item: &Item) -> f32 {
+        item.get_size() as f32 * self.pressure_weight(item)
+    }
+
+    // Gets the current credit of a resident item, undoing the credit queue's offset if it has one.
+    fn credit(&self, item: &Item) -> OrderedFloat<f32> {
+        let stored = self.cache.contents[item];
+        match &self.cache.queue {
+            Some(queue) => (stored - queue.offset) * self.credit_scale(item),
+            None => stored,
+        }
+    }
+
+    // Sets the credit of an item, placing it in cache if it is not already there. With a credit
+    // queue, this stores the credit in the queue's terms and pushes the item's new key.
+    fn set_credit(&mut self, item: &'a Item, credit: OrderedFloat<f32>) {
+        let scale = self.credit_scale(item);
+        let Some(queue) = self.cache.queue.as_mut() else {
+            self.cache.contents.insert(item, credit);
+            return;
+        };
+        let key = credit / scale + queue.offset;
+        self.cache.contents.insert(item, key);
+        queue.heap.push(Reverse((key, item)));
+        if queue.heap.len() > QUEUE_SLACK * self.cache.contents.len() {
+            self.rebuild_queue();
+        }
+    }
+
+    // Rebuilds the credit queue from the cache's contents, dropping every stale entry and
+    // subtracting the offset back out of every key.
+    fn rebuild_queue(&mut self) {
+        let Some(queue) = self.cache.queue.as_mut() else {
+            return;
+        };
+        for key in self.cache.contents.values_mut() {
+            *key -= queue.offset;
+        }
+        queue.offset = 0.0;
+        queue.heap = self
+            .cache
+            .contents
+            .iter()
+            .map(|(item, key)| Reverse((*key, *item)))
+            .collect();
+    }
+
     // Gets the tiebreaking index of a particular item if it exists in the tiebreaking vector.
     fn get_tiebreaker_index(&self, item: &'a Item) -> Option<usize> {
         self.tiebreaker.order.iter().position(|n| *n == item)
@@ -149,19 +1137,70 @@ impl<'a> Landlord<'a> {
         for item in self.cache.contents.iter() {
             ret.insert(
                 item.0.get_label().to_string(),
-                (item.1 .0, item.0.get_size()),
+                (self.credit(item.0).0, self.resident_size(item.0)),
             );
         }
         ret
     }
 
+    // Gets the credit of every resident item along with its cost.
+    pub fn get_credits(&self) -> Vec<(f32, f32)> {
+        self.cache
+            .contents
+            .keys()
+            .map(|item| (self.credit(item).0, item.get_cost().0))
+            .collect()
+    }
+
+    // Gets how many times each item has been requested and the clock tick it was last requested at,
+    // for every item the cache has seen whether or not it is still resident.
+    pub fn get_access_counts(&self) -> BTreeMap<String, (u32, u64)> {
+        self.cache
+            .accesses
+            .iter()
+            .map(|(item, accesses)| {
+                let last = self.cache.last_access.get(item).copied().unwrap_or(0);
+                (item.get_label().to_string(), (*accesses, last))
+            })
+            .collect()
+    }
+
+    // Gets how many requests the cache has serviced.
+    pub fn get_clock(&self) -> u64 {
+        self.cache.clock
+    }
+
     // Takes care of cleaning up our tiebreaking order by removing a particular item once it gets
     // evicted.
     fn manage_tiebreak(&mut self, item: &Item) {
         if let Some(index) = self.get_tiebreaker_index(item) {
             self.tiebreaker.order.remove(index);
-            self.tiebreaker.occupied -= item.get_size();
+            self.tiebreaker.occupied -= self.resident_size(item);
         }
+        self.tiebreaker.insertions.remove(item);
+    }
+
+    // Gets the space that the resident chunks of an item take up in cache.
+    fn resident_size(&self, item: &Item) -> u32 {
+        item.chunk_size(*self.cache.chunks.get(item).unwrap_or(&0))
+    }
+
+    // Removes every resident chunk of an item from cache along with its tiebreaking information.
+    fn remove(&mut self, item: &'a Item) {
+        self.manage_tiebreak(item);
+        self.cache.admitted.remove(item);
+        self.cache.occupied -= self.resident_size(item);
+        self.cache.contents.remove(item);
+        self.cache.chunks.remove(item);
+    }
+
+    // Gives a newly inserted item the next insertion sequence number.
+    fn record_insertion(&mut self, item: &'a Item) {
+        self.cache.admitted.insert(item, self.cache.clock);
+        self.tiebreaker
+            .insertions
+            .insert(item, self.tiebreaker.next_insertion);
+        self.tiebreaker.next_insertion += 1;
     }
 
     // Update our tiebreaking order on any request.
@@ -173,8 +1212,9 @@ impl<'a> Landlord<'a> {
             self.tiebreaker.order.remove(loc);
         }
         match self.tiebreaker.policy {
-            // Push the item to the back of the order
-            TiebreakingPolicy::Lru => {
+            // Push the item to the back of the order. LFU and ExpectedValue keep the same order so
+            // that they can fall back on recency between otherwise equal items.
+            TiebreakingPolicy::Lru | TiebreakingPolicy::Lfu | TiebreakingPolicy::ExpectedValue => {
                 self.tiebreaker.order.push_back(item);
             }
             TiebreakingPolicy::Fifo => {
@@ -185,22 +1225,28 @@ impl<'a> Landlord<'a> {
                     None => self.tiebreaker.order.push_back(item),
                 }
             }
-            // Insert the item into a random slot in the tiebreaking order
+            // Insert the item into a random slot in the tiebreaking order, which may be its end
             TiebreakingPolicy::Rand => {
-                let k = self.tiebreaker.size;
-                let new_index = rand::rng().random_range(0..k - 1) as usize;
+                let len = self.tiebreaker.order.len();
+                let new_index = self.tiebreak_rng.random_range(0..=len);
                 self.tiebreaker.order.insert(new_index, item);
             }
         }
     }
 
-    // Function called whenever Landlord hits on an item
-    fn hit(&mut self, label: &'a Item) {
-        // We first get the item's old credit.
-        let cred = match self.cache.contents.get_mut(label) {
-            Some(val) => val,
-            None => panic!("Could not find hit item"),
+    // Function called whenever Landlord hits on an item. Returns what the hit policy did with it.
+    fn hit(&mut self, label: &'a Item, cost: OrderedFloat<f32>) -> HitAction {
+        // LRFU needs the scores of every resident item, so we work out its refresh before
+        // borrowing the hit item's credit.
+        let crf_weight = match self.cache.policy.crf_lambda() {
+            Some(lambda) => self.crf_weight(label, lambda),
+            None => 1.0,
         };
+        // We first get the item's old credit.
+        if !self.cache.contents.contains_key(label) {
+            panic!("Could not find hit item");
+        }
+        let cred = self.credit(label);
 
         // Getting rid of the bad NaN case
         if cred.0.is_nan() {
@@ -208,19 +1254,20 @@ impl<'a> Landlord<'a> {
         }
 
         // Refresh the requested item's credit according to hit policy.
-        let new_cred = match &self.cache.policy {
-            // Refreshes it to its full cost.
-            HitPolicy::Lru => label.get_cost(),
-            // Does not refresh at all.
-            HitPolicy::Fifo => *cred,
-            // Refreshes to a random value between current credit and cost.
-            HitPolicy::Rand => OrderedFloat(rand::rng().random_range(cred.0..label.get_cost().0)),
-            // Refreshes it to half its current credit.
-            HitPolicy::Half => *cred + (label.get_cost() - *cred) / 2.0,
+        let hit = HitContext {
+            item: label,
+            credit: cred.0,
+            cost: cost.0,
+            accesses: self.cache.accesses.get(label).copied().unwrap_or(0),
+            crf_weight,
         };
+        let action = self.cache.policy.on_hit(&hit, &mut self.hit_rng);
 
         // Assigning our new credit to the item.
-        *cred = new_cred;
+        if let HitAction::Refresh(credit) = action {
+            self.set_credit(label, OrderedFloat(credit));
+        }
+        action
     }
 
     // Finding the element we want to evict in the case of a tie
@@ -229,6 +1276,43 @@ impl<'a> Landlord<'a> {
         if zeros.len() == 1 {
             return zeros[0];
         }
+        // The hit policy may pick the item itself, leaving the tiebreaking policy out of it.
+        if let Some(evicted) = self
+            .cache
+            .policy
+            .choose_eviction(&zeros, &mut self.tiebreak_rng)
+            .and_then(|index| zeros.get(index))
+        {
+            return evicted;
+        }
+        // FIFO evicts whichever tied item was inserted into cache first, regardless of when any of
+        // them were last accessed.
+        if let TiebreakingPolicy::Fifo = self.tiebreaker.policy {
+            return zeros
+                .into_iter()
+                .min_by_key(|n| self.tiebreaker.insertions.get(n))
+                .expect("Tiebreaking order mismanagement");
+        }
+        // LFU only considers the tied items with the fewest accesses and ExpectedValue the ones
+        // with the least expected savings, leaving the tiebreaking order to decide between those.
+        let accesses = |item: &&Item| self.cache.accesses.get(item).copied().unwrap_or(0);
+        let zeros = match self.tiebreaker.policy {
+            TiebreakingPolicy::Lfu => {
+                let fewest = zeros.iter().map(accesses).min().unwrap_or(0);
+                zeros
+                    .into_iter()
+                    .filter(|n| accesses(n) == fewest)
+                    .collect()
+            }
+            TiebreakingPolicy::ExpectedValue => {
+                let savings = |item: &&Item| {
+                    OrderedFloat(self.cache.requested_cost.get(item).copied().unwrap_or(0.0))
+                };
+                let least = zeros.iter().map(savings).min().unwrap_or_default();
+                zeros.into_iter().filter(|n| savings(n) == least).collect()
+            }
+            _ => zeros,
+        };
         // Otherwise, we iterate through our tiebreaking order from front to back, checking if each
         // item we find is in our zeros vector. If we find a candidate in our zeros vector, then
         // that is the element soonest on the tiebreaking order with 0 credit and so we return it.
@@ -237,73 +1321,205 @@ impl<'a> Landlord<'a> {
                 return cand;
             }
         }
-        dbg!(&zeros);
         // If we do not find any items in the zeros vector that are in our tiebreaking order, we
         // have somehow mismanaged our tiebreaking order and we throw an error.
         panic!("Tiebreaking order mismanagement");
     }
 
+    // Takes rent from every resident item by scanning for the item with the least normalized
+    // credit and decrementing every credit by it. Returns the normalized credit taken, which is the
+    // pressure increase, along with every item left with zero credit.
+    fn charge_rent(&mut self) -> (OrderedFloat<f32>, Vec<&'a Item>) {
+        // Getting the normalized credit of the minimum credit item, which is the credit it has per
+        // unit of decrement. Under uniform pressure this is just its credit divided by its size.
+        let weights: BTreeMap<&Item, f32> = self
+            .cache
+            .contents
+            .keys()
+            .map(|item| (*item, self.pressure_weight(item)))
+            .collect();
+        // Items still within their minimum residency cannot be evicted, so the rent is set by the
+        // items that can. If every item is exempt, nothing could ever be evicted, so the
+        // exemptions are ignored.
+        let mut exempt: BTreeSet<&Item> = self
+            .cache
+            .contents
+            .keys()
+            .filter(|item| self.is_exempt(item))
+            .copied()
+            .collect();
+        if exempt.len() == self.cache.contents.len() {
+            exempt.clear();
+        }
+        let (min_item, min) = self
+            .cache
+            .contents
+            .iter()
+            .filter(|a| !exempt.contains(a.0))
+            .map(|a| (*a.0, Landlord::norm_credit(a) / weights[a.0]))
+            .min_by_key(|a| a.1)
+            .expect("Could not find minimum credit element");
+        // Every exempt item that would have been evicted in its place counts as an exemption
+        self.cache.exemptions += self
+            .cache
+            .contents
+            .iter()
+            .filter(|a| exempt.contains(a.0))
+            .filter(|a| Landlord::norm_credit(*a) / weights[a.0] <= min)
+            .count() as u32;
+
+        // Decrementing the credit of each item in proportion to their size and pressure weight.
+        // The minimum credit item is set to exactly zero so rounding can never keep it from being
+        // considered for eviction. Exempt items may run out of credit but are never left below
+        // zero or considered for eviction.
+        for (item, cred) in self.cache.contents.iter_mut() {
+            *cred -= min * item.get_size() as f32 * weights[item];
+            if *item == min_item {
+                *cred = OrderedFloat(0.0);
+            }
+            if exempt.contains(item) && *cred < OrderedFloat(EPSILON) {
+                *cred = OrderedFloat(0.0);
+            }
+        }
+
+        // Finding how many items of 0 credit there are now
+        let mut zeros: Vec<&'_ Item> = Vec::new();
+        for item in self.cache.contents.iter() {
+            if *item.1 < OrderedFloat(EPSILON) && !exempt.contains(item.0) {
+                zeros.push(*item.0);
+            }
+        }
+        (min, zeros)
+    }
+
+    // Takes rent from every resident item in the same way as charge_rent, but by popping the least
+    // key off of the credit queue and raising the queue's offset to it. The minimum credit item is
+    // left with exactly zero credit since its key now equals the offset.
+    fn charge_rent_queued(&mut self) -> (OrderedFloat<f32>, Vec<&'a Item>) {
+        let queue = self
+            .cache
+            .queue
+            .as_mut()
+            .expect("Cache has no credit queue");
+        let contents = &self.cache.contents;
+        let is_live = |key: &OrderedFloat<f32>, item: &Item| contents.get(item) == Some(key);
+        // Skipping past entries for items whose credit has changed or that are no longer resident
+        while let Some(Reverse((key, item))) = queue.heap.peek() {
+            if is_live(key, item) {
+                break;
+            }
+            queue.heap.pop();
+        }
+        let Some(Reverse((min_key, _))) = queue.heap.peek() else {
+            panic!("Could not find minimum credit element");
+        };
+        let min = *min_key - queue.offset;
+        queue.offset = min_key.0;
+
+        // Popping every item whose key is within EPSILON of the offset. Credit is never less than
+        // its key's distance from the offset, so these are the only items that can be at zero.
+        // Every live entry is pushed back afterward since the evicted item may keep some chunks.
+        let mut popped = Vec::new();
+        let mut zeros: Vec<&'a Item> = Vec::new();
+        while let Some(Reverse((key, item))) = queue.heap.peek().copied() {
+            if key - queue.offset >= OrderedFloat(EPSILON) {
+                break;
+            }
+            queue.heap.pop();
+            if !is_live(&key, item) {
+                continue;
+            }
+            popped.push(Reverse((key, item)));
+            if !zeros.contains(&item) {
+                zeros.push(item);
+            }
+        }
+        queue.heap.extend(popped);
+        let over_limit = queue.offset > queue.offset_limit;
+        // Only items whose credit itself is near zero are tied for eviction
+        zeros.retain(|item| self.credit(item) < OrderedFloat(EPSILON));
+        if over_limit {
+            self.rebuild_queue();
+        }
+        (min, zeros)
+    }
+
     // Evicting an element if we do not have enough space for it
     fn evict(&mut self, size: u32) -> OrderedFloat<f32> {
         // Getting our return value
         let mut pressure = OrderedFloat(0.0);
 
         // Base case: we have enough space for our item and so we simply return 0
-        // because our pressure does not increase when we bring an item into cache.
-        if self.cache.size - self.cache.occupied >= size {
+        // because our pressure does not increase when we bring an item into cache. An item as
+        // large as the whole cache always reaches this once everything else has been evicted.
+        if self.capacity().saturating_sub(self.cache.occupied) >= size {
             return pressure;
         }
 
-        // Getting the normalized credit of the minimum credit item
-        let min = Landlord::norm_credit(
-            self.cache
-                .contents
-                .iter()
-                .min_by_key(|a| a.1 / OrderedFloat(a.0.get_size() as f32))
-                .expect("Could not find minimum credit element"),
-        );
-
-        // Decrementing the credit of each item in proportion to their size
-        for (item, cred) in self.cache.contents.iter_mut() {
-            *cred -= min * item.get_size() as f32;
-        }
+        // Taking rent from every item and finding which items it left with no credit
+        let (min, zeros) = match self.cache.queue {
+            Some(_) => self.charge_rent_queued(),
+            None => self.charge_rent(),
+        };
         // Increasing the pressure in relation to the credit of the minimum credit item we just
         // evicted.
         pressure += min;
 
-        // Finding how many items of 0 credit there are now
-        let mut zeros: Vec<&'_ Item> = Vec::new();
-        for item in self.cache.contents.iter() {
-            if *item.1 < OrderedFloat(EPSILON) {
-                zeros.push(*item.0);
-            }
-        }
         // Letting our tiebreaking policy take care of choosing the evicted item
         let evicted = self.tiebreak(zeros);
-        self.manage_tiebreak(evicted);
 
-        // Removing the item it picks from our cache and decreasing the occupied space by the size
-        // of the item we just evicted.
-        self.cache.contents.remove(evicted);
-        self.cache.occupied -= evicted.get_size();
+        // If the evicted item is chunked, we only evict as many of its chunks as we need to make
+        // room and leave the rest resident. Otherwise, we remove the item it picks from our cache
+        // and decrease the occupied space by the size of the item we just evicted.
+        let needed = self.cache.occupied + size - self.capacity();
+        let resident = self.cache.chunks[evicted];
+        let kept = (0..resident)
+            .rev()
+            .find(|kept| evicted.chunk_size(resident) - evicted.chunk_size(*kept) >= needed)
+            .unwrap_or(0);
+        // A dirty item has to be written back to the origin before any of it can leave cache.
+        if self.cache.dirty.remove(evicted) {
+            let cost = self.round_cost(evicted.get_cost().0);
+            let cost = self.clip(cost);
+            self.cache.writebacks += cost;
+        }
+        if kept == 0 {
+            self.remove(evicted);
+            self.remember_eviction(evicted);
+            let reason = if self.cache.margin > 0 {
+                EvictionReason::SoftLimit
+            } else {
+                EvictionReason::Capacity
+            };
+            self.cache.evictions.push((evicted, reason));
+        } else {
+            self.cache.evictions.push((evicted, EvictionReason::Chunk));
+            let freed = evicted.chunk_size(resident) - evicted.chunk_size(kept);
+            self.cache.chunks.insert(evicted, kept);
+            self.cache.occupied -= freed;
+            self.tiebreaker.occupied -= freed;
+        }
 
         // Returning our pressure at the end
         pressure + self.evict(size)
     }
 
     // The function called whenever the Landlord implementation faults on a request.
-    fn fault(&mut self, item: &'a Item) -> OrderedFloat<f32> {
+    fn fault(&mut self, item: &'a Item, cost: OrderedFloat<f32>) -> OrderedFloat<f32> {
+        let credit = OrderedFloat(self.cache.policy.on_insert(item, cost.0));
         // If the cache has too many items, throw an error.
-        if self.cache.occupied > self.cache.size {
+        if self.cache.occupied > self.capacity() {
             panic!("Cache is overfull");
         }
         // If the cache has empty space, just add the item!
-        else if self.cache.occupied + item.get_size() <= self.cache.size {
-            // We insert the item into cache at full cost.
-            self.cache.contents.insert(item, item.get_cost());
+        else if self.cache.occupied + item.get_size() <= self.capacity() {
+            // We insert the item into cache at the credit our policy gives it.
+            self.set_credit(item, credit);
+            self.cache.chunks.insert(item, item.get_chunks());
             // We increase the occupied cache/tiebreaker space by our item's size.
             self.cache.occupied += item.get_size();
             self.tiebreaker.occupied += item.get_size();
+            self.record_insertion(item);
             // We return pressure 0 because we did not have to evict anything.
             OrderedFloat(0.0)
         }
@@ -314,91 +1530,1167 @@ impl<'a> Landlord<'a> {
             // We allow our recursive eviction function to evict items until we have enough space,
             // thereby also getting our pressure.
             let pressure = self.evict(size);
-            // We insert our item into cache at full credit.
-            self.cache.contents.insert(item, item.get_cost());
+            // We insert our item into cache at the credit our policy gives it.
+            self.set_credit(item, credit);
+            self.cache.chunks.insert(item, item.get_chunks());
             self.cache.occupied += item.get_size();
             self.tiebreaker.occupied += item.get_size();
+            self.record_insertion(item);
             pressure
         }
     }
 
+    // Places an item into cache at full credit before any requests are serviced, as long as there
+    // is room for it without evicting anything. Returns whether the item is resident afterward.
+    pub fn preload(&mut self, item: &'a Item) -> bool {
+        if self.cache.contents.contains_key(item) {
+            return true;
+        }
+        if self.is_bypassed(item) || self.cache.occupied + item.get_size() > self.cache.size {
+            return false;
+        }
+        self.fault(item, item.get_cost().max(OrderedFloat(0.0)));
+        self.update_tiebreak(item);
+        true
+    }
+
     // Handle our request
-    pub fn request(&mut self, item: &'a Item) -> RequestResult {
+    pub fn request(&mut self, request: Request<'a>) -> RequestResult {
+        let item = request.get_item();
+        // Keeping track of when and how often each item is requested
+        self.cache.clock += 1;
+        self.cache
+            .first_access
+            .entry(item)
+            .or_insert(self.cache.clock);
+        // Credit is never negative, so items with a negative cost are cached with zero credit.
+        let mut cost = request.get_cost().max(OrderedFloat(0.0));
+        if let (Some(cap), true) = (self.cache.clip_cost, self.cache.clip_credit) {
+            cost = cost.min(OrderedFloat(cap as f32));
+        }
+        let cost = cost * self.decay(item);
+        *self.cache.accesses.entry(item).or_insert(0) += 1;
+        // Requests are charged their cost up to the clip, whether or not the clip caps credit
+        let charged = match self.cache.clip_cost {
+            Some(cap) => request.get_cost().min(OrderedFloat(cap as f32)),
+            None => request.get_cost(),
+        };
+        *self.cache.requested_cost.entry(item).or_insert(0.0) += charged.0;
+        if let Some(sketch) = self.cache.sketch.as_mut() {
+            sketch.record(item);
+        }
+        if let Some(lambda) = self.cache.policy.crf_lambda() {
+            let score = 1.0 + self.crf(item, lambda);
+            self.cache.crf.insert(item, score);
+        }
+        self.cache.last_access.insert(item, self.cache.clock);
+        // If only some of a chunked item's chunks are resident, we have to fetch the rest. We do
+        // this by dropping the resident chunks and faulting the whole item back in, which evicts
+        // exactly as much as the missing chunks need and brings the item back at full credit.
+        let resident = self.cache.chunks.get(item).copied().unwrap_or(0);
+        if resident != 0 && resident < item.get_chunks() {
+            let missing = (item.get_chunks() - resident) as f32 / item.get_chunks() as f32;
+            self.remove(item);
+            let pressure = self.fault(item, cost);
+            self.update_tiebreak(item);
+            RequestResult::PartialHit(missing, *pressure)
+        }
         // If our cache contains the requested item, we have a hit!
-        if self.cache.contents.contains_key(&item) {
+        else if self.cache.contents.contains_key(&item) {
             // We hit on that item, updating its credit according to hit policy.
-            self.hit(item);
-            // We update our tiebreaking order.
-            self.update_tiebreak(item);
+            let action = self.hit(item, cost);
+            // We update our tiebreaking order, unless our hit policy ignores hits entirely.
+            if action != HitAction::Ignore {
+                self.update_tiebreak(item);
+            }
             // We return a request result of a hit
             RequestResult::Hit
         }
+        // Items too large to cache always miss, paying their cost without touching the cache.
+        else if self.is_bypassed(item) {
+            self.cache.bypassed += 1;
+            RequestResult::Fault(0.0)
+        }
         // Otherwise, we have a fault :(.
         else {
+            // If the ghost still remembers evicting this item, a larger cache would have hit.
+            if let Some(index) = self.cache.ghost.iter().position(|n| *n == item) {
+                self.cache.ghost.remove(index);
+                self.cache.ghost_hits += 1;
+            }
+            // If the victim cache still holds this item, we promote it back into cache at full
+            // credit, only paying the victim cache's share of its cost.
+            if let Some(index) = self.cache.victim.iter().position(|n| *n == item) {
+                self.cache.victim.remove(index);
+                self.cache.victim_hits += 1;
+                let pressure = self.fault(item, cost);
+                self.update_tiebreak(item);
+                return RequestResult::VictimHit(self.cache.victim_cost, *pressure);
+            }
+            // If the item is not admitted, we still pay for it but leave the cache untouched.
+            if !self.admit(item) {
+                return RequestResult::Fault(0.0);
+            }
             // We get the pressure as a result of that fault.
-            let pressure = self.fault(item);
+            let pressure = self.fault(item, cost);
             // We update our tiebreaking ordering no matter what.
             self.update_tiebreak(item);
             // We wrap our pressure in a request result of a fault.
-            dbg!(&self);
             RequestResult::Fault(*pressure)
         }
     }
 
+    // Services the given requests with this cache alone, charging each one exactly as run does but
+    // logging nothing, and returns the total cost paid for them.
+    pub fn service_all<'r>(&mut self, requests: impl IntoIterator<Item = &'r Request<'a>>) -> i64
+    where
+        'a: 'r,
+    {
+        let mut total = 0;
+        for request in requests {
+            let cost = match self.request(*request) {
+                RequestResult::Hit => self.charge(request, 0),
+                RequestResult::Fault(_) => {
+                    let cost = self.round_cost(request.get_cost().0);
+                    self.charge(request, cost)
+                }
+                RequestResult::PartialHit(fraction, _) | RequestResult::VictimHit(fraction, _) => {
+                    let cost = self.round_cost(request.get_cost().0 * fraction);
+                    self.charge(request, cost)
+                }
+            };
+            self.take_evictions();
+            total += i64::from(cost);
+        }
+        total
+    }
+
     // Run our Landlord implementation over the provided trace. Trace is the trace you would like
-    // the two landlord implementations to service, suffix_start is the point at which you want to
-    // split the trace into prefix and suffix (exclusive of endpoints) and the logger is what
-    // keeps track of costs and pressure.
+    // the two landlord implementations to service, suffix_start is the index of the first request
+    // in the suffix and the logger is what keeps track of costs and pressure. The suffix cache is
+    // idle for every request before suffix_start: it services nothing and is logged as paying zero
+    // cost and zero pressure there. The observer is called after every request with how both
+    // caches fared on it so that callers can follow the run as it happens. Without a suffix cache,
+    // only the full cache is simulated and nothing is logged for the suffix at all. If the logger
+    // has a cost budget, the run stops as soon as the full cache has paid it, leaving the rest of
+    // the trace unserviced. The filled logger is handed back inside the run result along with both
+    // caches in their final state.
     pub fn run(
-        trace: VecDeque<&'a Item>,
+        trace: VecDeque<Request<'a>>,
         suffix_start: u32,
-        mut s: Landlord<'a>,
+        mut s: Option<Landlord<'a>>,
         mut f: Landlord<'a>,
-        logger: &mut Logger,
-    ) {
+        mut logger: Logger,
+        mut observer: impl FnMut(&RequestEvent),
+    ) -> RunResult<'a> {
+        let mut full_hits = 0;
+        let mut full_faults = 0;
+        let mut suff_hits = 0;
+        let mut suff_faults = 0;
+        logger.log_suffix_start(suffix_start);
+        if s.is_none() {
+            logger.log_full_only();
+        }
+        let mut serviced = trace.len();
         // For each request in our trace
         for (i, request) in trace.iter().enumerate() {
-            println!("Servicing request {}", i);
+            // Snapshotting both caches after the requests before this one if we were asked to
+            logger.log_snapshot(i as u32, &f, s.as_ref());
+            logger.log_credit_sample(i as u32, &f, s.as_ref());
+            // Stopping once the full cache has spent its budget
+            if logger.is_budget_spent() {
+                logger.log_budget_stop(i as u32);
+                serviced = i;
+                break;
+            }
             // We issue that request to the full trace cache because that one is going to have to
             // service that request no matter what.
-            let res = f.request(request);
+            let res = f.request(*request);
             // From there, we match on the result
-            match res {
+            let (full_hit, full_cost, full_pres) = match res {
                 // If it is a hit, we log that the request was a hit with our cost logger and
                 // pressure logger.
                 RequestResult::Hit => {
-                    logger.log_cost(request, RequestFullOrSuffix::Full(true));
+                    let cost = f.charge(request, 0);
+                    full_hits += 1;
+                    logger.log_class(request, cost, RequestFullOrSuffix::Full(true));
+                    logger.log_read_write(request.is_write(), RequestFullOrSuffix::Full(true));
+                    logger.log_cost(request, cost, RequestFullOrSuffix::Full(true));
                     logger.log_pres(0.0, RequestFullOrSuffix::Full(true));
+                    (true, cost, 0.0)
                 }
                 // If the request was a hi, we log_cost that the full trace cache paid that item's cost
                 // and that the pressure went up by whatever amount we wrapped in RequestResult.
                 RequestResult::Fault(pressure) => {
-                    logger.log_cost(request, RequestFullOrSuffix::Full(false));
+                    let cost = f.round_cost(request.get_cost().0);
+                    let cost = f.charge(request, cost);
+                    full_faults += 1;
+                    logger.log_class(request, cost, RequestFullOrSuffix::Full(false));
+                    logger.log_read_write(request.is_write(), RequestFullOrSuffix::Full(false));
+                    logger.log_cost(request, cost, RequestFullOrSuffix::Full(false));
                     logger.log_pres(pressure, RequestFullOrSuffix::Full(false));
+                    (false, cost, pressure)
                 }
-            }
+                // A partial hit is logged as a fault that only pays for the missing chunks, and a
+                // hit in the victim cache as one that only pays the victim cache's share.
+                RequestResult::PartialHit(fraction, pressure)
+                | RequestResult::VictimHit(fraction, pressure) => {
+                    let cost = f.round_cost(request.get_cost().0 * fraction);
+                    let cost = f.charge(request, cost);
+                    full_faults += 1;
+                    logger.log_class(request, cost, RequestFullOrSuffix::Full(false));
+                    logger.log_read_write(request.is_write(), RequestFullOrSuffix::Full(false));
+                    logger.log_cost(request, cost, RequestFullOrSuffix::Full(false));
+                    logger.log_pres(pressure, RequestFullOrSuffix::Full(false));
+                    (false, cost, pressure)
+                }
+            };
+            let full_evictions = f.take_evictions();
+            logger.log_evictions(&full_evictions, true);
+            logger.log_penalty(f.overflow_penalty(), true);
+            logger.log_utilization(f.utilization(), true);
             logger.log_state(&f, true);
+            let full_hit_ratio = full_hits as f32 / (full_hits + full_faults) as f32;
+            let full = f.decision(full_pres, &full_evictions);
+            // If we are only simulating the full cache, there is nothing left to do.
+            let Some(s) = s.as_mut() else {
+                observer(&RequestEvent {
+                    index: i,
+                    label: request.get_label(),
+                    full_hit,
+                    full_cost,
+                    full_hit_ratio,
+                    full,
+                    suff_hit: None,
+                    suff_cost: 0,
+                    suff_hit_ratio: 0.0,
+                    suff: None,
+                });
+                continue;
+            };
             // If we are not in the suffix yet, we are going to say that S simply paid no cost.
             // This is relevant for when we calculate individual suffix competitive ratios later.
             if i < suffix_start as usize {
-                logger.log_cost(request, RequestFullOrSuffix::Suff(true));
+                logger.log_cost(request, 0, RequestFullOrSuffix::Suff(true));
                 logger.log_pres(0.0, RequestFullOrSuffix::Suff(true));
+                logger.log_utilization(0.0, false);
+                observer(&RequestEvent {
+                    index: i,
+                    label: request.get_label(),
+                    full_hit,
+                    full_cost,
+                    full_hit_ratio,
+                    full,
+                    suff_hit: None,
+                    suff_cost: 0,
+                    suff_hit_ratio: 0.0,
+                    suff: None,
+                });
                 continue;
             }
-            let res = s.request(request);
+            let res = s.request(*request);
             // We perform an identical match statement as above but instead we just label that the
             // request results are for suff instead.
-            match res {
+            let (suff_hit, suff_cost, suff_pres) = match res {
                 RequestResult::Hit => {
-                    logger.log_cost(request, RequestFullOrSuffix::Suff(true));
+                    let cost = s.charge(request, 0);
+                    suff_hits += 1;
+                    logger.log_class(request, cost, RequestFullOrSuffix::Suff(true));
+                    logger.log_read_write(request.is_write(), RequestFullOrSuffix::Suff(true));
+                    logger.log_cost(request, cost, RequestFullOrSuffix::Suff(true));
                     logger.log_pres(0.0, RequestFullOrSuffix::Suff(true));
+                    (true, cost, 0.0)
                 }
                 RequestResult::Fault(pressure) => {
-                    logger.log_cost(request, RequestFullOrSuffix::Suff(false));
+                    let cost = s.round_cost(request.get_cost().0);
+                    let cost = s.charge(request, cost);
+                    suff_faults += 1;
+                    logger.log_class(request, cost, RequestFullOrSuffix::Suff(false));
+                    logger.log_read_write(request.is_write(), RequestFullOrSuffix::Suff(false));
+                    logger.log_cost(request, cost, RequestFullOrSuffix::Suff(false));
+                    logger.log_pres(pressure, RequestFullOrSuffix::Suff(false));
+                    (false, cost, pressure)
+                }
+                RequestResult::PartialHit(fraction, pressure)
+                | RequestResult::VictimHit(fraction, pressure) => {
+                    let cost = s.round_cost(request.get_cost().0 * fraction);
+                    let cost = s.charge(request, cost);
+                    suff_faults += 1;
+                    logger.log_class(request, cost, RequestFullOrSuffix::Suff(false));
+                    logger.log_read_write(request.is_write(), RequestFullOrSuffix::Suff(false));
+                    logger.log_cost(request, cost, RequestFullOrSuffix::Suff(false));
                     logger.log_pres(pressure, RequestFullOrSuffix::Suff(false));
+                    (false, cost, pressure)
                 }
+            };
+            let suff_evictions = s.take_evictions();
+            logger.log_evictions(&suff_evictions, false);
+            logger.log_penalty(s.overflow_penalty(), false);
+            logger.log_utilization(s.utilization(), false);
+            logger.log_state(s, false);
+            observer(&RequestEvent {
+                index: i,
+                label: request.get_label(),
+                full_hit,
+                full_cost,
+                full_hit_ratio,
+                full,
+                suff_hit: Some(suff_hit),
+                suff_cost,
+                suff_hit_ratio: suff_hits as f32 / (suff_hits + suff_faults) as f32,
+                suff: Some(s.decision(suff_pres, &suff_evictions)),
+            });
+        }
+        if serviced == trace.len() {
+            logger.log_snapshot(serviced as u32, &f, s.as_ref());
+            logger.log_credit_sample(serviced as u32, &f, s.as_ref());
+        }
+        // Without a suffix cache, its statistics are reported as zero.
+        let suff_stat = |stat: fn(&Landlord<'a>) -> Option<u32>| s.as_ref().map_or(Some(0), stat);
+        if let (Some(full), Some(suff)) = (f.ghost_hits(), suff_stat(Landlord::ghost_hits)) {
+            logger.log_ghost_hits(full, suff);
+        }
+        if let (Some(full), Some(suff)) = (f.victim_hits(), suff_stat(Landlord::victim_hits)) {
+            logger.log_victim_hits(full, suff);
+        }
+        if let (Some(full), Some(suff)) = (
+            f.residency_exemptions(),
+            suff_stat(Landlord::residency_exemptions),
+        ) {
+            logger.log_residency_exemptions(full, suff);
+        }
+        if let (Some(full), Some(suff)) = (
+            f.deferred_admissions(),
+            suff_stat(Landlord::deferred_admissions),
+        ) {
+            logger.log_deferred_admissions(full, suff);
+        }
+        if let (Some(full), Some(suff)) = (f.bypassed(), suff_stat(Landlord::bypassed)) {
+            logger.log_bypassed(full, suff);
+        }
+        if let Some(full) = f.clipped_cost() {
+            let suff = s.as_ref().and_then(Landlord::clipped_cost).unwrap_or(0);
+            logger.log_clipped_cost(full, suff);
+        }
+        // Making sure the suffix cache really was idle during the prefix and serviced every request
+        // after it.
+        if s.is_some() {
+            assert!(
+                logger.is_suffix_idle_before(suffix_start),
+                "Suffix cache paid cost before the division point"
+            );
+            assert_eq!(
+                (suff_hits + suff_faults) as usize,
+                serviced.saturating_sub(suffix_start as usize),
+                "Suffix cache did not service every request after the division point"
+            );
+        }
+        RunResult {
+            logger,
+            full: f,
+            suff: s,
+            full_hits,
+            full_faults,
+            suff_hits,
+            suff_faults,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(size: u32, tiebreak: TiebreakingPolicy, hit: HitPolicy) -> Landlord<'static> {
+        Landlord::new(size, tiebreak, Arc::new(hit)).with_seed(0)
+    }
+
+    fn item(label: &str, cost: i32, size: u32) -> Item {
+        Item::new(label.to_string(), cost, size)
+    }
+
+    // The labels of the items resident in a cache.
+    fn resident(cache: &Landlord) -> Vec<String> {
+        cache.get_cache_state().into_keys().collect()
+    }
+
+    // Builds a trace of unit items requested in the order of the given labels.
+    fn unit_trace<'a>(items: &'a [Item], labels: &str) -> VecDeque<Request<'a>> {
+        labels
+            .chars()
+            .map(|label| {
+                let item = items
+                    .iter()
+                    .find(|n| *n.get_label() == label.to_string())
+                    .unwrap();
+                Request::new(item, None)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn run_returns_both_final_caches_and_their_counts() {
+        let items = [item("a", 1, 1), item("b", 1, 1), item("c", 1, 1)];
+        let trace = unit_trace(&items, "abacab");
+        let lru = || cache(2, TiebreakingPolicy::Lru, HitPolicy::Lru);
+        let result = Landlord::run(
+            trace.clone(),
+            2,
+            Some(lru()),
+            lru(),
+            Logger::new(&trace),
+            |_| {},
+        );
+        // The full cache hits the second a and the fourth, while the suffix cache starts empty at
+        // the third request and only hits the fourth.
+        assert_eq!((result.full_hits, result.full_faults), (2, 4));
+        assert_eq!((result.suff_hits, result.suff_faults), (1, 3));
+        assert_eq!(result.full_hit_ratio(), 2.0 / 6.0);
+        assert_eq!(result.suff_hit_ratio(), 1.0 / 4.0);
+        assert_eq!(resident(&result.full), vec!["a", "b"]);
+        assert_eq!(resident(result.suff.as_ref().unwrap()), vec!["a", "b"]);
+        assert_eq!(result.logger.get_full_cost_range(6), 4);
+        assert_eq!(result.logger.get_suff_cost_range(6), 3);
+
+        let result = Landlord::run(trace.clone(), 2, None, lru(), Logger::new(&trace), |_| {});
+        assert!(result.suff.is_none());
+        assert_eq!((result.full_hits, result.full_faults), (2, 4));
+        assert_eq!((result.suff_hits, result.suff_faults), (0, 0));
+        assert_eq!(result.suff_hit_ratio(), 0.0);
+    }
+
+    #[test]
+    fn fifo_tiebreaking_evicts_by_insertion_not_access() {
+        let items = [item("a", 1, 1), item("b", 1, 1), item("c", 1, 1)];
+        // a is inserted first but accessed last, so both items are left with the same credit and
+        // FIFO and LRU tiebreaking have to disagree on which goes.
+        for (tiebreak, kept) in [
+            (TiebreakingPolicy::Fifo, "b"),
+            (TiebreakingPolicy::Lru, "a"),
+        ] {
+            let mut cache = cache(2, tiebreak, HitPolicy::Fifo);
+            for request in unit_trace(&items, "abac") {
+                cache.request(request);
             }
-            logger.log_state(&s, false);
+            assert_eq!(resident(&cache), vec![kept, "c"]);
+        }
+    }
+
+    #[test]
+    fn soft_limit_charges_a_penalty_while_overflowing() {
+        let items = [
+            item("a", 1, 1),
+            item("b", 1, 1),
+            item("c", 1, 1),
+            item("d", 1, 1),
+        ];
+        let mut cache = cache(2, TiebreakingPolicy::Lru, HitPolicy::Lru).with_soft_limit(1, 0.5);
+        let mut penalties = Vec::new();
+        let mut evictions = Vec::new();
+        for request in unit_trace(&items, "abcd") {
+            cache.request(request);
+            penalties.push(cache.overflow_penalty());
+            evictions.extend(cache.take_evictions().into_iter().map(|n| n.1));
+        }
+        // c overflows the cache by one line instead of evicting, and d has to evict to stay there
+        assert_eq!(penalties, vec![0.0, 0.0, 0.5, 0.5]);
+        assert_eq!(evictions, vec![EvictionReason::SoftLimit]);
+        assert_eq!(resident(&cache).len(), 3);
+    }
+
+    #[test]
+    fn preloaded_items_hit_on_their_first_request() {
+        let items = [item("a", 1, 1), item("b", 1, 1), item("c", 1, 2)];
+        let mut cache = cache(3, TiebreakingPolicy::Lru, HitPolicy::Lru);
+        assert!(cache.preload(&items[0]) && cache.preload(&items[1]));
+        assert!(cache.preload(&items[0]), "A resident item stays resident");
+        // Preloading never evicts, so there is no room left for c
+        assert!(!cache.preload(&items[2]));
+        let results: Vec<bool> = unit_trace(&items, "abc")
+            .into_iter()
+            .map(|n| matches!(cache.request(n), RequestResult::Hit))
+            .collect();
+        assert_eq!(results, vec![true, true, false]);
+    }
+
+    #[test]
+    fn half_resident_item_pays_half_its_cost() {
+        let big: Item = toml::from_str("label = 'b'\ncost = 10\nsize = 4\nchunks = 2").unwrap();
+        let items = [big, item("s", 1, 2)];
+        // s only needs one of b's two chunks evicted to fit, so b is then half resident
+        let trace = unit_trace(&items, "bsb");
+        let lru = cache(4, TiebreakingPolicy::Lru, HitPolicy::Lru);
+        let result = Landlord::run(trace.clone(), 0, None, lru, Logger::new(&trace), |_| {});
+        let costs: Vec<i32> = (0..3).map(|n| result.logger.get_full_cost(n)).collect();
+        assert_eq!(costs, vec![10, 1, 5]);
+        assert_eq!((result.full_hits, result.full_faults), (0, 3));
+    }
+
+    #[test]
+    fn half_of_an_odd_cost_is_rounded_by_the_cost_rounding() {
+        let big: Item = toml::from_str("label = 'b'\ncost = 3\nsize = 4\nchunks = 2").unwrap();
+        let items = [big, item("s", 1, 2)];
+        // b is half resident on its second request, which leaves 1.5 to be charged
+        let trace = unit_trace(&items, "bsb");
+        // A negative half rounds the same way, with the nearest whole cost away from zero
+        for (rounding, half, negative) in [
+            (CostRounding::Truncate, 1, -1),
+            (CostRounding::Floor, 1, -2),
+            (CostRounding::Ceil, 2, -1),
+            (CostRounding::Nearest, 2, -2),
+        ] {
+            let lru = cache(4, TiebreakingPolicy::Lru, HitPolicy::Lru).with_cost_rounding(rounding);
+            let result = Landlord::run(trace.clone(), 0, None, lru, Logger::new(&trace), |_| {});
+            let costs: Vec<i32> = (0..3).map(|n| result.logger.get_full_cost(n)).collect();
+            assert_eq!(costs, vec![3, 1, half], "{:?}", rounding);
+            assert_eq!(rounding.round(-1.5), negative, "{:?}", rounding);
         }
     }
+
+    #[test]
+    fn victim_cache_rescues_a_loop_just_too_large_to_fit() {
+        let items = [item("a", 10, 1), item("b", 10, 1), item("c", 10, 1)];
+        let trace = unit_trace(&items, &"abc".repeat(20));
+        let run = |victim: Option<u32>| {
+            let mut lru = cache(2, TiebreakingPolicy::Lru, HitPolicy::Lru);
+            if let Some(capacity) = victim {
+                lru = lru.with_victim(capacity, DEFAULT_VICTIM_COST);
+            }
+            let mut faults = 0;
+            let result = Landlord::run(trace.clone(), 0, None, lru, Logger::new(&trace), |event| {
+                faults += u32::from(event.full_cost == 10);
+            });
+            (
+                faults,
+                result.logger.get_full_cost_range(60),
+                result.full.victim_hits(),
+            )
+        };
+        // LRU with two lines misses every request of a loop over three items
+        assert_eq!(run(None), (60, 600, None));
+        // The item it just evicted is always the next one requested, so a victim cache of one line
+        // catches every fault after the first pass, each paying a tenth of the cost
+        assert_eq!(run(Some(1)), (3, 30 + 57, Some(57)));
+    }
+
+    #[test]
+    fn items_over_the_threshold_bypass_the_cache() {
+        let items = [item("a", 1, 1), item("b", 5, 3)];
+        let trace = unit_trace(&items, "ababb");
+        let full = cache(4, TiebreakingPolicy::Lru, HitPolicy::Lru).with_max_cacheable_size(2);
+        // b would fit, but is too large to be cached and so pays its cost every time while only
+        // a ever takes up space
+        let mut costs = Vec::new();
+        let result = Landlord::run(trace.clone(), 0, None, full, Logger::new(&trace), |event| {
+            costs.push(event.full_cost);
+            assert_eq!(event.full.occupied, 1);
+        });
+        assert_eq!(costs, vec![1, 5, 0, 5, 5]);
+        assert_eq!(result.full.bypassed(), Some(3));
+        assert_eq!(resident(&result.full), vec!["a"]);
+    }
+
+    #[test]
+    fn lfu_tiebreaking_evicts_the_least_requested_tied_item() {
+        // a and b both have full credit when c arrives, a being requested more but less recently
+        let items = [item("a", 1, 1), item("b", 1, 1), item("c", 1, 1)];
+        for (tiebreak, kept) in [(TiebreakingPolicy::Lru, "b"), (TiebreakingPolicy::Lfu, "a")] {
+            let mut cache = cache(2, tiebreak, HitPolicy::Lru);
+            for request in unit_trace(&items, "aaabc") {
+                cache.request(request);
+            }
+            assert_eq!(resident(&cache), vec![kept, "c"]);
+        }
+    }
+
+    #[test]
+    fn tiebreaking_draws_do_not_disturb_the_hit_stream() {
+        let items = [item("a", 1, 1), item("b", 1, 1), item("c", 1, 1)];
+        let run = |tiebreak: TiebreakingPolicy| {
+            let mut cache = cache(2, tiebreak, HitPolicy::Lru).with_seed(11);
+            for request in unit_trace(&items, "abcabcabc") {
+                cache.request(request);
+            }
+            cache
+        };
+        let mut random = run(TiebreakingPolicy::Rand);
+        let mut deterministic = run(TiebreakingPolicy::Lru);
+        // Only the random tiebreaker drew from its stream, which has moved on from where it started
+        let mut fresh = cache(2, TiebreakingPolicy::Lru, HitPolicy::Lru).with_seed(11);
+        assert_ne!(
+            random.tiebreak_rng.random::<u64>(),
+            fresh.tiebreak_rng.random::<u64>()
+        );
+        // The hit and admission streams are where they started either way
+        let hit_draws =
+            |cache: &mut Landlord| -> Vec<u64> { (0..4).map(|_| cache.hit_rng.random()).collect() };
+        let draws = hit_draws(&mut random);
+        assert_eq!(draws, hit_draws(&mut deterministic));
+        assert_eq!(draws, hit_draws(&mut fresh));
+        assert_eq!(
+            random.admission_rng.random::<u64>(),
+            deterministic.admission_rng.random::<u64>()
+        );
+    }
+
+    #[test]
+    fn random_tiebreaking_fills_caches_of_several_lines() {
+        let items = [
+            item("a", 1, 1),
+            item("b", 1, 1),
+            item("c", 1, 1),
+            item("d", 1, 1),
+            item("e", 1, 1),
+        ];
+        // Every slot of the order, including its end, has to be a valid place to insert into
+        // while the cache is still filling up and once it is full.
+        for size in [1, 3, 4] {
+            for seed in 0..8 {
+                let mut cache = cache(size, TiebreakingPolicy::Rand, HitPolicy::Lru).with_seed(seed);
+                for request in unit_trace(&items, "abcdabcdeaebdc") {
+                    cache.request(request);
+                    assert_eq!(cache.tiebreaker.order.len(), resident(&cache).len());
+                }
+                assert_eq!(resident(&cache).len(), size as usize);
+            }
+        }
+    }
+
+    #[test]
+    fn decay_shrinks_the_credit_refresh_as_an_item_ages() {
+        let items = [item("a", 16, 1), item("b", 1, 1)];
+        let credit = |cache: &Landlord| cache.get_cache_state()["a"].0;
+        let mut decaying =
+            cache(4, TiebreakingPolicy::Lru, HitPolicy::Lru).with_decay_half_life(2.0);
+        let mut steady = cache(4, TiebreakingPolicy::Lru, HitPolicy::Lru);
+        // a is refreshed every other request, so every refresh is worth half the last
+        let mut refreshes = Vec::new();
+        for request in unit_trace(&items, "abababa") {
+            decaying.request(request);
+            steady.request(request);
+            if request.get_label() == "a" {
+                refreshes.push(credit(&decaying));
+                assert_eq!(credit(&steady), 16.0);
+            }
+        }
+        assert_eq!(refreshes, vec![16.0, 8.0, 4.0, 2.0]);
+    }
+
+    #[test]
+    fn write_back_defers_what_write_through_pays_on_every_write() {
+        let items = [item("a", 5, 1), item("b", 3, 1)];
+        // Three writes to a, a read of b that evicts a, then another write to a
+        let trace: VecDeque<Request> = unit_trace(&items, "aaaba")
+            .into_iter()
+            .map(|n| {
+                let is_write = n.get_label() == "a";
+                n.with_write(is_write)
+            })
+            .collect();
+        let costs = |policy: WritePolicy| -> Vec<i32> {
+            let full = cache(1, TiebreakingPolicy::Lru, HitPolicy::Lru).with_write_policy(policy);
+            let result = Landlord::run(trace.clone(), 0, None, full, Logger::new(&trace), |_| {});
+            (0..5).map(|n| result.logger.get_full_cost(n)).collect()
+        };
+        // Write-through pays for every write, hit or not
+        assert_eq!(costs(WritePolicy::Through), vec![5, 5, 5, 3, 5]);
+        // Write-back only pays to write a back when b evicts it, and a is left dirty at the end
+        assert_eq!(costs(WritePolicy::Back), vec![0, 0, 0, 8, 0]);
+    }
+
+    #[test]
+    fn clipping_caps_what_a_huge_item_is_charged() {
+        let items = [item("h", 1000, 1), item("s", 2, 1)];
+        let trace = unit_trace(&items, "hshsh");
+        for clip_credit in [false, true] {
+            let full =
+                cache(1, TiebreakingPolicy::Lru, HitPolicy::Lru).with_clip_cost(10, clip_credit);
+            let result = Landlord::run(trace.clone(), 0, None, full, Logger::new(&trace), |_| {});
+            let costs: Vec<i32> = (0..5).map(|n| result.logger.get_full_cost(n)).collect();
+            assert_eq!(costs, vec![10, 2, 10, 2, 10]);
+            assert_eq!(result.logger.get_full_cost_range(5), 34);
+            assert_eq!(result.full.clipped_cost(), Some(3 * 990));
+            // The credit h is given is only capped when asked to be
+            let credit = result.full.get_cache_state()["h"].0;
+            assert_eq!(credit, if clip_credit { 10.0 } else { 1000.0 });
+        }
+        assert_eq!(
+            cache(1, TiebreakingPolicy::Lru, HitPolicy::Lru).clipped_cost(),
+            None
+        );
+    }
+
+    #[test]
+    fn recency_pressure_drains_stale_items_first() {
+        // a has the most credit, but b has been requested three times since a was last requested
+        let items = [item("a", 2, 1), item("b", 1, 1), item("c", 1, 1)];
+        for (mode, kept) in [(PressureMode::Uniform, "a"), (PressureMode::Recency, "b")] {
+            let mut cache =
+                cache(2, TiebreakingPolicy::Lru, HitPolicy::Lru).with_pressure_mode(mode);
+            for request in unit_trace(&items, "abbbc") {
+                cache.request(request);
+            }
+            assert_eq!(resident(&cache), vec![kept, "c"]);
+        }
+    }
+
+    #[test]
+    fn static_hit_policy_ignores_reuse() {
+        let items = [item("a", 1, 1), item("b", 1, 1), item("c", 1, 1)];
+        // a is reused heavily after b arrives, which only LRU takes into account
+        for (hit, kept) in [(HitPolicy::Lru, "a"), (HitPolicy::Static, "b")] {
+            let mut cache = cache(2, TiebreakingPolicy::Lru, hit);
+            for request in unit_trace(&items, "abaaac") {
+                cache.request(request);
+            }
+            assert_eq!(resident(&cache), vec![kept, "c"]);
+        }
+    }
+
+    #[test]
+    fn overridden_requests_charge_their_own_cost() {
+        let (a, b) = (item("a", 5, 1), item("b", 1, 1));
+        let trace: VecDeque<Request> = [
+            Request::new(&a, Some(2)),
+            Request::new(&b, None),
+            Request::new(&a, Some(7)),
+            Request::new(&b, None),
+            Request::new(&a, None),
+        ]
+        .into();
+        // A single line means every request misses
+        let lru = cache(1, TiebreakingPolicy::Lru, HitPolicy::Lru);
+        let result = Landlord::run(trace.clone(), 0, None, lru, Logger::new(&trace), |_| {});
+        let costs: Vec<i32> = (0..5).map(|n| result.logger.get_full_cost(n)).collect();
+        assert_eq!(costs, vec![2, 1, 7, 1, 5]);
+    }
+
+    #[test]
+    fn lrfu_moves_between_lfu_and_lru_with_its_decay() {
+        let items = [item("a", 1, 1), item("b", 1, 1), item("c", 1, 1)];
+        // When a comes back, c has been requested more often and b more recently
+        let kept = |hit: HitPolicy| {
+            let mut cache = cache(2, TiebreakingPolicy::Lru, hit);
+            for request in unit_trace(&items, "abcccba") {
+                cache.request(request);
+            }
+            resident(&cache)
+        };
+        assert_eq!(kept(HitPolicy::Lrfu(0.0)), vec!["a", "c"]);
+        assert_eq!(kept(HitPolicy::Lrfu(1.0)), vec!["a", "b"]);
+        assert_eq!(kept(HitPolicy::Lrfu(1.0)), kept(HitPolicy::Lru));
+    }
+
+    #[test]
+    fn ghost_counts_faults_a_larger_cache_would_hit() {
+        let items = [item("a", 1, 1), item("b", 1, 1), item("c", 1, 1)];
+        let ghost_hits = |labels: &str| {
+            let mut cache = cache(1, TiebreakingPolicy::Lru, HitPolicy::Lru).with_ghost(1);
+            for request in unit_trace(&items, labels) {
+                cache.request(request);
+            }
+            cache.ghost_hits()
+        };
+        // Each item is re-requested right after being evicted, so a two line cache hits on both
+        assert_eq!(ghost_hits("abab"), Some(2));
+        // By the time a comes back, the ghost only remembers evicting b
+        assert_eq!(ghost_hits("abca"), Some(0));
+        let plain = cache(1, TiebreakingPolicy::Lru, HitPolicy::Lru);
+        assert_eq!(plain.ghost_hits(), None);
+    }
+
+    #[test]
+    fn observer_sees_every_request_as_it_is_serviced() {
+        let items = [item("a", 2, 1), item("b", 3, 1), item("c", 1, 1)];
+        let trace = unit_trace(&items, "abcabcaab");
+        let lru = || cache(2, TiebreakingPolicy::Lru, HitPolicy::Lru);
+        let (mut full_hits, mut suff_hits, mut full_cost, mut seen) = (0, 0, 0, 0);
+        let mut last_ratio = 0.0;
+        let result = Landlord::run(
+            trace.clone(),
+            3,
+            Some(lru()),
+            lru(),
+            Logger::new(&trace),
+            |event| {
+                assert_eq!(event.index, seen);
+                seen += 1;
+                full_hits += u32::from(event.full_hit);
+                suff_hits += u32::from(event.suff_hit == Some(true));
+                full_cost += i64::from(event.full_cost);
+                // The suffix cache services nothing before the division point
+                assert_eq!(event.suff_hit.is_none(), event.index < 3);
+                last_ratio = event.full_hit_ratio;
+            },
+        );
+        assert_eq!(seen, trace.len());
+        assert_eq!(full_hits, result.full_hits);
+        assert_eq!(suff_hits, result.suff_hits);
+        assert_eq!(
+            full_cost,
+            result.logger.get_full_cost_range(trace.len() as u32)
+        );
+        assert_eq!(last_ratio, result.full_hit_ratio());
+    }
+
+    #[test]
+    fn items_as_large_as_the_cache_replace_each_other() {
+        let items = [item("a", 3, 4), item("b", 5, 4)];
+        let mut cache = cache(4, TiebreakingPolicy::Lru, HitPolicy::Lru);
+        for (i, request) in unit_trace(&items, "ababab").into_iter().enumerate() {
+            let result = cache.request(request);
+            let RequestResult::Fault(pressure) = result else {
+                panic!("Request {} hit in a cache that only holds one item", i);
+            };
+            // Each item is evicted at whatever credit it had left, which is never more than its
+            // cost per unit of size.
+            assert!((0.0..=5.0 / 4.0).contains(&pressure));
+            assert_eq!(i == 0, pressure == 0.0);
+            assert_eq!(resident(&cache), vec![request.get_label().as_str()]);
+        }
+    }
+
+    #[test]
+    fn negative_costs_lower_the_total_cost() {
+        // Costs the full cache pays on a trace with no suffix cache.
+        fn total(items: &[Item], labels: &str) -> i64 {
+            let trace = unit_trace(items, labels);
+            let lru = cache(1, TiebreakingPolicy::Lru, HitPolicy::Lru);
+            let result = Landlord::run(trace.clone(), 0, None, lru, Logger::new(&trace), |_| {});
+            result.logger.get_full_cost_range(trace.len() as u32)
+        }
+        let free = [item("a", 3, 1), item("p", 0, 1)];
+        let prefetched = [item("a", 3, 1), item("p", -2, 1)];
+        // Every request faults in a one item cache, so each p lowers the total by its benefit
+        assert_eq!(total(&free, "apap"), 6);
+        assert_eq!(total(&prefetched, "apap"), 2);
+        assert_eq!(total(&prefetched, "pp"), -2);
+        // The item is cached with zero credit rather than a negative one
+        let mut cache = cache(2, TiebreakingPolicy::Lru, HitPolicy::Lru);
+        cache.request(Request::new(&prefetched[1], None));
+        assert_eq!(cache.get_credits(), vec![(0.0, -2.0)]);
+    }
+
+    #[test]
+    fn second_hit_admission_keeps_one_hit_wonders_out() {
+        let items: Vec<Item> = "abcdef"
+            .chars()
+            .map(|n| item(&n.to_string(), 1, 1))
+            .collect();
+        let mut cache = cache(2, TiebreakingPolicy::Lru, HitPolicy::Lru)
+            .with_admission(AdmissionPolicy::SecondHit);
+        let hits: Vec<bool> = unit_trace(&items, "aabcdefa")
+            .into_iter()
+            .map(|request| {
+                let hit = matches!(cache.request(request), RequestResult::Hit);
+                // Nothing but a, the only item requested twice, ever becomes resident
+                assert!(resident(&cache).iter().all(|n| n == "a"));
+                hit
+            })
+            .collect();
+        assert_eq!(
+            hits,
+            vec![false, false, false, false, false, false, false, true]
+        );
+        assert_eq!(resident(&cache), vec!["a"]);
+    }
+
+    #[test]
+    fn min_residency_keeps_a_fresh_item_through_the_next_eviction() {
+        let items = [item("x", 10, 1), item("y", 1, 1), item("z", 10, 1)];
+        let trace = unit_trace(&items, "xyz");
+        let run = |min_residency: Option<u32>| {
+            let mut cache = cache(2, TiebreakingPolicy::Lru, HitPolicy::Lru);
+            if let Some(min_residency) = min_residency {
+                cache = cache.with_min_residency(min_residency);
+            }
+            for request in trace.iter() {
+                cache.request(*request);
+            }
+            (resident(&cache), cache.residency_exemptions())
+        };
+        // Admitted on the request before z, the cheap y has the least credit and is evicted for it
+        assert_eq!(run(None), (vec!["x".to_string(), "z".to_string()], None));
+        assert_eq!(
+            run(Some(0)),
+            (vec!["x".to_string(), "z".to_string()], Some(0))
+        );
+        // A residency of one request exempts y, so the costly x goes in its place
+        assert_eq!(
+            run(Some(1)),
+            (vec!["y".to_string(), "z".to_string()], Some(1))
+        );
+    }
+
+    #[test]
+    fn probabilistic_admission_mostly_turns_away_rare_items() {
+        // Two popular items are requested between 200 items that are each requested only once
+        let rare: Vec<Item> = (0..200)
+            .map(|n| item(&format!("rare{}", n), 1, 1))
+            .collect();
+        let popular = [item("p0", 1, 1), item("p1", 1, 1)];
+        let trace: Vec<&Item> = rare
+            .iter()
+            .enumerate()
+            .flat_map(|(n, item)| [item, &popular[n % 2]])
+            .collect();
+        for seed in 0..4 {
+            let mut cache = cache(4, TiebreakingPolicy::Lru, HitPolicy::Lru)
+                .with_seed(seed)
+                .with_admission(AdmissionPolicy::Probabilistic);
+            let mut admitted = 0;
+            let mut popular_hits = 0;
+            for item in trace.iter() {
+                let hit = matches!(cache.request(Request::new(item, None)), RequestResult::Hit);
+                if item.get_label().starts_with("rare") {
+                    admitted += u32::from(resident(&cache).contains(item.get_label()));
+                } else {
+                    popular_hits += u32::from(hit);
+                }
+            }
+            assert!(
+                admitted <= 10,
+                "Seed {} admitted {} rare items",
+                seed,
+                admitted
+            );
+            assert!(cache.deferred_admissions().unwrap() >= 190);
+            // The popular items get in after a few requests and stay, since nothing displaces them
+            assert!(
+                popular_hits >= 180,
+                "Seed {} hit {} times",
+                seed,
+                popular_hits
+            );
+        }
+    }
+
+    #[test]
+    fn expected_value_keeps_a_costly_frequent_item_over_a_cheap_rare_one() {
+        // Both items are left with the same normalized credit under FIFO, and the cheap one was
+        // requested last, so LRU tiebreaking would evict the costly one instead.
+        let (costly, cheap, new) = (
+            item("costly", 10, 2),
+            item("cheap", 5, 1),
+            item("new", 5, 1),
+        );
+        let trace = [&costly, &costly, &costly, &cheap, &new];
+        for (tiebreak, kept) in [
+            (TiebreakingPolicy::ExpectedValue, "costly"),
+            (TiebreakingPolicy::Lru, "cheap"),
+        ] {
+            let mut cache = cache(3, tiebreak, HitPolicy::Fifo);
+            for item in trace {
+                cache.request(Request::new(item, None));
+            }
+            assert_eq!(resident(&cache), vec![kept.to_string(), "new".to_string()]);
+        }
+    }
+
+    #[test]
+    fn expected_value_weighs_requests_by_the_cost_they_were_charged() {
+        // By catalog cost, a has the greater expected savings, but its later requests were
+        // overridden to cost less than b's were.
+        let (a, b, new) = (item("a", 5, 1), item("b", 5, 1), item("new", 5, 1));
+        let mut cache = cache(2, TiebreakingPolicy::ExpectedValue, HitPolicy::Fifo);
+        for request in [
+            Request::new(&b, None),
+            Request::new(&b, Some(20)),
+            Request::new(&a, None),
+            Request::new(&a, Some(1)),
+            Request::new(&a, Some(1)),
+            Request::new(&a, Some(1)),
+            Request::new(&new, None),
+        ] {
+            cache.request(request);
+        }
+        assert_eq!(resident(&cache), vec!["b".to_string(), "new".to_string()]);
+    }
+
+    // A catalog of items with costs from 1 to max_cost and sizes of 1, 2 or 4, and a trace of
+    // requests for them drawn from a seeded generator. With power of two sizes, every credit and
+    // pressure is exactly representable, so rounding cannot break a tie one way in one cache and
+    // the other way in another.
+    fn random_workload(seed: u64, max_cost: i32, len: usize) -> (Vec<Item>, Vec<usize>) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let items = (0..12)
+            .map(|n| {
+                let cost = rng.random_range(1..=max_cost);
+                item(&format!("item{}", n), cost, 1 << rng.random_range(0..3))
+            })
+            .collect();
+        let trace = (0..len).map(|_| rng.random_range(0..12)).collect();
+        (items, trace)
+    }
+
+    // Services a trace, returning whether each request hit and what it evicted. A queued cache is
+    // also checked to never let its offset or its heap grow past their limits.
+    fn decisions<'a>(
+        cache: &mut Landlord<'a>,
+        items: &'a [Item],
+        trace: &[usize],
+    ) -> Vec<(bool, Vec<&'a str>)> {
+        let mut decisions = Vec::new();
+        for index in trace {
+            let request = Request::new(&items[*index], None);
+            let hit = matches!(cache.request(request), RequestResult::Hit);
+            let evicted = cache.take_evictions();
+            decisions.push((
+                hit,
+                evicted.iter().map(|n| n.0.get_label().as_str()).collect(),
+            ));
+            if let Some(queue) = &cache.cache.queue {
+                assert!(queue.offset <= queue.offset_limit);
+                assert!(queue.heap.len() <= QUEUE_SLACK * cache.cache.contents.len() + 1);
+            }
+            // Credit never leaves the range from zero to the item's cost by more than rounding
+            for (credit, cost) in cache.get_credits() {
+                let slack = cost * 1e-4;
+                assert!(credit.is_finite() && credit >= -slack && credit <= cost + slack);
+            }
+        }
+        decisions
+    }
+
+    fn queue_offset(cache: &Landlord) -> f32 {
+        cache
+            .cache
+            .queue
+            .as_ref()
+            .expect("Cache has no credit queue")
+            .offset
+    }
+
+    #[test]
+    fn credit_queue_evicts_the_same_items_as_the_scan() {
+        for seed in 0..8 {
+            let (items, trace) = random_workload(seed, 20, 2000);
+            let mut scan = cache(8, TiebreakingPolicy::Lru, HitPolicy::Lru);
+            let mut queued = cache(8, TiebreakingPolicy::Lru, HitPolicy::Lru).with_credit_queue();
+            let expected = decisions(&mut scan, &items, &trace);
+            let actual = decisions(&mut queued, &items, &trace);
+            assert_eq!(actual, expected, "Seed {} evicted differently", seed);
+        }
+    }
+
+    #[test]
+    fn credit_queue_evicts_the_same_items_across_an_offset_rebuild() {
+        let (items, trace) = random_workload(7, 20, 400);
+        let mut scan = cache(8, TiebreakingPolicy::Lru, HitPolicy::Lru);
+        let mut queued = cache(8, TiebreakingPolicy::Lru, HitPolicy::Lru).with_credit_queue();
+        let (warmup, rest) = trace.split_at(50);
+        assert_eq!(
+            decisions(&mut queued, &items, warmup),
+            decisions(&mut scan, &items, warmup)
+        );
+        // Moving the queue to just below its offset limit without changing any credit, so the
+        // next eviction has to subtract the offset back out of every key
+        queued.rebuild_queue();
+        let shift = QUEUE_OFFSET_LIMIT - 1e-3;
+        for key in queued.cache.contents.values_mut() {
+            *key += shift;
+        }
+        let queue = queued.cache.queue.as_mut().unwrap();
+        queue.offset = shift;
+        queue.heap = queued
+            .cache
+            .contents
+            .iter()
+            .map(|(item, key)| Reverse((*key, *item)))
+            .collect();
+        let mut rebuilt = false;
+        for index in rest {
+            let before = queue_offset(&queued);
+            let expected = decisions(&mut scan, &items, &[*index]);
+            assert_eq!(decisions(&mut queued, &items, &[*index]), expected);
+            rebuilt |= before >= shift && queue_offset(&queued) < 1.0;
+        }
+        assert!(rebuilt, "The queue's offset never crossed its limit");
+    }
+
+    #[test]
+    fn credit_queue_renormalizes_its_offset_over_a_long_trace() {
+        // Costs this large raise the offset past its limit every few evictions, so the queue is
+        // renormalized many times over the trace without its credits drifting from the scan's.
+        let (items, trace) = random_workload(42, 1000, 20000);
+        let mut scan = cache(8, TiebreakingPolicy::Lru, HitPolicy::Half);
+        let mut queued = cache(8, TiebreakingPolicy::Lru, HitPolicy::Half).with_credit_queue();
+        let mut peak: f32 = 0.0;
+        let mut rebuilds = 0;
+        for index in trace.iter() {
+            let before = queue_offset(&queued);
+            let expected = decisions(&mut scan, &items, &[*index]);
+            let actual = decisions(&mut queued, &items, &[*index]);
+            assert_eq!(actual, expected);
+            peak = peak.max(before);
+            rebuilds += u32::from(queue_offset(&queued) < before);
+        }
+        assert!(
+            peak > QUEUE_OFFSET_LIMIT / 2.0,
+            "The offset only reached {}",
+            peak
+        );
+        assert!(rebuilds > 100, "Only {} rebuilds", rebuilds);
+    }
+
+    #[test]
+    fn credit_queue_renormalizes_without_changing_its_decisions() {
+        // A reference queue whose offset is never subtracted back out has to make exactly the same
+        // decisions for as long as its keys keep enough precision, which a short prefix does even
+        // though costs this large push the offset past its limit many times over.
+        let (items, trace) = random_workload(3, 1000, 100_000);
+        let mut aged = cache(8, TiebreakingPolicy::Lru, HitPolicy::Half).with_credit_queue();
+        let mut reference = cache(8, TiebreakingPolicy::Lru, HitPolicy::Half).with_credit_queue();
+        reference.cache.queue.as_mut().unwrap().offset_limit = f32::INFINITY;
+        let (prefix, rest) = trace.split_at(1000);
+        let mut renormalized = 0;
+        for index in prefix {
+            let before = queue_offset(&aged);
+            let expected = decisions(&mut reference, &items, &[*index]);
+            assert_eq!(decisions(&mut aged, &items, &[*index]), expected);
+            renormalized += u32::from(queue_offset(&aged) < before);
+        }
+        assert!(renormalized > 3, "Only {} renormalizations", renormalized);
+        assert!(queue_offset(&reference) > 4.0 * QUEUE_OFFSET_LIMIT);
+        // Over the rest of the trace every credit stays finite and within its item's cost
+        decisions(&mut aged, &items, rest);
+    }
+
+    #[test]
+    fn greedy_dual_coincides_with_landlord_on_unit_sizes() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let items: Vec<Item> = (0..12)
+            .map(|n| item(&format!("item{}", n), rng.random_range(1..=20), 1))
+            .collect();
+        let trace: Vec<usize> = (0..500).map(|_| rng.random_range(0..12)).collect();
+        let mut landlord = cache(5, TiebreakingPolicy::Lru, HitPolicy::Lru);
+        let mut greedy_dual = cache(5, TiebreakingPolicy::Lru, HitPolicy::Lru)
+            .with_eviction_policy(EvictionPolicy::GreedyDual);
+        for index in trace.iter() {
+            let expected = decisions(&mut landlord, &items, &[*index]);
+            let actual = decisions(&mut greedy_dual, &items, &[*index]);
+            assert_eq!(actual, expected);
+            assert_eq!(greedy_dual.get_cache_state(), landlord.get_cache_state());
+        }
+    }
+
+    #[test]
+    fn greedy_dual_ignores_size_when_evicting() {
+        // a has the lower credit per unit of size but the higher credit outright
+        let items = [item("a", 4, 2), item("b", 3, 1), item("c", 10, 1)];
+        let evicted = |eviction: EvictionPolicy| -> Vec<String> {
+            let mut cache =
+                cache(3, TiebreakingPolicy::Lru, HitPolicy::Lru).with_eviction_policy(eviction);
+            for item in items.iter() {
+                cache.request(Request::new(item, None));
+            }
+            cache
+                .take_evictions()
+                .iter()
+                .map(|n| n.0.get_label().clone())
+                .collect()
+        };
+        assert_eq!(evicted(EvictionPolicy::Landlord), vec!["a"]);
+        assert_eq!(evicted(EvictionPolicy::GreedyDual), vec!["b"]);
+    }
 }
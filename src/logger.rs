@@ -1,18 +1,29 @@
-use crate::landlord::{Landlord, RequestFullOrSuffix};
+use crate::analysis::{
+    pearson, AccessEntropy, BaselineCost, CatalogHistograms, CheApproximation, MissRatioCurve,
+    ReuseDistance, ZipfFit,
+};
+use crate::landlord::{
+    CacheDecision, EvictionReason, Landlord, Request, RequestEvent, RequestFullOrSuffix,
+};
 use crate::Item;
+use schemars::JsonSchema;
 use serde::Serialize;
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Struct that stores the individual suffix competitive ratio of our items.
-#[derive(Debug)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct IndScr {
     // We store the labels of items instead of references to the items for ease of deserialization.
-    full_costs: BTreeMap<String, VecDeque<u32>>,
-    suff_costs: BTreeMap<String, VecDeque<u32>>,
+    full_costs: BTreeMap<String, VecDeque<i32>>,
+    suff_costs: BTreeMap<String, VecDeque<i32>>,
 }
 
 impl IndScr {
-    fn new(trace: &VecDeque<&Item>) -> Self {
+    fn new(trace: &VecDeque<Request>) -> Self {
         Self {
             full_costs: {
                 let mut map = BTreeMap::new();
@@ -36,61 +47,1143 @@ impl IndScr {
     }
 }
 
+// The costs one item's requests paid in each cache and how many times it was requested, kept as
+// running totals. For thrash detection, it also holds the index of the request on which the full
+// cache last evicted the item, until the item is requested again, and how many times the item was
+// requested again within the thrash window of being evicted.
+#[derive(Debug, Default, Clone, Copy)]
+struct ItemTotals {
+    requests: u32,
+    full_cost: i64,
+    suff_cost: i64,
+    evicted_at: Option<u32>,
+    thrashed: u32,
+}
+
+// Aggregates over the whole trace, kept up to date as each request is logged. Unlike the
+// per-request series, these take the same space however long the trace is, so they are all that
+// is left to summarize a run when the series are not kept. The suffix counts only include requests
+// from the division point on, while its cost and pressure cover the whole trace since the suffix
+// cache is logged as idle before the division point.
+#[derive(Debug, Default)]
+struct RunningTotals {
+    full_requests: u32,
+    full_hits: u32,
+    full_cost: i64,
+    full_max_pres: f32,
+    full_zero_pres: u32,
+    full_no_cache_cost: i64,
+    full_pres_logged: u32,
+    suff_costs_logged: u32,
+    suff_pres_logged: u32,
+    suff_requests: u32,
+    suff_hits: u32,
+    suff_cost: i64,
+    suff_max_pres: f32,
+    suff_zero_pres: u32,
+    suff_no_cache_cost: i64,
+    // Whether the suffix cache paid any cost or saw any pressure before the division point
+    suff_prefix_busy: bool,
+    items: BTreeMap<String, ItemTotals>,
+}
+
+/// Hit, fault and cost totals for all of the items belonging to one class. The suffix totals are
+/// left out when only the full cache was simulated.
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct ClassStats {
+    full_hits: u32,
+    full_faults: u32,
+    full_cost: i64,
+    full_hit_ratio: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_hits: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_faults: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_cost: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_hit_ratio: Option<f32>,
+}
+
+impl ClassStats {
+    // Fills in the hit ratios from the hit and fault counts once logging is finished. A class the
+    // suffix cache never serviced still gets zero suffix totals unless there was no suffix cache.
+    fn finalize(&mut self, full_only: bool) {
+        let full_total = self.full_hits + self.full_faults;
+        if full_total != 0 {
+            self.full_hit_ratio = self.full_hits as f32 / full_total as f32;
+        }
+        if full_only {
+            return;
+        }
+        let suff_hits = *self.suff_hits.get_or_insert(0);
+        let suff_total = suff_hits + *self.suff_faults.get_or_insert(0);
+        self.suff_cost.get_or_insert(0);
+        self.suff_hit_ratio = Some(if suff_total != 0 {
+            suff_hits as f32 / suff_total as f32
+        } else {
+            0.0
+        });
+    }
+}
+
+/// Summary of one fixed-size epoch of the trace, spanning requests `start` up to but excluding
+/// `end`. The suffix hit ratio only counts requests the suffix cache actually serviced. The suffix
+/// statistics and SCR are left out when only the full cache was simulated.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EpochStats {
+    start: u32,
+    end: u32,
+    full_hit_ratio: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_hit_ratio: Option<f32>,
+    full_cost: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_cost: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scr: Option<f32>,
+    full_max_pres: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_max_pres: Option<f32>,
+}
+
+impl EpochStats {
+    fn new(logger: &Logger, start: usize, end: usize) -> Self {
+        let full_hits = logger.full_hits.range(start..end).filter(|h| **h).count();
+        let full_cost = logger
+            .full_cost
+            .range(start..end)
+            .map(|n| i64::from(*n))
+            .sum::<i64>();
+        let full_hit_ratio = if start == end {
+            0.0
+        } else {
+            full_hits as f32 / (end - start) as f32
+        };
+        let full_max_pres = logger
+            .full_pres
+            .range(start..end)
+            .fold(0.0, |a: f32, b| a.max(*b));
+        if logger.full_only {
+            return Self {
+                start: start as u32,
+                end: end as u32,
+                full_hit_ratio,
+                suff_hit_ratio: None,
+                full_cost,
+                suff_cost: None,
+                scr: None,
+                full_max_pres,
+                suff_max_pres: None,
+            };
+        }
+        let suff_start = start.max(logger.suffix_start as usize).min(end);
+        let suff_hits = logger
+            .suff_hits
+            .range(suff_start..end)
+            .filter(|h| **h)
+            .count();
+        let suff_cost = logger
+            .suff_cost
+            .range(start..end)
+            .map(|n| i64::from(*n))
+            .sum::<i64>();
+        Self {
+            start: start as u32,
+            end: end as u32,
+            full_hit_ratio,
+            suff_hit_ratio: Some(if suff_start == end {
+                0.0
+            } else {
+                suff_hits as f32 / (end - suff_start) as f32
+            }),
+            full_cost,
+            suff_cost: Some(suff_cost),
+            scr: Some(logger.scr(suff_cost, full_cost)),
+            full_max_pres,
+            suff_max_pres: Some(
+                logger
+                    .suff_pres
+                    .range(start..end)
+                    .fold(0.0, |a, b| a.max(*b)),
+            ),
+        }
+    }
+}
+
+/// What the time buckets are measured in. Traces whose requests all carry a timestamp are bucketed
+/// by arrival time in seconds, while any other trace falls back to buckets of consecutive requests.
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BucketUnit {
+    Seconds,
+    Requests,
+}
+
+/// The requests that arrived in one time bucket and what they cost, along with the rates per unit
+/// of time. Start is when the bucket begins, relative to the first request. Buckets no request
+/// arrived in are kept so that the rates form an unbroken series.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TimeBucket {
+    start: f64,
+    requests: u32,
+    request_rate: f64,
+    full_cost: i64,
+    full_cost_rate: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_cost: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_cost_rate: Option<f64>,
+}
+
+/// Request and cost rates over consecutive buckets of the trace, each `duration` units long.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TimeBuckets {
+    unit: BucketUnit,
+    duration: f64,
+    buckets: Vec<TimeBucket>,
+}
+
+impl TimeBuckets {
+    fn new(logger: &Logger, duration: f64) -> Self {
+        let len = logger.full_cost.len();
+        // Only the requests that were serviced are bucketed, which is fewer than the whole trace
+        // if the cost budget ran out
+        let timestamps = &logger.timestamps[..len.min(logger.timestamps.len())];
+        let (unit, duration, indices): (_, _, Vec<usize>) =
+            if timestamps.len() == len && timestamps.iter().all(Option::is_some) {
+                let first = timestamps
+                    .iter()
+                    .flatten()
+                    .fold(f64::INFINITY, |a, b| a.min(*b));
+                let indices = timestamps
+                    .iter()
+                    .flatten()
+                    .map(|n| ((n - first) / duration).floor() as usize)
+                    .collect();
+                (BucketUnit::Seconds, duration, indices)
+            } else {
+                // Without timestamps each request takes one unit of time, so buckets are whole
+                // numbers of requests
+                let length = duration.round().max(1.0);
+                let indices = (0..len).map(|n| n / length as usize).collect();
+                (BucketUnit::Requests, length, indices)
+            };
+        let count = indices.iter().max().map_or(0, |n| n + 1);
+        let mut buckets: Vec<TimeBucket> = (0..count)
+            .map(|n| TimeBucket {
+                start: n as f64 * duration,
+                requests: 0,
+                request_rate: 0.0,
+                full_cost: 0,
+                full_cost_rate: 0.0,
+                suff_cost: if logger.full_only { None } else { Some(0) },
+                suff_cost_rate: None,
+            })
+            .collect();
+        for (index, bucket) in indices.into_iter().enumerate() {
+            let bucket = &mut buckets[bucket];
+            bucket.requests += 1;
+            bucket.full_cost += i64::from(logger.full_cost[index]);
+            if let Some(suff_cost) = bucket.suff_cost.as_mut() {
+                *suff_cost += i64::from(logger.suff_cost[index]);
+            }
+        }
+        for bucket in buckets.iter_mut() {
+            bucket.request_rate = bucket.requests as f64 / duration;
+            bucket.full_cost_rate = bucket.full_cost as f64 / duration;
+            bucket.suff_cost_rate = bucket.suff_cost.map(|n| n as f64 / duration);
+        }
+        Self {
+            unit,
+            duration,
+            buckets,
+        }
+    }
+}
+
+/// How much the full cache paid to fetch one item over the whole trace.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ItemFetchStats {
+    requests: u32,
+    fetches: u32,
+    total_cost: i64,
+    // The average cost paid each time the item was fetched.
+    cost_per_fetch: f32,
+    // The average cost paid per request for the item, i.e. its total cost spread over every time
+    // it was requested.
+    cost_per_request: f32,
+}
+
+/// Fetch costs amortized over the distinct items of the trace. The number of fetches of an item is
+/// its fetch amplification: an item that never had to be refetched after its first miss has one.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AmortizedCost {
+    // The full cache's total cost divided by the number of distinct items requested.
+    cost_per_item: f32,
+    items: BTreeMap<String, ItemFetchStats>,
+}
+
+impl AmortizedCost {
+    fn new(logger: &Logger) -> Self {
+        let mut items = BTreeMap::new();
+        for (label, totals) in logger.totals.items.iter() {
+            let fetches = logger.full_fetches.get(label).copied().unwrap_or(0);
+            let total_cost = totals.full_cost;
+            items.insert(
+                label.to_string(),
+                ItemFetchStats {
+                    requests: totals.requests,
+                    fetches,
+                    total_cost,
+                    cost_per_fetch: if fetches == 0 {
+                        0.0
+                    } else {
+                        total_cost as f32 / fetches as f32
+                    },
+                    cost_per_request: if totals.requests == 0 {
+                        0.0
+                    } else {
+                        total_cost as f32 / totals.requests as f32
+                    },
+                },
+            );
+        }
+        Self {
+            cost_per_item: if items.is_empty() {
+                0.0
+            } else {
+                logger.totals.full_cost as f32 / items.len() as f32
+            },
+            items,
+        }
+    }
+}
+
+/// The spread of the gaps, in requests, between consecutive misses on one item in the full cache.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MissGaps {
+    min: u32,
+    mean: f32,
+    max: u32,
+}
+
+impl MissGaps {
+    // Collects the gap statistics of every item that missed at least twice. Items that missed
+    // fewer times have no gaps and are left out.
+    fn new(logger: &Logger) -> BTreeMap<String, Self> {
+        let mut gaps = BTreeMap::new();
+        for (label, misses) in logger.full_misses.iter() {
+            let item_gaps: Vec<u32> = misses.windows(2).map(|n| n[1] - n[0]).collect();
+            if item_gaps.is_empty() {
+                continue;
+            }
+            gaps.insert(
+                label.to_string(),
+                Self {
+                    min: *item_gaps.iter().min().unwrap(),
+                    mean: item_gaps.iter().sum::<u32>() as f32 / item_gaps.len() as f32,
+                    max: *item_gaps.iter().max().unwrap(),
+                },
+            );
+        }
+        gaps
+    }
+}
+
+// How soon after being evicted an item has to be requested again, in requests, and how many times
+// that has to happen for the item to count as thrashing, unless we are told otherwise.
+pub const DEFAULT_THRASH_WINDOW: u32 = 16;
+pub const DEFAULT_THRASH_THRESHOLD: u32 = 3;
+
+// How many thrashing items are listed in the output.
+const THRASHING_TOP_ITEMS: usize = 10;
+
+/// Items the full cache kept evicting only to have them requested again soon after, which suggests
+/// that the cache is too small for the trace or that its policy is a poor fit for it. Only items
+/// that were requested again within the window of an eviction at least threshold times are
+/// listed, worst first.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Thrashing {
+    window: u32,
+    threshold: u32,
+    items: Vec<ThrashedItem>,
+}
+
+/// An item the full cache thrashed on and how many times it was requested again within the window
+/// of being evicted.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ThrashedItem {
+    label: String,
+    times: u32,
+}
+
+impl Thrashing {
+    // Lists every item the full cache missed on again within the window of evicting it at least
+    // threshold times, returning None if there are none. The times are counted as the run is
+    // logged, so this works whether or not the per-request series were kept.
+    fn new(logger: &Logger, window: u32, threshold: u32) -> Option<Self> {
+        let mut items: Vec<ThrashedItem> = logger
+            .totals
+            .items
+            .iter()
+            .filter(|(_, totals)| totals.thrashed >= threshold)
+            .map(|(label, totals)| ThrashedItem {
+                label: label.clone(),
+                times: totals.thrashed,
+            })
+            .collect();
+        if items.is_empty() {
+            return None;
+        }
+        items.sort_by(|a, b| b.times.cmp(&a.times).then(a.label.cmp(&b.label)));
+        items.truncate(THRASHING_TOP_ITEMS);
+        Some(Self {
+            window,
+            threshold,
+            items,
+        })
+    }
+}
+
+/// Headline totals for the whole trace. These are what the summary table printed after a run
+/// reports. The suffix totals and SCR are left out when only the full cache was simulated.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Summary {
+    full_cost: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_cost: Option<i64>,
+    full_hit_ratio: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_hit_ratio: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scr: Option<f32>,
+    full_max_pres: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_max_pres: Option<f32>,
+    // The total overflow penalty paid by each cache running with a soft size limit. These are
+    // reported separately and are not included in the costs above.
+    full_penalty: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_penalty: Option<f32>,
+    // The number and fraction of requests on which each cache evicted nothing, either because it
+    // hit or because it had room. The suffix only counts requests from the division point on.
+    full_zero_pres: u32,
+    full_zero_pres_ratio: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_zero_pres: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_zero_pres_ratio: Option<f32>,
+    // What servicing the requests without a cache would have cost, paying every request's cost in
+    // full, and how much and what percentage of that each cache saved. The suffix only counts
+    // requests from the division point on. The percentage is 0 when going without a cache is free.
+    no_cache_cost: i64,
+    full_savings: i64,
+    full_savings_percent: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_no_cache_cost: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_savings: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_savings_percent: Option<f32>,
+}
+
+impl Summary {
+    // A summary is built from the running totals rather than the per-request series, so it is the
+    // same whether or not the series were kept.
+    fn new(logger: &Logger) -> Self {
+        let totals = &logger.totals;
+        let ratio = |count: u32, requests: u32| {
+            if requests == 0 {
+                0.0
+            } else {
+                count as f32 / requests as f32
+            }
+        };
+        let percent = |savings: i64, no_cache_cost: i64| {
+            if no_cache_cost == 0 {
+                0.0
+            } else {
+                savings as f32 / no_cache_cost as f32 * 100.0
+            }
+        };
+        let suff = (!logger.full_only).then_some(totals);
+        let full_savings = totals.full_no_cache_cost - totals.full_cost;
+        let suff_savings = suff.map(|n| n.suff_no_cache_cost - n.suff_cost);
+        Self {
+            full_cost: totals.full_cost,
+            suff_cost: suff.map(|n| n.suff_cost),
+            full_hit_ratio: ratio(totals.full_hits, totals.full_requests),
+            suff_hit_ratio: suff.map(|n| ratio(n.suff_hits, n.suff_requests)),
+            scr: suff.map(|n| logger.scr(n.suff_cost, n.full_cost)),
+            full_max_pres: totals.full_max_pres,
+            suff_max_pres: suff.map(|n| n.suff_max_pres),
+            full_penalty: logger.full_penalty,
+            suff_penalty: (!logger.full_only).then_some(logger.suff_penalty),
+            full_zero_pres: totals.full_zero_pres,
+            full_zero_pres_ratio: ratio(totals.full_zero_pres, totals.full_requests),
+            suff_zero_pres: suff.map(|n| n.suff_zero_pres),
+            suff_zero_pres_ratio: suff.map(|n| ratio(n.suff_zero_pres, n.suff_requests)),
+            no_cache_cost: totals.full_no_cache_cost,
+            full_savings,
+            full_savings_percent: percent(full_savings, totals.full_no_cache_cost),
+            suff_no_cache_cost: suff.map(|n| n.suff_no_cache_cost),
+            suff_savings,
+            suff_savings_percent: suff
+                .zip(suff_savings)
+                .map(|(n, savings)| percent(savings, n.suff_no_cache_cost)),
+        }
+    }
+}
+/// Hit and request counts for the reads and writes each cache serviced.
+#[derive(Debug, Default)]
+struct ReadWriteCounts {
+    full_reads: u32,
+    full_read_hits: u32,
+    full_writes: u32,
+    full_write_hits: u32,
+    suff_reads: u32,
+    suff_read_hits: u32,
+    suff_writes: u32,
+    suff_write_hits: u32,
+}
+
+/// Hit ratios of the reads and writes in a trace that has writes, reported separately. The suffix
+/// ratios are left out when only the full cache was simulated.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReadWriteStats {
+    full_reads: u32,
+    full_writes: u32,
+    full_read_hit_ratio: f32,
+    full_write_hit_ratio: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_read_hit_ratio: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff_write_hit_ratio: Option<f32>,
+}
+
+impl ReadWriteStats {
+    // Only traces with at least one write get read and write statistics.
+    fn new(logger: &Logger) -> Option<Self> {
+        let counts = &logger.read_write;
+        if counts.full_writes == 0 {
+            return None;
+        }
+        let ratio = |hits: u32, total: u32| {
+            if total == 0 {
+                0.0
+            } else {
+                hits as f32 / total as f32
+            }
+        };
+        let suffix = !logger.full_only;
+        Some(Self {
+            full_reads: counts.full_reads,
+            full_writes: counts.full_writes,
+            full_read_hit_ratio: ratio(counts.full_read_hits, counts.full_reads),
+            full_write_hit_ratio: ratio(counts.full_write_hits, counts.full_writes),
+            suff_read_hit_ratio: suffix.then(|| ratio(counts.suff_read_hits, counts.suff_reads)),
+            suff_write_hit_ratio: suffix.then(|| ratio(counts.suff_write_hits, counts.suff_writes)),
+        })
+    }
+}
+
+/// The number of evictions each cache made for each reason. The suffix counts are left out when
+/// the suffix cache made no evictions, which is always the case when only the full cache was
+/// simulated.
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct EvictionCounts {
+    full: BTreeMap<String, u32>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    suff: BTreeMap<String, u32>,
+}
+
+/// The number of faults each cache had on items its ghost still remembered evicting.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct GhostHits {
+    full: u32,
+    suff: u32,
+}
+
+/// The number of faults each cache's victim cache caught, promoting the item back for a fraction
+/// of its cost.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct VictimHits {
+    full: u32,
+    suff: u32,
+}
+
+/// The number of times each cache passed over an item for eviction because it had not been
+/// resident for its minimum residency yet.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ResidencyExemptions {
+    full: u32,
+    suff: u32,
+}
+
+/// The Pearson correlation between each cache's per-request pressure and the running SCR after
+/// each request. The suffix correlation only covers requests from the division point on, since the
+/// suffix cache sees no pressure before it. A correlation is left out when either series is
+/// constant.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PressureCorrelation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    full: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff: Option<f64>,
+}
+
+impl PressureCorrelation {
+    pub fn new(logger: &Logger) -> Self {
+        let mut full_total = 0;
+        let mut suff_total = 0;
+        let running_scr: Vec<f64> = logger
+            .full_cost
+            .iter()
+            .zip(logger.suff_cost.iter())
+            .map(|(full, suff)| {
+                full_total += i64::from(*full);
+                suff_total += i64::from(*suff);
+                f64::from(logger.scr(suff_total, full_total))
+            })
+            .collect();
+        let series = |pres: &VecDeque<f32>, start: usize| {
+            pres.iter()
+                .skip(start)
+                .map(|n| f64::from(*n))
+                .collect::<Vec<f64>>()
+        };
+        let start = (logger.suffix_start as usize).min(running_scr.len());
+        Self {
+            full: pearson(&series(&logger.full_pres, 0), &running_scr),
+            suff: pearson(&series(&logger.suff_pres, start), &running_scr[start..]),
+        }
+    }
+}
+
+/// The number of requests each cache let bypass it because their item was over the max cacheable
+/// size.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Bypassed {
+    full: u32,
+    suff: u32,
+}
+
+/// A resident item's credit and how many cache lines of it are resident.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ResidentItem {
+    credit: f32,
+    size: u32,
+}
+
+/// How many times an item has been requested and the request it was last requested on, counted
+/// from one.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct AccessCounts {
+    accesses: u32,
+    last_access: u64,
+}
+
+/// The complete state of one cache at a snapshot: how many requests it has serviced, its resident
+/// items and the access counters of every item it has seen, resident or not.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CacheSnapshot {
+    clock: u64,
+    utilization: f32,
+    resident: BTreeMap<String, ResidentItem>,
+    accesses: BTreeMap<String, AccessCounts>,
+}
+
+impl CacheSnapshot {
+    fn new(cache: &Landlord) -> Self {
+        Self {
+            clock: cache.get_clock(),
+            utilization: cache.utilization(),
+            resident: cache
+                .get_cache_state()
+                .into_iter()
+                .map(|(label, (credit, size))| (label, ResidentItem { credit, size }))
+                .collect(),
+            accesses: cache
+                .get_access_counts()
+                .into_iter()
+                .map(|(label, (accesses, last_access))| {
+                    (
+                        label,
+                        AccessCounts {
+                            accesses,
+                            last_access,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+    fn retain(&mut self, focus: &BTreeSet<String>) {
+        self.resident.retain(|label, _| focus.contains(label));
+        self.accesses.retain(|label, _| focus.contains(label));
+    }
+}
+
+/// The state of both caches after exactly `index` requests of the trace were serviced. The suffix
+/// cache is left out when only the full cache was simulated, and is empty before the division.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Snapshot {
+    index: u32,
+    full: CacheSnapshot,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff: Option<CacheSnapshot>,
+}
+
+/// How many bins the credits of resident items are split into by the fraction of their cost they
+/// still hold.
+const CREDIT_BINS: usize = 10;
+
+/// How credit is spread over the items resident in one cache. The minimum, median and maximum are
+/// left out while the cache is empty. The histogram counts the resident items by the fraction of
+/// their cost they still hold in CREDIT_BINS equal bins from 0 to 1, so an item inserted at full
+/// credit starts in the last bin and moves down as pressure drains it. Credit above an item's cost,
+/// which a request that overrides the item's cost can grant, is counted in the last bin.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CreditDistribution {
+    resident: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    median: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<f32>,
+    histogram: Vec<u32>,
+}
+
+impl CreditDistribution {
+    fn new(cache: &Landlord) -> Self {
+        let credits = cache.get_credits();
+        let mut histogram = vec![0; CREDIT_BINS];
+        for (credit, cost) in credits.iter() {
+            let fraction = if *cost > 0.0 { credit / cost } else { 0.0 };
+            let bin = (fraction * CREDIT_BINS as f32).max(0.0) as usize;
+            histogram[bin.min(CREDIT_BINS - 1)] += 1;
+        }
+        let mut sorted: Vec<f32> = credits.iter().map(|(credit, _)| *credit).collect();
+        sorted.sort_by(f32::total_cmp);
+        let median = match sorted.len() {
+            0 => None,
+            n if n % 2 == 1 => Some(sorted[n / 2]),
+            n => Some((sorted[n / 2 - 1] + sorted[n / 2]) / 2.0),
+        };
+        Self {
+            resident: sorted.len() as u32,
+            min: sorted.first().copied(),
+            median,
+            max: sorted.last().copied(),
+            histogram,
+        }
+    }
+}
+
+/// The credit distribution of both caches after exactly `index` requests of the trace were
+/// serviced. The suffix cache is left out when only the full cache was simulated, and is empty
+/// before the division.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CreditSample {
+    index: u32,
+    full: CreditDistribution,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff: Option<CreditDistribution>,
+}
+
+/// The total cost each cache did not charge because it was over the cost clip.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ClippedCost {
+    full: i64,
+    suff: i64,
+}
+
+/// How a result was produced: the exact command line, the version of the simulator, when and where
+/// it ran, what it read and the seed its random policies used.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RunInfo {
+    args: Vec<String>,
+    version: String,
+    // Seconds since the Unix epoch at which the run started.
+    timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hostname: Option<String>,
+    input: String,
+    seed: u64,
+    // When a run is one of several repetitions, the seed every repetition was derived from and
+    // the seeds derived for each of them in order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    derived_seeds: Option<Vec<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    competitive_bound: Option<CompetitiveBound>,
+    // The number of requests whose label was an alias resolved to another item's label, when the
+    // input had an aliases table.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    canonicalized: Option<u32>,
+    // The Zipf distribution fitted to how often each item in the trace was requested, when asked
+    // for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zipf_fit: Option<ZipfFit>,
+    // The entropy of how the trace's requests are spread over its items, when asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entropy: Option<AccessEntropy>,
+}
+
+/// Landlord's proven competitive ratio k/(k-h+1) against an optimal offline cache. Here k is the
+/// size of the cache being measured, which is the suffix cache, and h is the size of the cache it
+/// is compared against, which is the full cache.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CompetitiveBound {
+    k: u32,
+    h: u32,
+    bound: f64,
+}
+
+impl CompetitiveBound {
+    // The bound only exists when the measured cache is at least as large as the one it is compared
+    // against, since otherwise the denominator is not positive.
+    pub fn new(k: u32, h: u32) -> Option<Self> {
+        if h > k {
+            return None;
+        }
+        let bound = k as f64 / (k as f64 - h as f64 + 1.0);
+        Some(Self { k, h, bound })
+    }
+}
+
+impl RunInfo {
+    pub fn new(input: &str, seed: u64) -> Self {
+        Self {
+            args: std::env::args().collect(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0),
+            hostname: std::env::var("HOSTNAME").ok().or_else(|| {
+                std::fs::read_to_string("/etc/hostname")
+                    .ok()
+                    .map(|name| name.trim().to_string())
+            }),
+            input: input.to_string(),
+            seed,
+            base_seed: None,
+            derived_seeds: None,
+            competitive_bound: None,
+            canonicalized: None,
+            zipf_fit: None,
+            entropy: None,
+        }
+    }
+    /// Records that this run is one of several repetitions whose seeds were derived from the base
+    /// seed.
+    pub fn with_derived_seeds(mut self, base_seed: u64, derived_seeds: Vec<u64>) -> Self {
+        self.base_seed = Some(base_seed);
+        self.derived_seeds = Some(derived_seeds);
+        self
+    }
+    /// Records how many requests were made under an alias, if the input had an aliases table.
+    pub fn with_canonicalized(mut self, canonicalized: Option<u32>) -> Self {
+        self.canonicalized = canonicalized;
+        self
+    }
+    /// Records the Zipf distribution fitted to the trace, if one was fitted.
+    pub fn with_zipf_fit(mut self, zipf_fit: Option<ZipfFit>) -> Self {
+        self.zipf_fit = zipf_fit;
+        self
+    }
+    /// Records the entropy of the trace's access distribution, if it was computed.
+    pub fn with_entropy(mut self, entropy: Option<AccessEntropy>) -> Self {
+        self.entropy = entropy;
+        self
+    }
+    /// Records Landlord's theoretical competitive bound for a measured cache of size `k` compared
+    /// against a cache of size `h`, if the bound exists for these sizes.
+    pub fn with_competitive_bound(mut self, k: u32, h: u32) -> Self {
+        self.competitive_bound = CompetitiveBound::new(k, h);
+        self
+    }
+}
+
+/// The number of faults on which each cache turned the item away instead of admitting it.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DeferredAdmissions {
+    full: u32,
+    suff: u32,
+}
+
 #[derive(Debug)]
 pub struct Logger {
-    full_cost: VecDeque<u32>,
-    suff_cost: VecDeque<u32>,
+    full_cost: VecDeque<i32>,
+    suff_cost: VecDeque<i32>,
+    full_hits: VecDeque<bool>,
+    suff_hits: VecDeque<bool>,
+    full_fetches: BTreeMap<String, u32>,
+    full_misses: BTreeMap<String, Vec<u32>>,
+    full_penalty: f32,
+    suff_penalty: f32,
+    evictions: EvictionCounts,
+    read_write: ReadWriteCounts,
     full_pres: VecDeque<f32>,
     suff_pres: VecDeque<f32>,
+    full_utilization: VecDeque<f32>,
+    suff_utilization: VecDeque<f32>,
     full_states: VecDeque<BTreeMap<String, (f32, u32)>>,
     suff_states: VecDeque<BTreeMap<String, (f32, u32)>>,
     ind_scr: IndScr,
+    classes: BTreeMap<String, ClassStats>,
+    suffix_start: u32,
+    epoch_length: Option<u32>,
+    time_bucket: Option<f64>,
+    timestamps: Vec<Option<f64>>,
+    emit_per_item_series: bool,
+    amortized_cost: bool,
+    cumulative: bool,
+    pressure_correlation: bool,
+    miss_gaps: bool,
+    thrash_detection: Option<(u32, u32)>,
+    scr_floor: i32,
+    full_only: bool,
+    cost_budget: Option<i64>,
+    full_total: i64,
+    budget_stop: Option<u32>,
+    totals: RunningTotals,
+    summary_only: bool,
+    ghost_hits: Option<GhostHits>,
+    victim_hits: Option<VictimHits>,
+    residency_exemptions: Option<ResidencyExemptions>,
+    bypassed: Option<Bypassed>,
+    clipped_cost: Option<ClippedCost>,
+    deferred_admissions: Option<DeferredAdmissions>,
+    run_info: Option<RunInfo>,
+    reuse_distance: Option<ReuseDistance>,
+    miss_ratio_curve: Option<MissRatioCurve>,
+    che: Option<CheApproximation>,
+    baseline: Option<BaselineCost>,
+    catalog: Option<CatalogHistograms>,
+    snapshot_at: BTreeSet<u32>,
+    snapshots: Vec<Snapshot>,
+    credit_sample: Option<u32>,
+    credit_distribution: Vec<CreditSample>,
+    focus: Option<BTreeSet<String>>,
+    focus_mask: Vec<bool>,
 }
 
 impl Logger {
     // Creates a new logger instance
-    pub fn new(trace: &VecDeque<&Item>) -> Self {
+    pub fn new(trace: &VecDeque<Request>) -> Self {
         Self {
             full_cost: VecDeque::new(),
             full_pres: VecDeque::new(),
             suff_cost: VecDeque::new(),
             suff_pres: VecDeque::new(),
+            full_utilization: VecDeque::new(),
+            suff_utilization: VecDeque::new(),
+            full_hits: VecDeque::new(),
+            suff_hits: VecDeque::new(),
+            full_fetches: BTreeMap::new(),
+            full_misses: BTreeMap::new(),
+            full_penalty: 0.0,
+            suff_penalty: 0.0,
+            evictions: EvictionCounts::default(),
+            read_write: ReadWriteCounts::default(),
             full_states: VecDeque::new(),
             suff_states: VecDeque::new(),
             ind_scr: IndScr::new(trace),
+            classes: BTreeMap::new(),
+            suffix_start: 0,
+            epoch_length: None,
+            time_bucket: None,
+            timestamps: Vec::new(),
+            emit_per_item_series: false,
+            amortized_cost: false,
+            cumulative: false,
+            pressure_correlation: false,
+            miss_gaps: false,
+            thrash_detection: None,
+            scr_floor: 0,
+            full_only: false,
+            cost_budget: None,
+            full_total: 0,
+            budget_stop: None,
+            totals: RunningTotals {
+                items: trace
+                    .iter()
+                    .map(|n| (n.get_label().clone(), ItemTotals::default()))
+                    .collect(),
+                ..Default::default()
+            },
+            summary_only: false,
+            ghost_hits: None,
+            victim_hits: None,
+            residency_exemptions: None,
+            bypassed: None,
+            clipped_cost: None,
+            deferred_admissions: None,
+            run_info: None,
+            reuse_distance: None,
+            miss_ratio_curve: None,
+            che: None,
+            baseline: None,
+            catalog: None,
+            snapshot_at: BTreeSet::new(),
+            snapshots: Vec::new(),
+            credit_sample: None,
+            credit_distribution: Vec::new(),
+            focus: None,
+            focus_mask: Vec::new(),
         }
     }
     /// Gets the cost that the full cache paid at a particular point in the trace.
-    pub fn get_full_cost(&self, index: u32) -> u32 {
+    pub fn get_full_cost(&self, index: u32) -> i32 {
         *self
             .full_cost
             .get(index as usize)
             .expect("Full cost index out of bounds")
     }
     /// Gets the cost that the full cache paid from the start of the trace to the specified index.
-    pub fn get_full_cost_range(&self, index: u32) -> u32 {
-        self.full_cost.range(0..index as usize).sum::<u32>()
+    pub fn get_full_cost_range(&self, index: u32) -> i64 {
+        self.full_cost
+            .range(0..index as usize)
+            .map(|n| i64::from(*n))
+            .sum::<i64>()
     }
     /// Gets the cost that the suffix cache paid from the start of the trace to the specified index.
-    pub fn get_suff_cost_range(&self, index: u32) -> u32 {
-        self.suff_cost.range(0..index as usize).sum::<u32>()
+    pub fn get_suff_cost_range(&self, index: u32) -> i64 {
+        self.suff_cost
+            .range(0..index as usize)
+            .map(|n| i64::from(*n))
+            .sum::<i64>()
     }
     /// Gets the cost that the suffix cache paid at a particular point in the trace.
-    pub fn get_suff_cost(&self, index: u32) -> u32 {
+    pub fn get_suff_cost(&self, index: u32) -> i32 {
         *self
             .suff_cost
             .get(index as usize)
             .expect("Suffix index out of bounds")
     }
+    /// Checks that what was logged adds up for a trace of the given length: both caches logged a
+    /// cost and a pressure for every request serviced, the suffix cache serviced exactly the
+    /// requests from the division point on and was idle before it, and the per-request series
+    /// agree with the running totals. Returns an error describing the first mismatch.
+    pub fn reconcile(&self, trace_len: usize) -> Result<(), String> {
+        let totals = &self.totals;
+        let serviced = self.budget_stop.map_or(trace_len, |stop| stop as usize);
+        let suffix_serviced = serviced.saturating_sub(self.suffix_start as usize);
+        let mut counts = vec![
+            ("full cache costs", totals.full_requests as usize, serviced),
+            (
+                "full cache pressures",
+                totals.full_pres_logged as usize,
+                serviced,
+            ),
+        ];
+        if !self.full_only {
+            counts.extend([
+                (
+                    "suffix cache costs",
+                    totals.suff_costs_logged as usize,
+                    serviced,
+                ),
+                (
+                    "suffix cache pressures",
+                    totals.suff_pres_logged as usize,
+                    serviced,
+                ),
+                (
+                    "suffix cache requests",
+                    totals.suff_requests as usize,
+                    suffix_serviced,
+                ),
+            ]);
+        }
+        if !self.summary_only {
+            counts.extend([
+                ("full cost series entries", self.full_cost.len(), serviced),
+                (
+                    "full pressure series entries",
+                    self.full_pres.len(),
+                    serviced,
+                ),
+            ]);
+            if !self.full_only {
+                counts.extend([
+                    ("suffix cost series entries", self.suff_cost.len(), serviced),
+                    (
+                        "suffix pressure series entries",
+                        self.suff_pres.len(),
+                        serviced,
+                    ),
+                ]);
+            }
+        }
+        for (name, logged, expected) in counts {
+            if logged != expected {
+                return Err(format!(
+                    "Logged {} {} but expected {} for {} of {} requests serviced with the suffix \
+                     starting at {}",
+                    logged, name, expected, serviced, trace_len, self.suffix_start
+                ));
+            }
+        }
+        if !self.full_only && totals.suff_prefix_busy {
+            return Err(format!(
+                "The suffix cache paid cost or saw pressure before the division point at {}",
+                self.suffix_start
+            ));
+        }
+        if !self.summary_only {
+            let sum = |series: &VecDeque<i32>| series.iter().map(|n| i64::from(*n)).sum::<i64>();
+            let mut costs = vec![("full", sum(&self.full_cost), totals.full_cost)];
+            if !self.full_only {
+                costs.push(("suffix", sum(&self.suff_cost), totals.suff_cost));
+            }
+            for (name, series, total) in costs {
+                if series != total {
+                    return Err(format!(
+                        "The {} cost series adds up to {} but the {} cache paid {}",
+                        name, series, name, total
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Checks that the suffix cache paid no cost and had no pressure on any request before the
+    /// given index, which is how the prefix is logged.
+    pub fn is_suffix_idle_before(&self, index: u32) -> bool {
+        if self.summary_only {
+            return !self.totals.suff_prefix_busy;
+        }
+        let end = (index as usize).min(self.suff_cost.len());
+        self.suff_cost.range(0..end).all(|n| *n == 0)
+            && self.suff_pres.range(0..end).all(|n| *n == 0.0)
+    }
+    // Divides the suffix cost by the full cost under our SCR floor.
+    fn scr(&self, suff_cost: i64, full_cost: i64) -> f32 {
+        floored_scr(suff_cost, full_cost, self.scr_floor)
+    }
     /// Gets the suffix competitive ratio at a particular index.
     pub fn get_scr(&self, index: u32) -> f32 {
         if self.full_cost.is_empty() {
             0.0
         } else {
             let proper_index = index as usize;
-            let suff_cost_sum = self.suff_cost.range(0..proper_index).sum::<u32>();
-            let full_cost_sum = self.full_cost.range(0..proper_index).sum::<u32>();
-            suff_cost_sum as f32 / full_cost_sum as f32
+            let suff_cost_sum = self
+                .suff_cost
+                .range(0..proper_index)
+                .map(|n| i64::from(*n))
+                .sum::<i64>();
+            let full_cost_sum = self
+                .full_cost
+                .range(0..proper_index)
+                .map(|n| i64::from(*n))
+                .sum::<i64>();
+            self.scr(suff_cost_sum, full_cost_sum)
         }
     }
     /// Gets the individual suffix competitive ratio for the specified item at a particular index.
@@ -101,120 +1194,2576 @@ impl Logger {
             .get(item.get_label())
             .expect("Could not find item in full costs for indindividual SCR logging")
             .range(0..index as usize)
-            .sum::<u32>();
+            .map(|n| i64::from(*n))
+            .sum::<i64>();
         let item_full_costs = self
             .ind_scr
             .full_costs
             .get(item.get_label())
             .expect("Could not find item in full costs for indindividual SCR logging")
             .range(0..index as usize)
-            .sum::<u32>();
-        if item_full_costs == 0 {
+            .map(|n| i64::from(*n))
+            .sum::<i64>();
+        if item_full_costs <= 0 {
             return 0.0;
         }
         item_suff_costs as f32 / item_full_costs as f32
     }
-    /// Logs the cost of a particular item at a particular request
-    pub fn log_cost(&mut self, item: &Item, request_type: RequestFullOrSuffix) {
-        let cost = item.get_cost().0 as u32;
+    /// Logs the cost paid for a particular request. The request's own cost is what it would have
+    /// cost without a cache.
+    pub fn log_cost(&mut self, request: &Request, cost: i32, request_type: RequestFullOrSuffix) {
+        let label = request.get_label();
+        let no_cache_cost = request.get_cost().0 as i64;
+        let totals = &mut self.totals;
+        let item_totals = totals
+            .items
+            .get_mut(label)
+            .expect("Could not find item in running totals");
         match request_type {
             RequestFullOrSuffix::Full(is_hit) => {
-                let item_costs = self
-                    .ind_scr
+                totals.full_requests += 1;
+                totals.full_hits += u32::from(is_hit);
+                totals.full_cost += i64::from(cost);
+                totals.full_no_cache_cost += no_cache_cost;
+                item_totals.requests += 1;
+                item_totals.full_cost += i64::from(cost);
+                // Hits are usually free, but a write can still pay on a hit
+                self.full_total += i64::from(cost);
+                if !is_hit {
+                    *self.full_fetches.entry(label.to_string()).or_insert(0) += 1;
+                }
+                // The first miss after an eviction is the request that brought the item back
+                let index = totals.full_requests - 1;
+                if let (Some(evicted_at), false) = (item_totals.evicted_at, is_hit) {
+                    item_totals.evicted_at = None;
+                    if self
+                        .thrash_detection
+                        .is_some_and(|(window, _)| index - evicted_at <= window)
+                    {
+                        item_totals.thrashed += 1;
+                    }
+                }
+                if self.summary_only {
+                    return;
+                }
+                self.full_hits.push_back(is_hit);
+                if let Some(focus) = &self.focus {
+                    self.focus_mask.push(focus.contains(label));
+                }
+                self.full_cost.push_back(cost);
+                self.ind_scr
                     .full_costs
-                    .get_mut(item.get_label())
-                    .expect("Could not find item in full costs for individual SCR logging");
+                    .get_mut(label)
+                    .expect("Could not find item in full costs for individual SCR logging")
+                    .push_back(cost);
+                if !is_hit {
+                    self.full_misses
+                        .entry(label.to_string())
+                        .or_default()
+                        .push(self.full_hits.len() as u32 - 1);
+                }
+            }
+            RequestFullOrSuffix::Suff(is_hit) => {
+                if totals.suff_costs_logged >= self.suffix_start {
+                    totals.suff_requests += 1;
+                    totals.suff_hits += u32::from(is_hit);
+                    totals.suff_no_cache_cost += no_cache_cost;
+                } else if cost != 0 {
+                    totals.suff_prefix_busy = true;
+                }
+                totals.suff_costs_logged += 1;
+                totals.suff_cost += i64::from(cost);
+                item_totals.suff_cost += i64::from(cost);
+                if self.summary_only {
+                    return;
+                }
+                self.suff_hits.push_back(is_hit);
+                self.suff_cost.push_back(cost);
+                self.ind_scr
+                    .suff_costs
+                    .get_mut(label)
+                    .expect("Could not find item in full costs for individual SCR logging")
+                    .push_back(cost);
+            }
+        }
+    }
+    /// Logs a request against the statistics of its item's class. Unlike the cost logging, this
+    /// should only be called for requests that a cache actually serviced.
+    pub fn log_class(&mut self, item: &Item, cost: i32, request_type: RequestFullOrSuffix) {
+        let stats = self
+            .classes
+            .entry(item.get_class().to_string())
+            .or_default();
+        match request_type {
+            RequestFullOrSuffix::Full(is_hit) => {
                 if is_hit {
-                    self.full_cost.push_back(0);
-                    item_costs.push_back(0);
+                    stats.full_hits += 1;
                 } else {
-                    self.full_cost.push_back(cost);
-                    item_costs.push_back(cost);
+                    stats.full_faults += 1;
                 }
+                stats.full_cost += i64::from(cost);
             }
             RequestFullOrSuffix::Suff(is_hit) => {
-                let item_costs = self
-                    .ind_scr
-                    .suff_costs
-                    .get_mut(item.get_label())
-                    .expect("Could not find item in full costs for individual SCR logging");
                 if is_hit {
-                    self.suff_cost.push_back(0);
-                    item_costs.push_back(0);
+                    *stats.suff_hits.get_or_insert(0) += 1;
                 } else {
-                    self.suff_cost.push_back(cost);
-                    item_costs.push_back(cost);
+                    *stats.suff_faults.get_or_insert(0) += 1;
                 }
+                *stats.suff_cost.get_or_insert(0) += i64::from(cost);
+            }
+        }
+    }
+    /// Logs whether a request a cache serviced was a read or a write, and whether it hit.
+    pub fn log_read_write(&mut self, is_write: bool, request_type: RequestFullOrSuffix) {
+        let counts = &mut self.read_write;
+        let (total, hits, is_hit) = match (request_type, is_write) {
+            (RequestFullOrSuffix::Full(is_hit), false) => {
+                (&mut counts.full_reads, &mut counts.full_read_hits, is_hit)
+            }
+            (RequestFullOrSuffix::Full(is_hit), true) => {
+                (&mut counts.full_writes, &mut counts.full_write_hits, is_hit)
+            }
+            (RequestFullOrSuffix::Suff(is_hit), false) => {
+                (&mut counts.suff_reads, &mut counts.suff_read_hits, is_hit)
+            }
+            (RequestFullOrSuffix::Suff(is_hit), true) => {
+                (&mut counts.suff_writes, &mut counts.suff_write_hits, is_hit)
             }
+        };
+        *total += 1;
+        if is_hit {
+            *hits += 1;
         }
     }
     // Logging for pressure. Much simpler than the cost logging because we do not have to be
     // worried about keeping track of indiviual suffix competitive ratios.
     pub fn log_pres(&mut self, pressure: f32, request_type: RequestFullOrSuffix) {
+        let totals = &mut self.totals;
         match request_type {
             RequestFullOrSuffix::Full(is_hit) => {
-                if is_hit {
-                    self.full_pres.push_back(0.0);
-                } else {
+                let pressure = if is_hit { 0.0 } else { pressure };
+                totals.full_max_pres = totals.full_max_pres.max(pressure);
+                totals.full_zero_pres += u32::from(pressure == 0.0);
+                totals.full_pres_logged += 1;
+                if !self.summary_only {
                     self.full_pres.push_back(pressure);
                 }
             }
             RequestFullOrSuffix::Suff(is_hit) => {
-                if is_hit {
-                    self.suff_pres.push_back(0.0);
-                } else {
+                let pressure = if is_hit { 0.0 } else { pressure };
+                totals.suff_max_pres = totals.suff_max_pres.max(pressure);
+                if totals.suff_pres_logged >= self.suffix_start {
+                    totals.suff_zero_pres += u32::from(pressure == 0.0);
+                } else if pressure != 0.0 {
+                    totals.suff_prefix_busy = true;
+                }
+                totals.suff_pres_logged += 1;
+                if !self.summary_only {
                     self.suff_pres.push_back(pressure);
                 }
             }
         }
     }
+    /// Logs the overflow penalty a cache paid on a request.
+    pub fn log_penalty(&mut self, penalty: f32, is_full: bool) {
+        if is_full {
+            self.full_penalty += penalty;
+        } else {
+            self.suff_penalty += penalty;
+        }
+    }
+    /// Logs the reasons for the evictions a cache made on a request. This should be called after
+    /// the request's cost was logged.
+    pub fn log_evictions(&mut self, evictions: &[(&Item, EvictionReason)], is_full: bool) {
+        let counts = if is_full {
+            &mut self.evictions.full
+        } else {
+            &mut self.evictions.suff
+        };
+        for (item, reason) in evictions {
+            *counts.entry(reason.name().to_string()).or_insert(0) += 1;
+            // Only evictions that leave none of an item in the full cache count toward thrashing
+            if is_full && *reason != EvictionReason::Chunk {
+                if let Some(totals) = self.totals.items.get_mut(item.get_label()) {
+                    totals.evicted_at = Some(self.totals.full_requests - 1);
+                }
+            }
+        }
+    }
+    /// Gets every interval during which an item was resident in the full cache, as its label, the
+    /// index of the request that brought it in and the index of the first request after which it
+    /// was gone. Items still resident at the end of the trace get the trace length as their end.
+    pub fn residency_intervals(&self) -> Vec<(String, u32, u32)> {
+        let mut open: BTreeMap<&String, u32> = BTreeMap::new();
+        let mut intervals = Vec::new();
+        for (index, state) in self.full_states.iter().enumerate() {
+            open.retain(|label, start| {
+                let resident = state.contains_key(*label);
+                if !resident {
+                    intervals.push((label.to_string(), *start, index as u32));
+                }
+                resident
+            });
+            for label in state.keys() {
+                open.entry(label).or_insert(index as u32);
+            }
+        }
+        let end = self.full_states.len() as u32;
+        intervals.extend(
+            open.into_iter()
+                .map(|(label, start)| (label.to_string(), start, end)),
+        );
+        intervals.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+        intervals
+    }
+    /// Formats the full cache's residency intervals as a CSV with one row per interval, suitable
+    /// for drawing as a Gantt chart.
+    pub fn residency_csv(&self) -> String {
+        let mut csv = "label,resident_start_index,resident_end_index\n".to_string();
+        for (label, start, end) in self.residency_intervals() {
+            // Quoting labels that would otherwise break the row apart
+            let label = if label.contains([',', '"', '\n']) {
+                format!("\"{}\"", label.replace('"', "\"\""))
+            } else {
+                label
+            };
+            csv.push_str(&format!("{},{},{}\n", label, start, end));
+        }
+        csv
+    }
+    /// Logs the fraction of a cache's size that was occupied after a request.
+    pub fn log_utilization(&mut self, utilization: f32, is_full: bool) {
+        if self.summary_only {
+            return;
+        }
+        if is_full {
+            self.full_utilization.push_back(utilization);
+        } else {
+            self.suff_utilization.push_back(utilization);
+        }
+    }
     pub fn log_state(&mut self, cache: &Landlord, is_full: bool) {
+        if self.summary_only {
+            return;
+        }
         if is_full {
             self.full_states.push_back(cache.get_cache_state());
         } else {
             self.suff_states.push_back(cache.get_cache_state());
         }
     }
-}
-
-#[derive(Debug, Serialize)]
-pub struct PrettyLogger {
-    full_costs: VecDeque<u32>,
-    suff_costs: VecDeque<u32>,
-    full_pres: VecDeque<f32>,
-    suff_pres: VecDeque<f32>,
-    full_states: VecDeque<BTreeMap<String, (f32, u32)>>,
-    suff_states: VecDeque<BTreeMap<String, (f32, u32)>>,
-    ind_scr: BTreeMap<String, f32>,
-}
-
-impl PrettyLogger {
-    pub fn new(logger: Logger) -> Self {
-        Self {
-            full_costs: logger.full_cost,
-            suff_costs: logger.suff_cost,
-            full_pres: logger.full_pres,
-            suff_pres: logger.suff_pres,
-            full_states: logger.full_states,
-            suff_states: logger.suff_states,
-            ind_scr: {
-                let mut ind_scrs = BTreeMap::new();
-                for label in logger.ind_scr.suff_costs.iter() {
-                    let full_costs = logger.ind_scr.full_costs.get(label.0).unwrap();
-                    let full_costs_sum: u32 = full_costs.iter().sum();
-                    if full_costs_sum == 0 {
-                        ind_scrs.insert(label.0.to_string(), 0.0);
-                    } else {
-                        let suff_costs_sum: u32 = label.1.iter().sum();
-                        let ind_scr = suff_costs_sum as f32 / full_costs_sum as f32;
-                        ind_scrs.insert(label.0.to_string(), ind_scr);
-                    }
-                }
-                ind_scrs
-            },
+    /// Sets the request indices at which to snapshot the complete state of both caches.
+    pub fn set_snapshot_at(&mut self, snapshot_at: BTreeSet<u32>) {
+        self.snapshot_at = snapshot_at;
+    }
+    /// Snapshots both caches as they stand after the first `index` requests, if that is one of the
+    /// indices we were asked to snapshot at.
+    pub fn log_snapshot(&mut self, index: u32, full: &Landlord, suff: Option<&Landlord>) {
+        if self.snapshot_at.contains(&index) {
+            self.snapshots.push(Snapshot {
+                index,
+                full: CacheSnapshot::new(full),
+                suff: suff.map(CacheSnapshot::new),
+            });
+        }
+    }
+    /// Sets how many requests apart the credit distribution of both caches is sampled.
+    pub fn set_credit_sample(&mut self, every: u32) {
+        self.credit_sample = Some(every.max(1));
+    }
+    /// Samples the credit distribution of both caches as they stand after the first `index`
+    /// requests, if a whole number of sampling intervals has passed.
+    pub fn log_credit_sample(&mut self, index: u32, full: &Landlord, suff: Option<&Landlord>) {
+        match self.credit_sample {
+            Some(every) if index > 0 && index.is_multiple_of(every) => {
+                self.credit_distribution.push(CreditSample {
+                    index,
+                    full: CreditDistribution::new(full),
+                    suff: suff.map(CreditDistribution::new),
+                });
+            }
+            _ => {}
         }
     }
-    pub fn ser_logger(&self) -> String {
-        toml::to_string_pretty(self).unwrap()
+    /// Logs that only the full cache is being simulated, so that the suffix statistics and SCR are
+    /// left out of the output.
+    pub fn log_full_only(&mut self) {
+        self.full_only = true;
+    }
+    /// Logs the index at which the suffix cache starts servicing requests.
+    pub fn log_suffix_start(&mut self, suffix_start: u32) {
+        self.suffix_start = suffix_start;
+    }
+    /// Sets the number of requests in each epoch, which must be at least one. When set, per-epoch
+    /// statistics are computed from the per-request series once logging is finished.
+    pub fn set_epoch_length(&mut self, epoch_length: u32) {
+        self.epoch_length = Some(epoch_length);
+    }
+    /// Keeps the per-item full and suffix cost series in the output rather than collapsing them
+    /// into an individual SCR. This repeats the cost of every request in the output, grouped by
+    /// item.
+    pub fn set_emit_per_item_series(&mut self, emit_per_item_series: bool) {
+        self.emit_per_item_series = emit_per_item_series;
+    }
+    /// Buckets the request and cost rates by this many seconds of arrival time, using the trace's
+    /// timestamps. Traces without a timestamp on every request are bucketed by this many requests
+    /// instead.
+    pub fn set_time_bucket(&mut self, duration: f64, trace: &VecDeque<Request>) {
+        self.time_bucket = Some(duration);
+        self.timestamps = trace.iter().map(|n| n.get_timestamp()).collect();
+    }
+    /// Includes each item's fetch count and amortized fetch cost in the output.
+    pub fn set_amortized_cost(&mut self, amortized_cost: bool) {
+        self.amortized_cost = amortized_cost;
+    }
+    /// Keeps only the running totals behind the summary and per-item statistics instead of every
+    /// per-request series, so the logger takes the same space however long the trace is.
+    pub fn set_summary_only(&mut self, summary_only: bool) {
+        self.summary_only = summary_only;
+    }
+    /// Includes the running totals of the full and suffix cost series in the output.
+    pub fn set_cumulative(&mut self, cumulative: bool) {
+        self.cumulative = cumulative;
+    }
+    /// Includes the correlation between each cache's pressure and the running SCR in the output.
+    pub fn set_pressure_correlation(&mut self, pressure_correlation: bool) {
+        self.pressure_correlation = pressure_correlation;
+    }
+    /// Includes the spread of the gaps between consecutive misses on each item in the output.
+    pub fn set_miss_gaps(&mut self, miss_gaps: bool) {
+        self.miss_gaps = miss_gaps;
+    }
+    /// Sets the smallest full cost an SCR is computed against. Any SCR whose full cost is below
+    /// the floor is reported as 0.
+    pub fn set_scr_floor(&mut self, scr_floor: i32) {
+        self.scr_floor = scr_floor;
+    }
+    /// Sets how soon after being evicted and how many times an item must be requested again for the
+    /// full cache to be reported as thrashing on it.
+    pub fn set_thrash_detection(&mut self, window: u32, threshold: u32) {
+        self.thrash_detection = Some((window, threshold));
+    }
+    /// Sets the full cost after which the run stops servicing requests.
+    pub fn set_cost_budget(&mut self, cost_budget: u64) {
+        self.cost_budget = Some(i64::try_from(cost_budget).unwrap_or(i64::MAX));
+    }
+    /// Checks whether the full cache has paid at least the cost budget, if there is one.
+    pub fn is_budget_spent(&self) -> bool {
+        self.cost_budget
+            .is_some_and(|budget| self.full_total >= budget)
+    }
+    /// Logs the index of the first request that was not serviced because the budget was spent.
+    pub fn log_budget_stop(&mut self, index: u32) {
+        self.budget_stop = Some(index);
+    }
+    /// Logs how many faults each cache's ghost would have turned into hits.
+    pub fn log_ghost_hits(&mut self, full: u32, suff: u32) {
+        self.ghost_hits = Some(GhostHits { full, suff });
+    }
+    /// Logs how many faults each cache's victim cache caught.
+    pub fn log_victim_hits(&mut self, full: u32, suff: u32) {
+        self.victim_hits = Some(VictimHits { full, suff });
+    }
+    /// Logs how many times each cache's minimum residency kept an item from being evicted.
+    pub fn log_residency_exemptions(&mut self, full: u32, suff: u32) {
+        self.residency_exemptions = Some(ResidencyExemptions { full, suff });
+    }
+    /// Logs how many requests bypassed each cache because their item was too large to cache.
+    pub fn log_bypassed(&mut self, full: u32, suff: u32) {
+        self.bypassed = Some(Bypassed { full, suff });
+    }
+    /// Logs how much cost each cache cut off by clipping.
+    pub fn log_clipped_cost(&mut self, full: i64, suff: i64) {
+        self.clipped_cost = Some(ClippedCost { full, suff });
+    }
+    /// Logs how many times each cache turned away a faulting item.
+    pub fn log_deferred_admissions(&mut self, full: u32, suff: u32) {
+        self.deferred_admissions = Some(DeferredAdmissions { full, suff });
+    }
+    /// Logs how the run was produced so that it is recorded alongside its results.
+    pub fn log_run_info(&mut self, run_info: RunInfo) {
+        self.run_info = Some(run_info);
+    }
+    /// Logs the reuse distance histogram of the trace.
+    pub fn log_reuse_distance(&mut self, trace: &VecDeque<Request>) {
+        self.reuse_distance = Some(ReuseDistance::new(trace));
+    }
+    /// Logs the LRU miss ratio curve of the trace, computed from its reuse distances in one pass.
+    pub fn log_miss_ratio_curve(&mut self, trace: &VecDeque<Request>) {
+        self.miss_ratio_curve = Some(MissRatioCurve::new(&ReuseDistance::new(trace)));
+    }
+    /// Logs the Che approximation of an LRU cache of the given size's hit ratio on the trace.
+    pub fn log_che(&mut self, trace: &VecDeque<Request>, size: u32) {
+        self.che = Some(CheApproximation::new(trace, size));
+    }
+    /// Logs what a baseline cache pays for the trace, to compare the full cache against.
+    pub fn log_baseline(&mut self, baseline: BaselineCost) {
+        self.baseline = Some(baseline);
+    }
+    /// Restricts the per-request series and per-item statistics in the output to requests for and
+    /// items in the focus set. The summary, epochs and classes still cover every request.
+    pub fn set_focus(&mut self, focus: BTreeSet<String>) {
+        self.focus = Some(focus);
+    }
+    /// Logs histograms of the sizes and costs of the items in the catalog.
+    pub fn log_catalog(&mut self, items: &[Item]) {
+        self.catalog = Some(CatalogHistograms::new(items));
+    }
+}
+
+/// The per-request costs of a run so far, written out periodically so that a run that dies partway
+/// through still leaves its results up to the last checkpoint behind. The suffix cost is 0 for
+/// every request before the division point, as in the full output.
+#[derive(Debug, Default, Serialize)]
+pub struct Checkpoint {
+    requests: usize,
+    full_costs: Vec<i32>,
+    suff_costs: Vec<i32>,
+    full_hits: Vec<bool>,
+    #[serde(skip)]
+    path: String,
+    #[serde(skip)]
+    every: usize,
+}
+
+impl Checkpoint {
+    pub fn new(path: String, every: usize) -> Self {
+        Self {
+            path,
+            every: every.max(1),
+            ..Default::default()
+        }
+    }
+    /// Records a serviced request, writing the checkpoint out every time another `every` requests
+    /// have been recorded.
+    pub fn observe(&mut self, event: &RequestEvent) {
+        self.requests += 1;
+        self.full_costs.push(event.full_cost);
+        self.suff_costs.push(event.suff_cost);
+        self.full_hits.push(event.full_hit);
+        if self.requests.is_multiple_of(self.every) {
+            self.write();
+        }
+    }
+    // Writes the checkpoint to a temporary file first and renames it into place, so the checkpoint
+    // is never left half written. Failing to write a checkpoint does not stop the run.
+    fn write(&self) {
+        let temp = format!("{}.tmp", self.path);
+        let data = toml::to_string_pretty(self).unwrap();
+        if std::fs::write(&temp, data).is_ok() {
+            let _ = std::fs::rename(&temp, &self.path);
+        }
+    }
+    /// Removes the checkpoint once the full output has been written.
+    pub fn remove(&self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// One line of a decision log: what each cache did on a single request.
+#[derive(Debug, Serialize)]
+struct DecisionLine<'e> {
+    index: usize,
+    label: &'e str,
+    full: DecisionEntry<'e>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suff: Option<DecisionEntry<'e>>,
+}
+
+#[derive(Debug, Serialize)]
+struct DecisionEntry<'e> {
+    hit: bool,
+    cost: i32,
+    pressure: f32,
+    evicted: Vec<EvictedEntry<'e>>,
+    resident: usize,
+    occupied: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct EvictedEntry<'e> {
+    label: &'e str,
+    reason: &'static str,
+}
+
+impl<'e> DecisionEntry<'e> {
+    fn new(hit: bool, cost: i32, decision: &CacheDecision<'e>) -> Self {
+        Self {
+            hit,
+            cost,
+            pressure: decision.pressure,
+            evicted: decision
+                .evicted
+                .iter()
+                .map(|(item, reason)| EvictedEntry {
+                    label: item.get_label(),
+                    reason: reason.name(),
+                })
+                .collect(),
+            resident: decision.resident,
+            occupied: decision.occupied,
+        }
+    }
+}
+
+/// Streams what both caches decided on every request to a file as it happens, one JSON object per
+/// line. The suffix entry is left out of the lines for requests before the division point.
+#[derive(Debug)]
+pub struct DecisionLog {
+    writer: BufWriter<File>,
+    // Writing stops at the first failure, which is reported once the run is over.
+    error: Option<io::Error>,
+}
+
+impl DecisionLog {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            error: None,
+        })
+    }
+    /// Writes out the line for a serviced request.
+    pub fn observe(&mut self, event: &RequestEvent) {
+        if self.error.is_some() {
+            return;
+        }
+        let line = DecisionLine {
+            index: event.index,
+            label: event.label,
+            full: DecisionEntry::new(event.full_hit, event.full_cost, &event.full),
+            suff: event.suff.as_ref().map(|suff| {
+                DecisionEntry::new(event.suff_hit.unwrap_or(false), event.suff_cost, suff)
+            }),
+        };
+        let written = serde_json::to_writer(&mut self.writer, &line)
+            .map_err(io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"));
+        if let Err(e) = written {
+            self.error = Some(e);
+        }
+    }
+    /// Flushes the log once the run is over, reporting the first error hit while writing it.
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PrettyLogger {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_info: Option<RunInfo>,
+    summary: Summary,
+    // The index of the first request left unserviced because the full cache spent its cost
+    // budget. Every series and statistic only covers the requests before it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    budget_stop: Option<u32>,
+    // The per-request series are left out when only the running totals were kept. Every field left
+    // out when empty defaults to empty, so the schema does not require it.
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    full_costs: VecDeque<i32>,
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    suff_costs: VecDeque<i32>,
+    // Whether each request hit. The cost series cannot tell a hit from a miss on a zero-cost
+    // item, so these are recorded directly. The suffix series starts at the division point since
+    // the suffix cache services nothing before it.
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    full_hits: VecDeque<bool>,
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    suff_hits: VecDeque<bool>,
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    full_pres: VecDeque<f32>,
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    suff_pres: VecDeque<f32>,
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    full_utilization: VecDeque<f32>,
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    suff_utilization: VecDeque<f32>,
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    full_states: VecDeque<BTreeMap<String, (f32, u32)>>,
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    suff_states: VecDeque<BTreeMap<String, (f32, u32)>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    ind_scr: BTreeMap<String, f32>,
+    classes: BTreeMap<String, ClassStats>,
+    evictions: EvictionCounts,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    epochs: Option<Vec<EpochStats>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_buckets: Option<TimeBuckets>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    amortized_cost: Option<AmortizedCost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cumulative_full_costs: Option<VecDeque<i64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cumulative_suff_costs: Option<VecDeque<i64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pressure_correlation: Option<PressureCorrelation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    miss_gaps: Option<BTreeMap<String, MissGaps>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    read_write: Option<ReadWriteStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thrashing: Option<Thrashing>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ghost_hits: Option<GhostHits>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    victim_hits: Option<VictimHits>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    residency_exemptions: Option<ResidencyExemptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bypassed: Option<Bypassed>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clipped_cost: Option<ClippedCost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deferred_admissions: Option<DeferredAdmissions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    per_item_series: Option<IndScr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reuse_distance: Option<ReuseDistance>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    miss_ratio_curve: Option<MissRatioCurve>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    che: Option<CheApproximation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    baseline: Option<BaselineCost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    catalog: Option<CatalogHistograms>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    snapshots: Vec<Snapshot>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    credit_distribution: Vec<CreditSample>,
+    // The indices of the requests kept in the per-request series when the output is focused on a
+    // subset of the items.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    focus_indices: Option<Vec<u32>>,
+    // The total cost the full cache paid for each item. This is only used by the human-readable
+    // report, which lists the most costly items.
+    #[serde(skip)]
+    item_costs: BTreeMap<String, i64>,
+}
+
+/// One cache size in a sweep over sizes, with how much less the full cache paid than at the
+/// smallest size swept and the marginal value of the cache lines added since the previous size,
+/// which is the cost saved per extra cache line. The smallest size has nothing to compare against
+/// and so no marginal value.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MarginalPoint {
+    size: u32,
+    full_cost: i64,
+    cost_saved: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    marginal_value: Option<f64>,
+}
+
+/// The marginal value curve of a sweep over cache sizes with one pair of policies. The knee is the
+/// size past which extra cache lines stop paying off as well, found as the point furthest above the
+/// straight line from the smallest to the largest size once both the sizes and the cost saved are
+/// scaled to between 0 and 1. It is left out with fewer than three sizes or if no point lies above
+/// that line.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct MarginalValue {
+    points: Vec<MarginalPoint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    knee: Option<u32>,
+}
+
+impl MarginalValue {
+    pub fn new(runs: &[(u32, &PrettyLogger)]) -> Self {
+        let mut runs = runs.to_vec();
+        runs.sort_by_key(|(size, _)| *size);
+        let first_cost = runs.first().map_or(0, |(_, n)| n.summary.full_cost);
+        let mut points: Vec<MarginalPoint> = Vec::new();
+        for (size, display) in runs.iter() {
+            let full_cost = display.summary.full_cost;
+            let marginal_value = points
+                .last()
+                .filter(|last| last.size < *size)
+                .map(|last| (last.full_cost - full_cost) as f64 / f64::from(size - last.size));
+            points.push(MarginalPoint {
+                size: *size,
+                full_cost,
+                cost_saved: first_cost - full_cost,
+                marginal_value,
+            });
+        }
+        let knee = match (points.first(), points.last()) {
+            (Some(first), Some(last))
+                if points.len() >= 3 && last.size > first.size && last.cost_saved > 0 =>
+            {
+                let span = f64::from(last.size - first.size);
+                points
+                    .iter()
+                    .map(|n| {
+                        let x = f64::from(n.size - first.size) / span;
+                        let y = n.cost_saved as f64 / last.cost_saved as f64;
+                        (n.size, y - x)
+                    })
+                    .filter(|(_, above)| *above > 0.0)
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(size, _)| size)
+            }
+            _ => None,
+        };
+        Self { points, knee }
+    }
+
+    // Lays the curve out as a table for the human-readable report.
+    fn human_report(&self) -> String {
+        let mut report = format!(
+            "{:>10}  {:>12}  {:>12}  {:>14}\n",
+            "size", "full cost", "cost saved", "marginal value"
+        );
+        for point in self.points.iter() {
+            report.push_str(&format!(
+                "{:>10}  {:>12}  {:>12}  {:>14}{}\n",
+                point.size,
+                point.full_cost,
+                point.cost_saved,
+                point
+                    .marginal_value
+                    .map_or("".to_string(), |n| format!("{:.4}", n)),
+                if self.knee == Some(point.size) {
+                    "  <- knee"
+                } else {
+                    ""
+                }
+            ));
+        }
+        report
+    }
+}
+
+// Divides the suffix cost by the full cost, reporting 0 instead while the full cost is still below
+// the SCR floor so that a tiny early denominator cannot produce a huge ratio. Negative costs can
+// bring the full cost to or below zero, where the ratio means nothing, so we report 0 there too.
+fn floored_scr(suff_cost: i64, full_cost: i64, scr_floor: i32) -> f32 {
+    if full_cost <= 0 || full_cost < i64::from(scr_floor) {
+        0.0
+    } else {
+        suff_cost as f32 / full_cost as f32
+    }
+}
+
+/// A division point in a sweep, with what a suffix cache starting there paid and its SCR.
+#[derive(Debug, Serialize, JsonSchema)]
+struct DivSweepPoint {
+    div: u32,
+    suff_cost: i64,
+    scr: f32,
+}
+
+/// How the suffix competitive ratio changes with where the suffix starts. The full cache services
+/// the whole trace wherever the suffix starts, so every point shares its cost.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DivSweep {
+    run_info: RunInfo,
+    full_cost: i64,
+    points: Vec<DivSweepPoint>,
+}
+
+impl DivSweep {
+    pub fn new(run_info: RunInfo, full_cost: i64, points: &[(u32, i64)], scr_floor: i32) -> Self {
+        Self {
+            run_info,
+            full_cost,
+            points: points
+                .iter()
+                .map(|(div, suff_cost)| DivSweepPoint {
+                    div: *div,
+                    suff_cost: *suff_cost,
+                    scr: floored_scr(*suff_cost, full_cost, scr_floor),
+                })
+                .collect(),
+        }
+    }
+    /// Gets the SCR at every division point, keyed by where its suffix starts.
+    pub fn get_scrs(&self) -> Vec<(String, f32)> {
+        let width = self
+            .points
+            .iter()
+            .map(|n| n.div.to_string().len())
+            .max()
+            .unwrap_or(1);
+        self.points
+            .iter()
+            .map(|n| (format!("div_{:0width$}", n.div, width = width), n.scr))
+            .collect()
+    }
+    /// Serializes the sweep along with how many NaN or infinite floats had to be replaced.
+    pub fn ser(&self, precision: Option<u32>) -> (String, u32) {
+        to_finite_toml(self, precision)
+    }
+    /// Lays the sweep out as a table of SCR against division point.
+    pub fn human_report(&self) -> String {
+        let mut report = format!("full cost: {}\n\n", self.full_cost);
+        report.push_str(&format!(
+            "{:>10}  {:>12}  {:>10}\n",
+            "div", "suffix cost", "scr"
+        ));
+        for point in self.points.iter() {
+            report.push_str(&format!(
+                "{:>10}  {:>12}  {:>10.4}\n",
+                point.div, point.suff_cost, point.scr
+            ));
+        }
+        report
+    }
+}
+
+// The results of a matrix of runs along with the marginal value curve of each pair of policies.
+#[derive(Serialize)]
+struct MatrixResults<'r> {
+    #[serde(flatten)]
+    runs: &'r BTreeMap<String, PrettyLogger>,
+    marginal_value: &'r BTreeMap<String, MarginalValue>,
+}
+
+/// What a NaN or infinite float in the output is written as. TOML can spell these, but many of the
+/// tools that read our output cannot, so a NaN or positive infinity becomes the largest finite f32
+/// and a negative infinity becomes its negation. It is far beyond anything a real run produces, so
+/// it cannot be mistaken for a result.
+pub const NON_FINITE_SENTINEL: f64 = f32::MAX as f64;
+
+// Serializes a value to TOML with every NaN and infinite float replaced by NON_FINITE_SENTINEL,
+// returning how many were replaced. Given a precision, every float is also rounded to that many
+// decimal places, which only changes what is written and not anything we computed. Written out from
+// a toml::Value, arrays of tables come before other tables, so the value is only serialized that
+// way when something had to be replaced or rounded and otherwise keeps the order of its fields.
+fn to_finite_toml<T: Serialize>(value: &T, precision: Option<u32>) -> (String, u32) {
+    fn sanitize(value: &mut toml::Value, scale: Option<f64>) -> u32 {
+        match value {
+            toml::Value::Float(n) if !n.is_finite() => {
+                *n = if *n == f64::NEG_INFINITY {
+                    -NON_FINITE_SENTINEL
+                } else {
+                    NON_FINITE_SENTINEL
+                };
+                1
+            }
+            toml::Value::Float(n) => {
+                if let Some(scale) = scale {
+                    *n = (*n * scale).round() / scale;
+                }
+                0
+            }
+            toml::Value::Array(values) => values.iter_mut().map(|n| sanitize(n, scale)).sum(),
+            toml::Value::Table(table) => table.iter_mut().map(|(_, n)| sanitize(n, scale)).sum(),
+            _ => 0,
+        }
+    }
+    let scale = precision.map(|digits| 10f64.powi(digits as i32));
+    let mut sanitized = toml::Value::try_from(value).unwrap();
+    match sanitize(&mut sanitized, scale) {
+        0 if precision.is_none() => (toml::to_string_pretty(value).unwrap(), 0),
+        replaced => (toml::to_string_pretty(&sanitized).unwrap(), replaced),
+    }
+}
+
+// How many of the most costly items the human-readable report lists.
+const REPORT_TOP_ITEMS: usize = 10;
+
+impl PrettyLogger {
+    pub fn new(logger: Logger) -> Self {
+        // We split the trace into epochs before moving the series out of our logger. The final
+        // epoch may be shorter than the rest if the epoch length does not divide the trace.
+        let epochs = logger.epoch_length.map(|length| {
+            let length = length as usize;
+            (0..logger.full_cost.len())
+                .step_by(length)
+                .map(|start| {
+                    EpochStats::new(&logger, start, (start + length).min(logger.full_cost.len()))
+                })
+                .collect()
+        });
+        let time_buckets = logger
+            .time_bucket
+            .map(|duration| TimeBuckets::new(&logger, duration));
+        let amortized_cost = if logger.amortized_cost {
+            Some(AmortizedCost::new(&logger))
+        } else {
+            None
+        };
+        // The cumulative series are the running sums of the per-request costs. These are summed as
+        // i64 so that long traces with large costs cannot overflow.
+        let cumulative = |costs: &VecDeque<i32>| {
+            costs
+                .iter()
+                .scan(0, |total: &mut i64, cost| {
+                    *total += i64::from(*cost);
+                    Some(*total)
+                })
+                .collect()
+        };
+        let (cumulative_full_costs, cumulative_suff_costs) = if logger.cumulative {
+            (
+                Some(cumulative(&logger.full_cost)),
+                Some(cumulative(&logger.suff_cost)),
+            )
+        } else {
+            (None, None)
+        };
+        let pressure_correlation = if logger.pressure_correlation {
+            Some(PressureCorrelation::new(&logger))
+        } else {
+            None
+        };
+        let miss_gaps = if logger.miss_gaps {
+            Some(MissGaps::new(&logger))
+        } else {
+            None
+        };
+        let read_write = ReadWriteStats::new(&logger);
+        let thrashing = logger
+            .thrash_detection
+            .and_then(|(window, threshold)| Thrashing::new(&logger, window, threshold));
+        let full_hits = logger.full_hits.clone();
+        let suff_hits = logger
+            .suff_hits
+            .iter()
+            .skip(logger.suffix_start as usize)
+            .copied()
+            .collect();
+        let item_costs = logger
+            .totals
+            .items
+            .iter()
+            .map(|(label, totals)| (label.clone(), totals.full_cost))
+            .collect();
+        let focus = logger.focus.clone();
+        let focus_mask = logger.focus_mask.clone();
+        let mut pretty = Self {
+            summary: Summary::new(&logger),
+            run_info: logger.run_info,
+            budget_stop: logger.budget_stop,
+            full_costs: logger.full_cost,
+            suff_costs: logger.suff_cost,
+            full_hits,
+            suff_hits,
+            full_pres: logger.full_pres,
+            suff_pres: logger.suff_pres,
+            full_utilization: logger.full_utilization,
+            suff_utilization: logger.suff_utilization,
+            full_states: logger.full_states,
+            suff_states: logger.suff_states,
+            ind_scr: {
+                let mut ind_scrs = BTreeMap::new();
+                // Individual SCRs need a suffix cache to compare against
+                if !logger.full_only {
+                    for (label, totals) in logger.totals.items.iter() {
+                        if totals.full_cost <= 0 {
+                            ind_scrs.insert(label.to_string(), 0.0);
+                        } else {
+                            let ind_scr = totals.suff_cost as f32 / totals.full_cost as f32;
+                            ind_scrs.insert(label.to_string(), ind_scr);
+                        }
+                    }
+                }
+                ind_scrs
+            },
+            classes: {
+                let mut classes = logger.classes;
+                for stats in classes.values_mut() {
+                    stats.finalize(logger.full_only);
+                }
+                classes
+            },
+            evictions: logger.evictions,
+            epochs,
+            time_buckets,
+            amortized_cost,
+            cumulative_full_costs,
+            cumulative_suff_costs,
+            pressure_correlation,
+            miss_gaps,
+            read_write,
+            thrashing,
+            ghost_hits: logger.ghost_hits,
+            victim_hits: logger.victim_hits,
+            residency_exemptions: logger.residency_exemptions,
+            bypassed: logger.bypassed,
+            clipped_cost: logger.clipped_cost,
+            deferred_admissions: logger.deferred_admissions,
+            per_item_series: if logger.emit_per_item_series {
+                Some(logger.ind_scr)
+            } else {
+                None
+            },
+            reuse_distance: logger.reuse_distance,
+            miss_ratio_curve: logger.miss_ratio_curve,
+            che: logger.che,
+            baseline: logger.baseline,
+            catalog: logger.catalog,
+            snapshots: logger.snapshots,
+            credit_distribution: logger.credit_distribution,
+            focus_indices: None,
+            item_costs,
+        };
+        let full_hit_ratio = f64::from(pretty.summary.full_hit_ratio);
+        if let Some(che) = pretty.che.as_mut() {
+            che.compare(full_hit_ratio);
+        }
+        let full_cost = pretty.summary.full_cost;
+        if let Some(baseline) = pretty.baseline.as_mut() {
+            baseline.compare(full_cost);
+        }
+        if let Some(focus) = focus {
+            pretty.focus(&focus, &focus_mask);
+        }
+        pretty
+    }
+    // Drops every request not for a focus item from the per-request series and every item not in
+    // the focus set from the per-item statistics.
+    fn focus(&mut self, focus: &BTreeSet<String>, mask: &[bool]) {
+        fn retain<T>(series: &mut VecDeque<T>, mask: &[bool]) {
+            let mut index = 0;
+            series.retain(|_| {
+                index += 1;
+                mask[index - 1]
+            });
+        }
+        retain(&mut self.full_costs, mask);
+        retain(&mut self.suff_costs, mask);
+        retain(&mut self.full_hits, mask);
+        let suffix_start = mask.len() - self.suff_hits.len();
+        retain(&mut self.suff_hits, &mask[suffix_start..]);
+        retain(&mut self.full_pres, mask);
+        retain(&mut self.suff_pres, mask);
+        retain(&mut self.full_utilization, mask);
+        retain(&mut self.suff_utilization, mask);
+        retain(&mut self.full_states, mask);
+        retain(&mut self.suff_states, &mask[suffix_start..]);
+        for state in self
+            .full_states
+            .iter_mut()
+            .chain(self.suff_states.iter_mut())
+        {
+            state.retain(|label, _| focus.contains(label));
+        }
+        if let Some(costs) = self.cumulative_full_costs.as_mut() {
+            retain(costs, mask);
+        }
+        if let Some(costs) = self.cumulative_suff_costs.as_mut() {
+            retain(costs, mask);
+        }
+        self.ind_scr.retain(|label, _| focus.contains(label));
+        self.item_costs.retain(|label, _| focus.contains(label));
+        if let Some(thrashing) = self.thrashing.as_mut() {
+            thrashing.items.retain(|item| focus.contains(&item.label));
+        }
+        if self.thrashing.as_ref().is_some_and(|n| n.items.is_empty()) {
+            self.thrashing = None;
+        }
+        if let Some(series) = self.per_item_series.as_mut() {
+            series.full_costs.retain(|label, _| focus.contains(label));
+            series.suff_costs.retain(|label, _| focus.contains(label));
+        }
+        if let Some(amortized_cost) = self.amortized_cost.as_mut() {
+            amortized_cost
+                .items
+                .retain(|label, _| focus.contains(label));
+        }
+        if let Some(miss_gaps) = self.miss_gaps.as_mut() {
+            miss_gaps.retain(|label, _| focus.contains(label));
+        }
+        if let Some(che) = self.che.as_mut() {
+            che.retain_items(|label| focus.contains(label));
+        }
+        for snapshot in self.snapshots.iter_mut() {
+            snapshot.full.retain(focus);
+            if let Some(suff) = snapshot.suff.as_mut() {
+                suff.retain(focus);
+            }
+        }
+        self.focus_indices = Some(
+            mask.iter()
+                .enumerate()
+                .filter(|(_, keep)| **keep)
+                .map(|(index, _)| index as u32)
+                .collect(),
+        );
+    }
+    /// Gets the suffix competitive ratio over the whole trace, which is 0 if only the full cache
+    /// was simulated.
+    pub fn get_scr(&self) -> f32 {
+        self.summary.scr.unwrap_or(0.0)
+    }
+    /// Formats the summary as a small table with one row per metric and a column for each cache.
+    pub fn summary_table(&self) -> String {
+        let summary = &self.summary;
+        // Missing suffix statistics are left blank, and the suffix column is dropped altogether
+        // when only the full cache was simulated.
+        let cell = |value: Option<String>| value.unwrap_or_default();
+        let mut rows = vec![
+            vec![
+                "metric".to_string(),
+                "full".to_string(),
+                "suffix".to_string(),
+            ],
+            vec![
+                "total cost".to_string(),
+                summary.full_cost.to_string(),
+                cell(summary.suff_cost.map(|n| n.to_string())),
+            ],
+            vec![
+                "hit ratio".to_string(),
+                summary.full_hit_ratio.to_string(),
+                cell(summary.suff_hit_ratio.map(|n| n.to_string())),
+            ],
+            vec![
+                "max pressure".to_string(),
+                summary.full_max_pres.to_string(),
+                cell(summary.suff_max_pres.map(|n| n.to_string())),
+            ],
+            vec![
+                "zero pressure".to_string(),
+                summary.full_zero_pres_ratio.to_string(),
+                cell(summary.suff_zero_pres_ratio.map(|n| n.to_string())),
+            ],
+            vec![
+                "overflow penalty".to_string(),
+                summary.full_penalty.to_string(),
+                cell(summary.suff_penalty.map(|n| n.to_string())),
+            ],
+            vec![
+                "no-cache cost".to_string(),
+                summary.no_cache_cost.to_string(),
+                cell(summary.suff_no_cache_cost.map(|n| n.to_string())),
+            ],
+            vec![
+                "savings".to_string(),
+                format!(
+                    "{} ({:.2}%)",
+                    summary.full_savings, summary.full_savings_percent
+                ),
+                cell(
+                    summary
+                        .suff_savings
+                        .zip(summary.suff_savings_percent)
+                        .map(|(savings, percent)| format!("{} ({:.2}%)", savings, percent)),
+                ),
+            ],
+        ];
+        match summary.scr {
+            Some(scr) => rows.push(vec!["scr".to_string(), String::new(), scr.to_string()]),
+            None => {
+                for row in rows.iter_mut() {
+                    row.truncate(2);
+                }
+            }
+        }
+        let widths: Vec<usize> = (0..rows[0].len())
+            .map(|col| rows.iter().map(|row| row[col].len()).max().unwrap_or(0))
+            .collect();
+        let border = format!(
+            "+{}+\n",
+            widths
+                .iter()
+                .map(|width| "-".repeat(width + 2))
+                .collect::<Vec<String>>()
+                .join("+")
+        );
+        let mut table = border.clone();
+        for (i, row) in rows.iter().enumerate() {
+            for (cell, width) in row.iter().zip(widths.iter()) {
+                table.push_str(&format!("| {:<width$} ", cell, width = width));
+            }
+            table.push_str("|\n");
+            if i == 0 {
+                table.push_str(&border);
+            }
+        }
+        table.push_str(&border);
+        table
+    }
+    /// Formats a plain-text report of the run meant to be read rather than parsed: how the run was
+    /// configured, its headline metrics, the items the full cache paid the most for, a summary of
+    /// the pressure each cache saw and anything about the run that looks out of the ordinary.
+    pub fn human_report(&self) -> String {
+        let summary = &self.summary;
+        let mut report = String::new();
+        let section = |report: &mut String, title: &str| {
+            if !report.is_empty() {
+                report.push('\n');
+            }
+            report.push_str(&format!("{}\n{}\n", title, "-".repeat(title.len())));
+        };
+        section(&mut report, "Configuration");
+        match &self.run_info {
+            Some(info) => {
+                report.push_str(&format!("input:   {}\n", info.input));
+                report.push_str(&format!("command: {}\n", info.args.join(" ")));
+                report.push_str(&format!("version: {}\n", info.version));
+                report.push_str(&format!("seed:    {}\n", info.seed));
+                if let Some(bound) = &info.competitive_bound {
+                    report.push_str(&format!(
+                        "competitive bound: {} (k = {}, h = {})\n",
+                        bound.bound, bound.k, bound.h
+                    ));
+                }
+            }
+            None => report.push_str("not recorded\n"),
+        }
+        section(&mut report, "Headline metrics");
+        report.push_str(&self.summary_table());
+        section(&mut report, "Most costly items");
+        let mut items: Vec<(&String, i64)> = self
+            .item_costs
+            .iter()
+            .filter(|(_, cost)| **cost > 0)
+            .map(|(label, cost)| (label, *cost))
+            .collect();
+        items.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        items.truncate(REPORT_TOP_ITEMS);
+        if items.is_empty() {
+            report.push_str("no item cost anything\n");
+        }
+        let label_width = items
+            .iter()
+            .map(|(label, _)| label.len())
+            .max()
+            .unwrap_or(0);
+        let cost_width = items.first().map_or(0, |(_, cost)| cost.to_string().len());
+        for (rank, (label, cost)) in items.iter().enumerate() {
+            let share = if summary.full_cost > 0 {
+                *cost as f64 / summary.full_cost as f64 * 100.0
+            } else {
+                0.0
+            };
+            report.push_str(&format!(
+                "{:>2}. {:<label_width$}  {:>cost_width$}  ({:.1}% of full cost)\n",
+                rank + 1,
+                label,
+                cost,
+                share,
+                label_width = label_width,
+                cost_width = cost_width
+            ));
+        }
+        section(&mut report, "Pressure");
+        // The suffix cache only sees pressure from the division point on, which is where its hit
+        // series starts.
+        let suffix_start = self.suff_pres.len() - self.suff_hits.len();
+        let caches = [
+            ("full", &self.full_pres, 0, &self.evictions.full),
+            (
+                "suffix",
+                &self.suff_pres,
+                suffix_start,
+                &self.evictions.suff,
+            ),
+        ];
+        for (name, pres, start, evictions) in caches.iter() {
+            if pres.is_empty() {
+                continue;
+            }
+            let serviced = pres.len() - start;
+            let mean = pres.iter().skip(*start).sum::<f32>() / serviced.max(1) as f32;
+            let max = pres.iter().skip(*start).fold(0.0, |a: f32, b| a.max(*b));
+            let zeros = pres.iter().skip(*start).filter(|n| **n == 0.0).count();
+            report.push_str(&format!(
+                "{}: mean {:.3}, max {}, none on {} of {} requests, {} evictions\n",
+                name,
+                mean,
+                max,
+                zeros,
+                serviced,
+                evictions.values().sum::<u32>()
+            ));
+        }
+        section(&mut report, "Anomalies");
+        let mut anomalies = Vec::new();
+        if let Some(index) = self.budget_stop {
+            anomalies.push(format!(
+                "the run stopped at request {} once the full cache spent its cost budget",
+                index
+            ));
+        }
+        if summary.full_hit_ratio == 0.0 {
+            anomalies.push("the full cache never hit".to_string());
+        }
+        if let Some(scr) = summary.scr {
+            if scr > 1.0 {
+                anomalies.push(format!(
+                    "the suffix cache paid more than the full cache (SCR {})",
+                    scr
+                ));
+            }
+            let bound = self
+                .run_info
+                .as_ref()
+                .and_then(|info| info.competitive_bound.as_ref());
+            if let Some(bound) = bound.filter(|bound| f64::from(scr) > bound.bound) {
+                anomalies.push(format!(
+                    "the SCR of {} is above the competitive bound of {}",
+                    scr, bound.bound
+                ));
+            }
+        }
+        if let Some(thrashing) = &self.thrashing {
+            anomalies.push(format!(
+                "the full cache thrashed on {} items, worst on {} which was requested again \
+                 within {} requests of its eviction {} times",
+                thrashing.items.len(),
+                thrashing.items[0].label,
+                thrashing.window,
+                thrashing.items[0].times
+            ));
+        }
+        let costlier = self.ind_scr.values().filter(|scr| **scr > 1.0).count();
+        if costlier > 0 {
+            anomalies.push(format!(
+                "{} items cost the suffix cache more than the full cache",
+                costlier
+            ));
+        }
+        for (name, penalty) in [
+            ("full", Some(summary.full_penalty)),
+            ("suffix", summary.suff_penalty),
+        ] {
+            if let Some(penalty) = penalty.filter(|penalty| *penalty > 0.0) {
+                anomalies.push(format!(
+                    "the {} cache paid an overflow penalty of {}",
+                    name, penalty
+                ));
+            }
+        }
+        if let Some(clipped) = self.clipped_cost.as_ref().filter(|n| n.full > 0) {
+            anomalies.push(format!(
+                "{} of the full cache's cost was clipped",
+                clipped.full
+            ));
+        }
+        if anomalies.is_empty() {
+            report.push_str("none\n");
+        }
+        for anomaly in anomalies.iter() {
+            report.push_str(&format!("- {}\n", anomaly));
+        }
+        report
+    }
+    /// Lays out the human-readable reports of several runs of the same trace one after another,
+    /// each headed by what differed between them.
+    pub fn human_comparison(results: &BTreeMap<String, PrettyLogger>) -> String {
+        results
+            .iter()
+            .map(|(key, display)| {
+                format!(
+                    "{}\n{}\n\n{}",
+                    key,
+                    "=".repeat(key.len()),
+                    display.human_report()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+    /// Gets the JSON Schema describing the serialized form of a pretty logger.
+    pub fn schema() -> String {
+        serde_json::to_string_pretty(&schemars::schema_for!(PrettyLogger)).unwrap()
+    }
+    /// Serializes the results to TOML with floats rounded to the given number of decimal places,
+    /// along with how many NaN or infinite floats had to be replaced with NON_FINITE_SENTINEL.
+    pub fn ser_logger(&self, precision: Option<u32>) -> (String, u32) {
+        to_finite_toml(self, precision)
+    }
+    /// Serializes the results of a matrix of runs along with the marginal value curve of each pair
+    /// of policies, and how many NaN or infinite floats had to be replaced.
+    pub fn ser_matrix(
+        results: &BTreeMap<String, PrettyLogger>,
+        marginal_value: &BTreeMap<String, MarginalValue>,
+        precision: Option<u32>,
+    ) -> (String, u32) {
+        to_finite_toml(
+            &MatrixResults {
+                runs: results,
+                marginal_value,
+            },
+            precision,
+        )
+    }
+    /// Lays out the results of a matrix of runs as readable reports, followed by the marginal value
+    /// curve of each pair of policies.
+    pub fn human_matrix(
+        results: &BTreeMap<String, PrettyLogger>,
+        marginal_value: &BTreeMap<String, MarginalValue>,
+    ) -> String {
+        let mut report = Self::human_comparison(results);
+        for (policies, curve) in marginal_value.iter() {
+            let title = format!("Marginal value of {}", policies);
+            report.push_str(&format!(
+                "\n{}\n{}\n\n{}",
+                title,
+                "=".repeat(title.len()),
+                curve.human_report()
+            ));
+        }
+        report
+    }
+    /// Serializes the results of several runs of the same trace, keyed by what differed between
+    /// them, along with how many NaN or infinite floats had to be replaced.
+    pub fn ser_comparison(
+        results: &BTreeMap<String, PrettyLogger>,
+        precision: Option<u32>,
+    ) -> (String, u32) {
+        to_finite_toml(results, precision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::landlord::{HitPolicy, RunResult, TiebreakingPolicy};
+    use std::sync::Arc;
+
+    fn lru(size: u32) -> Landlord<'static> {
+        Landlord::new(size, TiebreakingPolicy::Lru, Arc::new(HitPolicy::Lru)).with_seed(0)
+    }
+
+    // Builds a trace requesting the items with the given labels in order.
+    fn trace<'a>(items: &'a [Item], labels: &str) -> VecDeque<Request<'a>> {
+        labels
+            .chars()
+            .map(|label| {
+                let item = items
+                    .iter()
+                    .find(|n| *n.get_label() == label.to_string())
+                    .unwrap();
+                Request::new(item, None)
+            })
+            .collect()
+    }
+
+    fn items(labels: &str) -> Vec<Item> {
+        labels
+            .chars()
+            .map(|label| Item::new(label.to_string(), 1, 1))
+            .collect()
+    }
+
+    #[test]
+    fn checkpoint_holds_the_prefix_serviced_before_an_interruption() {
+        let items = items("abc");
+        let trace = trace(&items, "abcabcabca");
+        let path = std::env::temp_dir().join(format!("csim-checkpoint-{}", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let mut checkpoint = Checkpoint::new(path.clone(), 4);
+        // The run services all ten requests but the checkpoint was last written after eight, which
+        // is all that would survive a crash before the output is written.
+        let result = Landlord::run(
+            trace.clone(),
+            3,
+            Some(lru(2)),
+            lru(2),
+            Logger::new(&trace),
+            |event| checkpoint.observe(event),
+        );
+        let written: toml::Value = std::fs::read_to_string(&path).unwrap().parse().unwrap();
+        checkpoint.remove();
+        assert!(!Path::new(&path).exists());
+        let costs = |key: &str| -> Vec<i64> {
+            written[key]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|n| n.as_integer().unwrap())
+                .collect()
+        };
+        assert_eq!(written["requests"].as_integer(), Some(8));
+        let logger = &result.logger;
+        let full: Vec<i64> = (0..8).map(|i| logger.get_full_cost(i).into()).collect();
+        let suff: Vec<i64> = (0..8).map(|i| logger.get_suff_cost(i).into()).collect();
+        assert_eq!(costs("full_costs"), full);
+        assert_eq!(costs("suff_costs"), suff);
+        assert_eq!(&costs("suff_costs")[..3], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn decision_log_has_one_line_per_request() {
+        let items = items("abc");
+        let trace = trace(&items, "abcabcabca");
+        let path = std::env::temp_dir().join(format!("csim-decisions-{}", std::process::id()));
+        let mut log = DecisionLog::create(&path).unwrap();
+        let result = Landlord::run(
+            trace.clone(),
+            3,
+            Some(lru(2)),
+            lru(2),
+            Logger::new(&trace),
+            |event| log.observe(event),
+        );
+        log.finish().unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), trace.len());
+        let fields = ["hit", "cost", "pressure", "evicted", "resident", "occupied"];
+        for (index, line) in lines.iter().enumerate() {
+            assert_eq!(line["index"], index);
+            assert_eq!(line["label"], trace[index].get_label().as_str());
+            let full = line["full"].as_object().unwrap();
+            assert!(fields.iter().all(|field| full.contains_key(*field)));
+            let cost = result.logger.get_full_cost(index as u32);
+            assert_eq!(full["cost"], cost);
+            // Every item costs one, so only the free requests hit
+            assert_eq!(full["hit"], cost == 0);
+            assert!(full["resident"].as_u64().unwrap() <= 2);
+            for evicted in full["evicted"].as_array().unwrap() {
+                assert!(evicted["label"].is_string());
+                assert_eq!(evicted["reason"], "capacity");
+            }
+            // The suffix cache only has an entry once it starts servicing requests
+            assert_eq!(line.get("suff").is_some(), index >= 3);
+        }
+        // Two slots cycling over three items evict on every request once full
+        let evicted = lines[2]["full"]["evicted"].as_array().unwrap();
+        assert_eq!(evicted[0]["label"], "a");
+    }
+
+    #[test]
+    fn snapshots_hold_the_cache_state_after_that_many_requests() {
+        let items = items("abc");
+        let trace = trace(&items, "abcab");
+        let mut logger = Logger::new(&trace);
+        logger.set_snapshot_at([0, 3, 5].into());
+        let result = Landlord::run(trace.clone(), 3, Some(lru(2)), lru(2), logger, |_| {});
+        let snapshots = &result.logger.snapshots;
+        assert_eq!(
+            snapshots.iter().map(|n| n.index).collect::<Vec<_>>(),
+            vec![0, 3, 5]
+        );
+        // Each snapshot matches a cache that only ever serviced the requests before its index
+        for snapshot in snapshots.iter() {
+            let mut full = lru(2);
+            full.service_all(trace.range(..snapshot.index as usize));
+            assert_eq!(json(&snapshot.full), json(&CacheSnapshot::new(&full)));
+        }
+        let resident = |snapshot: &CacheSnapshot| snapshot.resident.keys().cloned().collect();
+        let labels = |labels: &[&str]| labels.iter().map(|n| n.to_string()).collect();
+        let full: Vec<Vec<String>> = snapshots.iter().map(|n| resident(&n.full)).collect();
+        assert_eq!(
+            full,
+            vec![labels(&[]), labels(&["b", "c"]), labels(&["a", "b"])]
+        );
+        // The suffix cache starts at the division, so it is still empty in the snapshot there
+        let suff = |n: usize| snapshots[n].suff.as_ref().unwrap();
+        assert_eq!((suff(1).clock, suff(1).resident.len()), (0, 0));
+        assert_eq!((suff(2).clock, resident(suff(2))), (2, labels(&["a", "b"])));
+        assert_eq!(suff(2).accesses["a"].accesses, 1);
+    }
+
+    #[test]
+    fn reconciled_counts_match_a_hand_worked_trace() {
+        let items = items("abc");
+        let trace = trace(&items, "abcaab");
+        let result = Landlord::run(
+            trace.clone(),
+            3,
+            Some(lru(2)),
+            lru(2),
+            Logger::new(&trace),
+            |_| {},
+        );
+        let totals = &result.logger.totals;
+        // Two slots over a, b, c: the full cache only hits the repeated a at index 4, and the
+        // suffix cache, starting empty at index 3, sees a, a, b and hits the same a.
+        assert_eq!(totals.full_requests, 6);
+        assert_eq!(totals.full_hits, 1);
+        assert_eq!(totals.full_cost, 5);
+        assert_eq!(totals.full_no_cache_cost, 6);
+        assert_eq!(totals.suff_requests, 3);
+        assert_eq!(totals.suff_hits, 1);
+        assert_eq!(totals.suff_cost, 2);
+        assert_eq!(totals.suff_no_cache_cost, 3);
+        // Both caches log an entry for every request, with zeros for the suffix before the division
+        assert_eq!(totals.suff_costs_logged, 6);
+        assert_eq!(totals.suff_pres_logged, 6);
+        assert_eq!(result.logger.suff_cost, [0, 0, 0, 1, 0, 1]);
+        assert!(result.logger.reconcile(6).is_ok());
+        let error = result.logger.reconcile(7).unwrap_err();
+        assert!(error.starts_with("Logged 6 full cache costs but expected 7"));
+    }
+
+    #[test]
+    fn sampled_credits_stay_between_zero_and_cost() {
+        // Every item costs the same, so the largest credit any sample may hold is that cost
+        let items: Vec<Item> = "abcdef"
+            .chars()
+            .zip([1, 2, 1, 3, 2, 1])
+            .map(|(label, size)| Item::new(label.to_string(), 6, size))
+            .collect();
+        let trace = trace(&items, "abcadbeafcbdaefcabdfeacbdfae");
+        let cache =
+            || Landlord::new(5, TiebreakingPolicy::Lru, Arc::new(HitPolicy::Half)).with_seed(0);
+        let mut logger = Logger::new(&trace);
+        logger.set_credit_sample(3);
+        let len = trace.len();
+        let result = Landlord::run(trace, 10, Some(cache()), cache(), logger, |_| {});
+        let samples = &result.logger.credit_distribution;
+        assert_eq!(samples.len(), (len - 1) / 3);
+        for sample in samples.iter() {
+            assert_eq!(sample.index % 3, 0);
+            let caches = std::iter::once(&sample.full).chain(sample.suff.as_ref());
+            for distribution in caches {
+                let histogram: u32 = distribution.histogram.iter().sum();
+                assert_eq!(histogram, distribution.resident);
+                if distribution.resident == 0 {
+                    assert!(distribution.min.is_none() && distribution.max.is_none());
+                    continue;
+                }
+                let (min, max) = (distribution.min.unwrap(), distribution.max.unwrap());
+                let median = distribution.median.unwrap();
+                assert!(0.0 <= min && min <= median && median <= max && max <= 6.0);
+            }
+        }
+        // Both caches evict as soon as they fill up, so some item has been drained below its cost
+        assert!(samples.iter().any(|n| n.full.min.unwrap() < 6.0));
+    }
+
+    // Gets the cells of each row of a summary table, keyed by the metric it is for.
+    fn table_rows(table: &str) -> BTreeMap<String, Vec<String>> {
+        table
+            .lines()
+            .filter(|line| line.starts_with('|'))
+            .map(|line| {
+                let mut cells = line
+                    .trim_matches('|')
+                    .split('|')
+                    .map(|n| n.trim().to_string());
+                (cells.next().unwrap(), cells.collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn summary_table_matches_the_serialized_summary() {
+        let items = items("abc");
+        let display = run_with(&trace(&items, "abcabcaab"), 2, 3, |_| {});
+        let written: toml::Value = display.ser_logger(None).0.parse().unwrap();
+        let summary = &written["summary"];
+        let rows = table_rows(&display.summary_table());
+        assert_eq!(rows["metric"], vec!["full", "suffix"]);
+        // The table prints the f32 each metric was computed as, which TOML widens to an f64
+        let number = |cell: &String| cell.parse::<f32>().unwrap();
+        let float = |key: &str| summary[key].as_float().unwrap() as f32;
+        let integer = |key: &str| summary[key].as_integer().unwrap() as f32;
+        assert_eq!(number(&rows["total cost"][0]), integer("full_cost"));
+        assert_eq!(number(&rows["total cost"][1]), integer("suff_cost"));
+        assert_eq!(number(&rows["hit ratio"][0]), float("full_hit_ratio"));
+        assert_eq!(number(&rows["hit ratio"][1]), float("suff_hit_ratio"));
+        assert_eq!(number(&rows["max pressure"][0]), float("full_max_pres"));
+        assert_eq!(number(&rows["no-cache cost"][0]), integer("no_cache_cost"));
+        assert_eq!(number(&rows["scr"][1]), float("scr"));
+
+        // Without a suffix cache, its column is dropped
+        let trace = trace(&items, "abcabcaab");
+        let result = Landlord::run(trace.clone(), 3, None, lru(2), Logger::new(&trace), |_| {});
+        let rows = table_rows(&PrettyLogger::new(result.logger).summary_table());
+        assert_eq!(rows["metric"], vec!["full"]);
+        assert!(!rows.contains_key("scr"));
+    }
+
+    #[test]
+    fn savings_are_the_no_cache_cost_less_what_each_cache_paid() {
+        let items = items("abc");
+        let display = run_with(&trace(&items, "abcaab"), 2, 3, |_| {});
+        let summary = &display.summary;
+        assert_eq!(
+            summary.full_savings,
+            summary.no_cache_cost - summary.full_cost
+        );
+        assert_eq!((summary.no_cache_cost, summary.full_savings), (6, 1));
+        assert_eq!(summary.full_savings_percent, 1.0 / 6.0 * 100.0);
+        assert_eq!(summary.suff_savings, Some(1));
+        assert_eq!(summary.suff_savings_percent, Some(1.0 / 3.0 * 100.0));
+        // Free items cost nothing with or without a cache, which saves nothing rather than NaN
+        let free: Vec<_> = "abc"
+            .chars()
+            .map(|label| Item::new(label.to_string(), 0, 1))
+            .collect();
+        let display = run_with(&trace(&free, "abcaab"), 2, 3, |_| {});
+        let summary = &display.summary;
+        assert_eq!((summary.no_cache_cost, summary.full_savings), (0, 0));
+        assert_eq!(summary.full_savings_percent, 0.0);
+        assert_eq!(summary.suff_savings_percent, Some(0.0));
+    }
+
+    // Checks a value against the parts of JSON Schema our generated schema uses, returning the path
+    // to the first part of the value that does not match.
+    fn validate(
+        value: &serde_json::Value,
+        schema: &serde_json::Value,
+        root: &serde_json::Value,
+        path: &str,
+    ) -> Result<(), String> {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/definitions/");
+            return validate(value, &root["definitions"][name], root, path);
+        }
+        if let Some(options) = schema["anyOf"].as_array() {
+            return match options
+                .iter()
+                .any(|n| validate(value, n, root, path).is_ok())
+            {
+                true => Ok(()),
+                false => Err(format!("{} matches none of its options", path)),
+            };
+        }
+        if let Some(options) = schema["allOf"].as_array() {
+            for option in options {
+                validate(value, option, root, path)?;
+            }
+        }
+        if let Some(variants) = schema["enum"].as_array() {
+            if !variants.contains(value) {
+                return Err(format!("{} is not one of {:?}", path, variants));
+            }
+        }
+        let types: Vec<&str> = match &schema["type"] {
+            serde_json::Value::String(name) => vec![name.as_str()],
+            serde_json::Value::Array(names) => names.iter().filter_map(|n| n.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        let matches = |name: &&str| match *name {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "boolean" => value.is_boolean(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "null" => value.is_null(),
+            _ => false,
+        };
+        if !types.is_empty() && !types.iter().any(matches) {
+            return Err(format!("{} is not of type {:?}", path, types));
+        }
+        if let Some(object) = value.as_object() {
+            for key in schema["required"].as_array().into_iter().flatten() {
+                if !object.contains_key(key.as_str().unwrap()) {
+                    return Err(format!("{} is missing {}", path, key));
+                }
+            }
+            for (key, field) in object.iter() {
+                let path = format!("{}.{}", path, key);
+                match schema["properties"].get(key) {
+                    Some(field_schema) => validate(field, field_schema, root, &path)?,
+                    None => match &schema["additionalProperties"] {
+                        serde_json::Value::Bool(false) => {
+                            return Err(format!("{} is not in the schema", path))
+                        }
+                        serde_json::Value::Object(_) => {
+                            validate(field, &schema["additionalProperties"], root, &path)?
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+        if let Some(values) = value.as_array() {
+            match &schema["items"] {
+                serde_json::Value::Array(items) => {
+                    for (i, (value, item)) in values.iter().zip(items.iter()).enumerate() {
+                        validate(value, item, root, &format!("{}[{}]", path, i))?;
+                    }
+                }
+                serde_json::Value::Object(_) => {
+                    for (i, value) in values.iter().enumerate() {
+                        validate(value, &schema["items"], root, &format!("{}[{}]", path, i))?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn schema_validates_the_output_of_real_runs() {
+        let schema: serde_json::Value = serde_json::from_str(&PrettyLogger::schema()).unwrap();
+        let items = items("abc");
+        let trace = trace(&items, "abcabcaabcbca");
+        let detailed = run_with(&trace, 2, 4, |logger| {
+            logger.set_epoch_length(5);
+            logger.set_thrash_detection(DEFAULT_THRASH_WINDOW, DEFAULT_THRASH_THRESHOLD);
+        });
+        let summary = run_with(&trace, 2, 4, |logger| logger.set_summary_only(true));
+        let result = Landlord::run(trace.clone(), 4, None, lru(2), Logger::new(&trace), |_| {});
+        let full_only = PrettyLogger::new(result.logger);
+        for display in [&detailed, &summary, &full_only] {
+            assert_eq!(validate(&json(display), &schema, &schema, "output"), Ok(()));
+        }
+        // A value of the wrong shape is caught
+        let mut broken = json(&detailed);
+        broken["summary"]["full_cost"] = serde_json::json!("lots");
+        assert!(validate(&broken, &schema, &schema, "output").is_err());
+    }
+
+    #[test]
+    fn per_item_series_round_trip_through_the_output() {
+        let items = items("abc");
+        let trace = trace(&items, "abcabcaab");
+        let display = run_with(&trace, 2, 4, |logger| logger.set_emit_per_item_series(true));
+        let written: toml::Value = display.ser_logger(None).0.parse().unwrap();
+        let series = |value: &toml::Value| -> Vec<i32> {
+            value
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|n| n.as_integer().unwrap() as i32)
+                .collect()
+        };
+        for (key, costs) in [
+            ("full_costs", &display.full_costs),
+            ("suff_costs", &display.suff_costs),
+        ] {
+            for label in ["a", "b", "c"] {
+                // Each item's series is the whole-trace series at the requests for that item
+                let expected: Vec<i32> = trace
+                    .iter()
+                    .zip(costs.iter())
+                    .filter(|(request, _)| request.get_label() == label)
+                    .map(|(_, cost)| *cost)
+                    .collect();
+                assert_eq!(series(&written["per_item_series"][key][label]), expected);
+            }
+        }
+        // Two lines are too few for the loop, so a only hits when it is requested twice in a row
+        assert_eq!(
+            series(&written["per_item_series"]["full_costs"]["a"]),
+            vec![1, 1, 1, 0]
+        );
+    }
+
+    #[test]
+    fn suffix_cache_is_idle_before_the_division_point() {
+        let items = items("abc");
+        let trace = trace(&items, "abcabcab");
+        for summary_only in [false, true] {
+            let mut logger = Logger::new(&trace);
+            logger.set_summary_only(summary_only);
+            let result = Landlord::run(trace.clone(), 3, Some(lru(2)), lru(2), logger, |_| {});
+            assert!(result.logger.is_suffix_idle_before(3));
+            if !summary_only {
+                // The suffix cache starts empty, so it faults on its first request
+                assert_eq!(result.logger.get_suff_cost(3), 1);
+                assert!(!result.logger.is_suffix_idle_before(4));
+            }
+        }
+        // A suffix cache that paid for a request in the prefix is caught in either mode
+        for summary_only in [false, true] {
+            let mut logger = Logger::new(&trace);
+            logger.set_summary_only(summary_only);
+            logger.log_suffix_start(3);
+            logger.log_cost(&trace[0], 1, RequestFullOrSuffix::Suff(false));
+            logger.log_pres(0.0, RequestFullOrSuffix::Suff(false));
+            assert!(!logger.is_suffix_idle_before(3));
+        }
+    }
+
+    #[test]
+    fn non_finite_floats_are_written_as_the_sentinel() {
+        #[derive(Serialize)]
+        struct Series {
+            scr: Vec<f32>,
+            mean: f64,
+        }
+        let series = Series {
+            scr: vec![0.5, f32::NAN, f32::INFINITY, f32::NEG_INFINITY],
+            mean: f64::NAN,
+        };
+        let (written, replaced) = to_finite_toml(&series, None);
+        assert_eq!(replaced, 4);
+        let parsed: toml::Value = written.parse().expect("Sanitized output should parse");
+        let scr: Vec<f64> = parsed["scr"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|n| n.as_float().unwrap())
+            .collect();
+        assert_eq!(
+            scr,
+            vec![
+                0.5,
+                NON_FINITE_SENTINEL,
+                NON_FINITE_SENTINEL,
+                -NON_FINITE_SENTINEL
+            ]
+        );
+        assert_eq!(parsed["mean"].as_float(), Some(NON_FINITE_SENTINEL));
+        assert!(!written.contains("nan") && !written.contains("inf"));
+        // Finite output is left alone
+        let finite = Series {
+            scr: vec![0.5],
+            mean: 1.0,
+        };
+        assert_eq!(
+            to_finite_toml(&finite, None),
+            (toml::to_string_pretty(&finite).unwrap(), 0)
+        );
+    }
+
+    #[test]
+    fn precision_rounds_every_float_written() {
+        let items = items("abc");
+        let display = run_with(&trace(&items, "abcaab"), 2, 3, |_| {});
+        let float = |written: &str, key: &str| {
+            let parsed: toml::Value = written.parse().unwrap();
+            parsed["summary"][key].as_float().unwrap()
+        };
+        // One saved out of six and out of three, which neither f32 nor f64 can write exactly
+        let (exact, _) = display.ser_logger(None);
+        let (rounded, replaced) = display.ser_logger(Some(2));
+        assert_eq!(replaced, 0);
+        assert_ne!(float(&exact, "full_savings_percent"), 16.67);
+        assert_eq!(float(&rounded, "full_savings_percent"), 16.67);
+        assert_eq!(float(&rounded, "suff_savings_percent"), 33.33);
+        assert_eq!(float(&rounded, "full_hit_ratio"), 0.17);
+        assert!(rounded.contains("full_savings_percent = 16.67\n"));
+        // Rounding reaches the floats nested in arrays too, and leaves the integers alone
+        #[derive(Serialize)]
+        struct Series {
+            pres: Vec<f32>,
+            cost: i64,
+        }
+        let series = Series {
+            pres: vec![0.123_45, 2.0 / 3.0],
+            cost: 7,
+        };
+        let (written, _) = to_finite_toml(&series, Some(3));
+        let parsed: toml::Value = written.parse().unwrap();
+        assert_eq!(parsed["pres"][0].as_float(), Some(0.123));
+        assert_eq!(parsed["pres"][1].as_float(), Some(0.667));
+        assert_eq!(parsed["cost"].as_integer(), Some(7));
+    }
+
+    #[test]
+    fn marginal_value_finds_the_knee_of_a_cyclic_trace() {
+        let items = items("abcd");
+        let trace = trace(&items, &"abcd".repeat(10));
+        let runs: Vec<(u32, PrettyLogger)> = (1..=6)
+            .map(|size| (size, run_with(&trace, size, 0, |_| {})))
+            .collect();
+        let runs: Vec<(u32, &PrettyLogger)> = runs.iter().map(|(size, n)| (*size, n)).collect();
+        // LRU misses every request of a cycle through four items until all four fit, after which
+        // only the first pass misses, so every cache line past the fourth is worth nothing
+        let curve = MarginalValue::new(&runs);
+        let saved: Vec<i64> = curve.points.iter().map(|n| n.cost_saved).collect();
+        assert_eq!(saved, vec![0, 0, 0, 36, 36, 36]);
+        assert_eq!(curve.points[3].marginal_value, Some(36.0));
+        assert_eq!(curve.points[4].marginal_value, Some(0.0));
+        assert_eq!(curve.knee, Some(4));
+        assert!(curve.human_report().contains("  <- knee\n"));
+        // Two sizes are only a straight line, and a curve that saves nothing has no knee
+        assert_eq!(MarginalValue::new(&runs[2..4]).knee, None);
+        assert_eq!(MarginalValue::new(&runs[3..]).knee, None);
+    }
+
+    fn json<T: Serialize>(value: &T) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    // Runs LRU caches of the given size over a trace, with only the running totals kept if asked.
+    fn run_lru(trace: &VecDeque<Request>, size: u32, summary_only: bool) -> PrettyLogger {
+        let mut logger = Logger::new(trace);
+        logger.set_summary_only(summary_only);
+        logger.set_thrash_detection(DEFAULT_THRASH_WINDOW, DEFAULT_THRASH_THRESHOLD);
+        let result = Landlord::run(trace.clone(), 4, Some(lru(size)), lru(size), logger, |_| {});
+        PrettyLogger::new(result.logger)
+    }
+
+    // Runs LRU caches of the given size over a trace, letting the caller set up the logger first.
+    fn run_with(
+        trace: &VecDeque<Request>,
+        size: u32,
+        div: u32,
+        configure: impl FnOnce(&mut Logger),
+    ) -> PrettyLogger {
+        let mut logger = Logger::new(trace);
+        configure(&mut logger);
+        let result = Landlord::run(
+            trace.clone(),
+            div,
+            Some(lru(size)),
+            lru(size),
+            logger,
+            |_| {},
+        );
+        PrettyLogger::new(result.logger)
+    }
+
+    #[test]
+    fn classes_are_aggregated_independently() {
+        let item = |label: &str, class: &str| -> Item {
+            let data = format!(
+                "label = '{}'\ncost = 2\nsize = 1\nclass = '{}'",
+                label, class
+            );
+            toml::from_str(&data).unwrap()
+        };
+        let items = vec![item("x", "img"), item("y", "img"), item("a", "api")];
+        // Everything fits, so only the first request for each item misses
+        let display = run_with(&trace(&items, "xaxyaa"), 3, 0, |_| {});
+        let classes = json(&display.classes);
+        let expected = |hits: u32, faults: u32| {
+            serde_json::json!({
+                "full_hits": hits,
+                "full_faults": faults,
+                "full_cost": 2 * faults,
+                "full_hit_ratio": hits as f32 / (hits + faults) as f32,
+                "suff_hits": hits,
+                "suff_faults": faults,
+                "suff_cost": 2 * faults,
+                "suff_hit_ratio": hits as f32 / (hits + faults) as f32,
+            })
+        };
+        assert_eq!(classes["img"], expected(1, 2));
+        assert_eq!(classes["api"], expected(2, 1));
+        assert_eq!(classes.as_object().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn epochs_report_each_part_of_the_trace_separately() {
+        let items = items("abc");
+        // Two items fit and are reused in the first epoch, then three loop through the cache in
+        // the second so that every request misses.
+        let trace = trace(&items, "abababcabcab");
+        let display = run_with(&trace, 2, 0, |logger| logger.set_epoch_length(6));
+        let epochs = display.epochs.as_ref().unwrap();
+        assert_eq!(epochs.len(), 2);
+        let (first, second) = (&epochs[0], &epochs[1]);
+        assert_eq!(
+            (first.start, first.end, second.start, second.end),
+            (0, 6, 6, 12)
+        );
+        assert_eq!(first.full_hit_ratio, 4.0 / 6.0);
+        assert_eq!(second.full_hit_ratio, 0.0);
+        assert_eq!((first.full_cost, second.full_cost), (2, 6));
+        assert_eq!(first.full_max_pres, 0.0);
+        assert!(second.full_max_pres > 0.0);
+    }
+
+    #[test]
+    fn refetched_items_show_fetch_amplification() {
+        let items = items("abcd");
+        // a, b and c loop through a cache with room for two of them, so every request refetches
+        let trace = trace(&items, "abcabcabcdd");
+        let display = run_with(&trace, 2, 0, |logger| logger.set_amortized_cost(true));
+        let amortized = json(display.amortized_cost.as_ref().unwrap());
+        let stats = |label: &str| {
+            let item = &amortized["items"][label];
+            (item["requests"].as_u64(), item["fetches"].as_u64())
+        };
+        assert_eq!(stats("a"), (Some(3), Some(3)));
+        assert_eq!(stats("d"), (Some(2), Some(1)));
+        assert_eq!(amortized["items"]["d"]["cost_per_request"], 0.5);
+        assert_eq!(amortized["cost_per_item"], 2.5);
+    }
+
+    #[test]
+    fn evictions_are_counted_by_reason() {
+        let big: Item = toml::from_str("label = 'b'\ncost = 10\nsize = 4\nchunks = 2").unwrap();
+        let items = vec![big, Item::new("s".to_string(), 1, 2)];
+        // s only needs one of b's chunks evicted to fit, then bringing the rest of b back in evicts
+        // s, which the suffix cache can only do once it has used up its overflow margin.
+        let trace = trace(&items, "bsb");
+        let result = Landlord::run(
+            trace.clone(),
+            0,
+            Some(lru(4).with_soft_limit(1, 0.0)),
+            lru(4),
+            Logger::new(&trace),
+            |_| {},
+        );
+        let evictions = json(&PrettyLogger::new(result.logger).evictions);
+        assert_eq!(
+            evictions,
+            serde_json::json!({
+                "full": { "capacity": 1, "chunk": 1 },
+                "suff": { "chunk": 1, "soft_limit": 1 },
+            })
+        );
+    }
+
+    #[test]
+    fn utilization_jumps_while_a_large_item_is_resident() {
+        let items = vec![
+            Item::new("s".to_string(), 1, 1),
+            Item::new("L".to_string(), 1, 4),
+        ];
+        let display = run_with(&trace(&items, "sLs"), 4, 1, |_| {});
+        assert_eq!(display.full_utilization, vec![0.25, 1.0, 0.25]);
+        // The suffix cache is idle, and so empty, before the division point
+        assert_eq!(display.suff_utilization, vec![0.0, 1.0, 0.25]);
+    }
+
+    #[test]
+    fn cumulative_costs_end_at_the_total_cost() {
+        let items = items("abc");
+        let display = run_with(&trace(&items, "abcabcaab"), 2, 3, |logger| {
+            logger.set_cumulative(true)
+        });
+        let full = display.cumulative_full_costs.as_ref().unwrap();
+        let suff = display.cumulative_suff_costs.as_ref().unwrap();
+        assert_eq!(full.back(), Some(&display.summary.full_cost));
+        assert_eq!(suff.back().copied(), display.summary.suff_cost);
+        // Each entry is the running sum of the per-request costs
+        let mut running = 0;
+        for (cost, cumulative) in display.full_costs.iter().zip(full.iter()) {
+            running += i64::from(*cost);
+            assert_eq!(*cumulative, running);
+        }
+    }
+
+    #[test]
+    fn cost_sums_do_not_wrap_past_the_u32_range() {
+        let big = 2_000_000_000;
+        let items: Vec<Item> = "abc"
+            .chars()
+            .map(|label| Item::new(label.to_string(), big, 1))
+            .collect();
+        let trace = trace(&items, "abcabc");
+        let result = Landlord::run(
+            trace.clone(),
+            3,
+            Some(lru(1)),
+            lru(1),
+            Logger::new(&trace),
+            |_| {},
+        );
+        // Every request misses, so the total is six times a cost close to i32::MAX
+        let total = 6 * i64::from(big);
+        assert!(total > i64::from(u32::MAX));
+        assert_eq!(result.logger.get_full_cost_range(6), total);
+        assert_eq!(result.logger.get_suff_cost_range(6), 3 * i64::from(big));
+        assert_eq!(result.logger.get_scr(6), 0.5);
+        let display = PrettyLogger::new(result.logger);
+        assert_eq!(display.summary.full_cost, total);
+        assert_eq!(display.summary.suff_cost, Some(3 * i64::from(big)));
+    }
+
+    #[test]
+    fn focus_only_narrows_the_logged_series() {
+        let items = items("abc");
+        let trace = trace(&items, "abcabcaabcb");
+        let run = |focus: bool| {
+            let mut logger = Logger::new(&trace);
+            if focus {
+                logger.set_focus(BTreeSet::from(["a".to_string()]));
+            }
+            Landlord::run(trace.clone(), 4, Some(lru(2)), lru(2), logger, |_| {})
+        };
+        let all = run(false);
+        let focused = run(true);
+        // The caches serviced every request either way
+        assert_eq!(focused.full.get_cache_state(), all.full.get_cache_state());
+        let suff = |result: &RunResult| result.suff.as_ref().unwrap().get_cache_state();
+        assert_eq!(suff(&focused), suff(&all));
+        assert_eq!(
+            (
+                focused.full_hits,
+                focused.full_faults,
+                focused.suff_hits,
+                focused.suff_faults
+            ),
+            (
+                all.full_hits,
+                all.full_faults,
+                all.suff_hits,
+                all.suff_faults
+            )
+        );
+
+        let all = PrettyLogger::new(all.logger);
+        let focused = PrettyLogger::new(focused.logger);
+        assert_eq!(
+            serde_json::to_value(&focused.summary).unwrap(),
+            serde_json::to_value(&all.summary).unwrap()
+        );
+        // Only the requests for a are left in the per-request series
+        let requests_for_a: Vec<usize> = trace
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| n.get_label() == "a")
+            .map(|(index, _)| index)
+            .collect();
+        let expected: Vec<i32> = requests_for_a.iter().map(|n| all.full_costs[*n]).collect();
+        assert_eq!(
+            focused.full_costs.iter().copied().collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(focused.full_states.len(), requests_for_a.len());
+        assert!(focused
+            .full_states
+            .iter()
+            .all(|state| state.keys().all(|label| label == "a")));
+        assert_eq!(focused.ind_scr.keys().collect::<Vec<_>>(), vec!["a"]);
+        assert!(all.full_costs.len() > focused.full_costs.len());
+    }
+
+    #[test]
+    fn focus_keeps_the_suffix_states_of_the_focused_requests() {
+        let items = items("abcd");
+        let trace = trace(&items, "abcabdacba");
+        let run = |focus: bool| {
+            let mut logger = Logger::new(&trace);
+            if focus {
+                logger.set_focus(BTreeSet::from(["a".to_string()]));
+            }
+            let result = Landlord::run(trace.clone(), 4, Some(lru(3)), lru(3), logger, |_| {});
+            PrettyLogger::new(result.logger)
+        };
+        let all = run(false);
+        let focused = run(true);
+        // The suffix states only start at the division, so of the requests for a at 0, 3, 6 and 9
+        // only the last two have one, which are the third and sixth suffix states
+        assert_eq!(all.suff_states.len(), 6);
+        let only_a = |state: &BTreeMap<String, (f32, u32)>| {
+            let mut state = state.clone();
+            state.retain(|label, _| label == "a");
+            state
+        };
+        let expected = vec![only_a(&all.suff_states[2]), only_a(&all.suff_states[5])];
+        assert_eq!(
+            focused.suff_states.iter().cloned().collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(focused.full_states.len(), 4);
+    }
+
+    #[test]
+    fn competitive_bound_matches_hand_computed_values() {
+        let bound = |k: u32, h: u32| -> Option<f64> {
+            let info =
+                serde_json::to_value(RunInfo::new("in.toml", 0).with_competitive_bound(k, h))
+                    .unwrap();
+            info.get("competitive_bound")
+                .map(|n| n["bound"].as_f64().unwrap())
+        };
+        // 4 / (4 - 2 + 1) and 10 / (10 - 4 + 1)
+        assert_eq!(bound(4, 2), Some(4.0 / 3.0));
+        assert_eq!(bound(10, 4), Some(10.0 / 7.0));
+        // Equal sizes give the classic bound of k, and a cache with h = 1 is 1-competitive
+        assert_eq!(bound(6, 6), Some(6.0));
+        assert_eq!(bound(6, 1), Some(1.0));
+        // A measured cache smaller than the one it is compared against has no bound
+        assert_eq!(bound(3, 4), None);
+    }
+
+    #[test]
+    fn hit_series_tells_zero_cost_misses_from_hits() {
+        let items = [
+            Item::new("a".to_string(), 0, 1),
+            Item::new("b".to_string(), 1, 1),
+        ];
+        // a misses for free, hits, is evicted by b and misses for free again
+        let display = run_with(&trace(&items, "aaba"), 1, 2, |_| {});
+        assert_eq!(display.full_costs, [0, 0, 1, 0]);
+        assert_eq!(display.full_hits, [false, true, false, false]);
+        // The suffix cache starts empty at b, so neither of its requests hit
+        assert_eq!(display.suff_hits, [false, false]);
+        assert_eq!(display.suff_costs, [0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn pressure_correlation_follows_the_series() {
+        // Synthetic series with known coefficients
+        let xs: Vec<f64> = (0..50).map(f64::from).collect();
+        let rising: Vec<f64> = xs.iter().map(|x| 3.0 * x + 2.0).collect();
+        let falling: Vec<f64> = xs.iter().map(|x| -x).collect();
+        assert!((pearson(&xs, &rising).unwrap() - 1.0).abs() < 1e-12);
+        assert!((pearson(&xs, &falling).unwrap() + 1.0).abs() < 1e-12);
+        let noisy = pearson(&[1.0, 2.0, 3.0, 4.0, 5.0], &[2.0, 1.0, 4.0, 3.0, 5.0]).unwrap();
+        assert!((noisy - 0.8).abs() < 1e-12);
+        assert_eq!(pearson(&xs, &vec![1.0; 50]), None);
+
+        // The full cache's coefficient is the one between its pressure and the running SCR
+        let items = items("abcd");
+        let trace = trace(&items, "abcdabcaabdcbbad");
+        let mut logger = Logger::new(&trace);
+        logger.set_pressure_correlation(true);
+        let result = Landlord::run(trace.clone(), 5, Some(lru(2)), lru(2), logger, |_| {});
+        let pressure: Vec<f64> = result
+            .logger
+            .full_pres
+            .iter()
+            .map(|n| f64::from(*n))
+            .collect();
+        let scr: Vec<f64> = (1..=trace.len() as u32)
+            .map(|n| f64::from(result.logger.get_scr(n)))
+            .collect();
+        let expected = pearson(&pressure, &scr).unwrap();
+        let display = PrettyLogger::new(result.logger);
+        let correlation = display.pressure_correlation.unwrap();
+        assert!((correlation.full.unwrap() - expected).abs() < 1e-9);
+        assert!(correlation.suff.unwrap().abs() <= 1.0);
+    }
+
+    #[test]
+    fn under_filled_cache_never_sees_pressure() {
+        let items = items("abc");
+        let trace = trace(&items, "abcabcbbacab");
+        // Every item fits, so no request ever needs an eviction
+        let roomy = run_with(&trace, 5, 4, |_| {});
+        let summary = &roomy.summary;
+        assert_eq!(summary.full_zero_pres, 12);
+        assert_eq!(summary.full_zero_pres_ratio, 1.0);
+        assert_eq!(summary.suff_zero_pres, Some(8));
+        assert_eq!(summary.suff_zero_pres_ratio, Some(1.0));
+        assert_eq!(summary.full_max_pres, 0.0);
+        // With room for only two, the faults on the third item apply pressure
+        let tight = run_with(&trace, 2, 4, |_| {});
+        let zero = tight.full_pres.iter().filter(|n| **n == 0.0).count() as u32;
+        assert_eq!(tight.summary.full_zero_pres, zero);
+        assert!(zero < 12);
+        assert!(tight.summary.full_zero_pres_ratio < 1.0);
+    }
+
+    #[test]
+    fn cost_budget_stops_the_run_once_spent() {
+        let items: Vec<Item> = "ab"
+            .chars()
+            .map(|label| Item::new(label.to_string(), 3, 1))
+            .collect();
+        // Every request misses at a cost of 3, so a budget of 10 is spent by the fourth request
+        let trace = trace(&items, "abababababab");
+        let display = run_with(&trace, 1, 2, |logger| logger.set_cost_budget(10));
+        assert_eq!(display.budget_stop, Some(4));
+        assert_eq!(display.full_costs, [3, 3, 3, 3]);
+        assert_eq!(display.summary.full_cost, 12);
+        // The suffix cache is only reported up to the same point
+        assert_eq!(display.suff_costs.len(), 4);
+        assert_eq!(display.summary.suff_cost, Some(6));
+        // A budget exactly met stops the run straight away
+        let display = run_with(&trace, 1, 2, |logger| logger.set_cost_budget(9));
+        assert_eq!(display.budget_stop, Some(3));
+        let display = run_with(&trace, 1, 2, |logger| logger.set_cost_budget(1000));
+        assert_eq!(display.budget_stop, None);
+        assert_eq!(display.full_costs.len(), 12);
+    }
+
+    #[test]
+    fn residency_intervals_of_a_small_trace() {
+        let items = items("abc");
+        let trace = trace(&items, "abcab");
+        let result = Landlord::run(trace.clone(), 0, None, lru(2), Logger::new(&trace), |_| {});
+        // Each arrival evicts the item that has waited longest since its last request, and a and b
+        // are still resident at the end
+        let expected = [
+            ("a", 0, 2),
+            ("b", 1, 3),
+            ("c", 2, 4),
+            ("a", 3, 5),
+            ("b", 4, 5),
+        ];
+        let intervals = result.logger.residency_intervals();
+        assert_eq!(
+            intervals
+                .iter()
+                .map(|(label, start, end)| (label.as_str(), *start, *end))
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(
+            result.logger.residency_csv(),
+            "label,resident_start_index,resident_end_index\n\
+             a,0,2\nb,1,3\nc,2,4\na,3,5\nb,4,5\n"
+        );
+
+        // Labels with commas or quotes are quoted so they stay in one column
+        let odd = [Item::new("x,\"y\"".to_string(), 1, 1)];
+        let trace: VecDeque<Request> = [Request::new(&odd[0], None)].into();
+        let result = Landlord::run(trace.clone(), 0, None, lru(1), Logger::new(&trace), |_| {});
+        assert!(result
+            .logger
+            .residency_csv()
+            .ends_with("\n\"x,\"\"y\"\"\",0,1\n"));
+    }
+
+    #[test]
+    fn human_report_includes_the_key_numbers() {
+        let items: Vec<Item> = [("a", 1), ("b", 2), ("c", 3)]
+            .iter()
+            .map(|(label, cost)| Item::new(label.to_string(), *cost, 1))
+            .collect();
+        let display = run_with(&trace(&items, "abcabacabc"), 2, 3, |logger| {
+            logger.log_run_info(RunInfo::new("in.toml", 7).with_competitive_bound(2, 2))
+        });
+        assert_eq!(display.summary.full_cost, 15);
+        let report = display.human_report();
+        for expected in [
+            "input:   in.toml",
+            "seed:    7",
+            "competitive bound: 2 (k = 2, h = 2)",
+            // b and c both cost the full cache 6 of its 15, and a the other 3
+            " 1. b  6  (40.0% of full cost)",
+            " 2. c  6  (40.0% of full cost)",
+            " 3. a  3  (20.0% of full cost)",
+            "full: mean 0.600, max 1, none on 4 of 10 requests, 6 evictions",
+            "Anomalies\n---------\nnone\n",
+        ] {
+            assert!(
+                report.contains(expected),
+                "{} is missing from\n{}",
+                expected,
+                report
+            );
+        }
+        assert!(report.contains(&display.summary_table()));
+        // The suffix cache paid 9 to the full cache's 15
+        let scr_row = report.lines().find(|n| n.starts_with("| scr")).unwrap();
+        assert!(scr_row.contains("0.6"));
+    }
+
+    #[test]
+    fn two_items_in_a_one_item_cache_thrash_on_every_request() {
+        let items = items("ab");
+        let trace = trace(&items, &"ab".repeat(10));
+        let configure = |logger: &mut Logger| logger.set_thrash_detection(4, 3);
+        // Each request evicts the other item, which is requested again straight after
+        let display = run_with(&trace, 1, 0, configure);
+        let thrashing = display.thrashing.as_ref().expect("Should thrash");
+        let times: Vec<(&str, u32)> = thrashing
+            .items
+            .iter()
+            .map(|n| (n.label.as_str(), n.times))
+            .collect();
+        assert_eq!(times, vec![("a", 9), ("b", 9)]);
+        assert!(display
+            .human_report()
+            .contains("the full cache thrashed on 2 items"));
+        // With room for both, nothing is ever evicted
+        assert!(run_with(&trace, 2, 0, configure).thrashing.is_none());
+    }
+
+    #[test]
+    fn time_buckets_rate_requests_and_costs_by_arrival() {
+        let items = [
+            Item::new("a".to_string(), 2, 1),
+            Item::new("b".to_string(), 3, 1),
+        ];
+        let arrivals = [10.0, 10.5, 11.0, 14.0, 14.2];
+        let trace: VecDeque<Request> = trace(&items, "ababa")
+            .into_iter()
+            .zip(arrivals)
+            .map(|(request, arrival)| request.with_timestamp(Some(arrival)))
+            .collect();
+        // Every request misses, three arrive in the first two seconds, none in the next two and
+        // two in the two after that
+        let display = run_with(&trace, 1, 0, |logger| logger.set_time_bucket(2.0, &trace));
+        let buckets = json(&display.time_buckets);
+        assert_eq!(buckets["unit"], "seconds");
+        let rates: Vec<(f64, u64, f64, i64, f64)> = buckets["buckets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|n| {
+                (
+                    n["start"].as_f64().unwrap(),
+                    n["requests"].as_u64().unwrap(),
+                    n["request_rate"].as_f64().unwrap(),
+                    n["full_cost"].as_i64().unwrap(),
+                    n["full_cost_rate"].as_f64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            rates,
+            vec![
+                (0.0, 3, 1.5, 7, 3.5),
+                (2.0, 0, 0.0, 0, 0.0),
+                (4.0, 2, 1.0, 5, 2.5)
+            ]
+        );
+
+        // Without timestamps the buckets are runs of two requests
+        let untimed = trace.iter().map(|n| n.with_timestamp(None)).collect();
+        let display = run_with(&untimed, 1, 0, |logger| {
+            logger.set_time_bucket(2.0, &untimed)
+        });
+        let buckets = json(&display.time_buckets);
+        assert_eq!(buckets["unit"], "requests");
+        let requests: Vec<u64> = buckets["buckets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|n| n["requests"].as_u64().unwrap())
+            .collect();
+        assert_eq!(requests, vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn run_info_records_the_command_line() {
+        let items = items("ab");
+        let run_info = RunInfo::new("traces/in put.toml", 7).with_derived_seeds(3, vec![7, 9]);
+        let display = run_with(&trace(&items, "abab"), 2, 0, |logger| {
+            logger.log_run_info(run_info)
+        });
+        let written: toml::Value = display.ser_logger(None).0.parse().unwrap();
+        let info = &written["run_info"];
+        let args: Vec<&str> = info["args"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|n| n.as_str().unwrap())
+            .collect();
+        assert_eq!(args, std::env::args().collect::<Vec<String>>());
+        assert_eq!(info["input"].as_str(), Some("traces/in put.toml"));
+        assert_eq!(info["version"].as_str(), Some(env!("CARGO_PKG_VERSION")));
+        assert_eq!(info["seed"].as_integer(), Some(7));
+        assert_eq!(info["base_seed"].as_integer(), Some(3));
+        assert!(info["timestamp"].as_integer().unwrap() > 0);
+    }
+
+    #[test]
+    fn scr_is_reported_as_zero_below_the_floor() {
+        let items = items("abc");
+        let trace = trace(&items, "abababc");
+        // The full cache only pays for the first a and b until c arrives, while the one line
+        // suffix cache misses on everything.
+        let scrs = |floor: i32| -> Vec<f32> {
+            let mut logger = Logger::new(&trace);
+            logger.set_scr_floor(floor);
+            let result = Landlord::run(trace.clone(), 0, Some(lru(1)), lru(3), logger, |_| {});
+            (1..=7).map(|n| result.logger.get_scr(n)).collect()
+        };
+        assert_eq!(scrs(0), vec![1.0, 1.0, 1.5, 2.0, 2.5, 3.0, 7.0 / 3.0]);
+        assert_eq!(scrs(3), vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 7.0 / 3.0]);
+    }
+
+    #[test]
+    fn miss_gaps_separate_periodic_and_irregular_items() {
+        let items = items("prxy");
+        // No two requests in a row are for the same item, so a one line cache misses on all of them
+        let display = run_with(&trace(&items, "prxprypxypyxpxr"), 1, 0, |logger| {
+            logger.set_miss_gaps(true)
+        });
+        let gaps = display.miss_gaps.as_ref().unwrap();
+        let spread = |label: &str| (gaps[label].min, gaps[label].mean, gaps[label].max);
+        assert_eq!(spread("p"), (3, 3.0, 3));
+        assert_eq!(spread("r"), (3, 6.5, 10));
+    }
+
+    #[test]
+    fn summary_only_totals_match_the_full_retention_run() {
+        let items = items("abc");
+        let trace = trace(&items, "abcabcabcabcaab");
+        let full = run_lru(&trace, 2, false);
+        let summary = run_lru(&trace, 2, true);
+        assert_eq!(json(&summary.summary), json(&full.summary));
+        assert_eq!(json(&summary.classes), json(&full.classes));
+        assert_eq!(json(&summary.evictions), json(&full.evictions));
+        assert!(summary.full_costs.is_empty() && summary.full_states.is_empty());
+        // A cache one item too small for a loop evicts every item just before it is requested
+        let thrashing = full
+            .thrashing
+            .as_ref()
+            .expect("LRU should thrash on a loop");
+        assert_eq!(thrashing.items.len(), 3);
+        assert_eq!(json(&summary.thrashing), json(&full.thrashing));
     }
 }
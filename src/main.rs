@@ -1,22 +1,44 @@
 // Clap is the command line parser
-use clap::Parser;
+use clap::{Parser, Subcommand};
 // Importing our landlord module
-use landlord::{HitPolicy, Item, Landlord, TiebreakingPolicy};
+use landlord::{
+    AdmissionPolicy, CostRounding, EvictionPolicy, Item, Landlord, PressureMode, Request,
+    RequestEvent, RunResult, TiebreakingPolicy, WritePolicy, DEFAULT_LRFU_LAMBDA,
+    DEFAULT_VICTIM_COST,
+};
 // Serde does serialization and deserialization
 use serde::Deserialize;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 // We need to include the logger to do cost and pressure logging
-use logger::{Logger, PrettyLogger};
+use logger::{
+    Checkpoint, DecisionLog, DivSweep, Logger, MarginalValue, PrettyLogger, RunInfo,
+    DEFAULT_THRASH_THRESHOLD, DEFAULT_THRASH_WINDOW, NON_FINITE_SENTINEL,
+};
+// The input module reads traces stored in formats other than TOML
+use input::{InputFormat, WeblogSize};
+// Zipf fitting characterizes the trace independently of any cache
+use analysis::{AccessEntropy, Baseline, BaselineCost, ZipfFit};
+// Hit policies are looked up by name in a registry of trait objects
+use policy::{PolicyRegistry, ReplacementPolicy};
+// Errors are reported with our own error type
+use error::SimError;
 // We need ordered floats to keep them properly in our cache map
 // Io and path are required for writing to our output file and getting our path buffer input.
-use std::io::Write;
-use std::path::PathBuf;
-// File system is required to actually read and write toml files. Env is required to read command
-// line arguments.
-use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+// Time is required to profile each phase of a run.
+use std::time::{Duration, Instant};
+// File system is required to actually write toml files. Env is required to read command line
+// arguments.
+use std::fs::File;
 
+pub mod analysis;
+pub mod error;
+pub mod input;
 pub mod landlord;
 pub mod logger;
+pub mod policy;
 
 // STRUCTS
 // ----------------------------------------------------------------------------
@@ -24,116 +46,1869 @@ pub mod logger;
 #[derive(Debug, Deserialize)]
 pub struct TraceInfo {
     items: Vec<Item>,
-    trace: Vec<String>,
+    trace: Vec<TraceEntry>,
+    // Other labels the trace may use to request an item, mapped to the item's own label. This is
+    // optional in the TOML file.
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+}
+
+// A matrix of cache configurations to run over a single trace. Every size is run with every pair of
+// hit and tiebreaking policies.
+#[derive(Debug, Deserialize)]
+pub struct MatrixInfo {
+    sizes: Vec<MatrixSize>,
+    policies: Vec<[String; 2]>,
+}
+
+// A cache size in a matrix, written the same way as an item's size.
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct MatrixSize(#[serde(deserialize_with = "input::deserialize_size")] u32);
+
+// A single entry in the trace table. Most entries are just an item's label, but an entry can also
+// be a table with a label and a cost that overrides the item's catalog cost for that request alone,
+// and may mark the request as a write rather than a read or give the time it arrived in seconds.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TraceEntry {
+    Label(String),
+    Override {
+        label: String,
+        cost: Option<i32>,
+        #[serde(default)]
+        write: bool,
+        timestamp: Option<f64>,
+    },
+}
+
+impl TraceEntry {
+    fn get_label(&self) -> &String {
+        match self {
+            TraceEntry::Label(label) => label,
+            TraceEntry::Override { label, .. } => label,
+        }
+    }
+    fn get_cost(&self) -> Option<i32> {
+        match self {
+            TraceEntry::Label(_) => None,
+            TraceEntry::Override { cost, .. } => *cost,
+        }
+    }
+    fn is_write(&self) -> bool {
+        match self {
+            TraceEntry::Label(_) => false,
+            TraceEntry::Override { write, .. } => *write,
+        }
+    }
+    fn get_timestamp(&self) -> Option<f64> {
+        match self {
+            TraceEntry::Label(_) => None,
+            TraceEntry::Override { timestamp, .. } => *timestamp,
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
 #[command(name = "csim")]
 #[command(version = "1.0")]
 #[command(about = "A simple cache simulator for the Landlord cache replacement policy")]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 pub struct Args {
     /// The path to the input TOML file
-    #[arg(short, long, value_name = "INPUT FILE")]
-    in_path: PathBuf,
+    #[arg(short, long, value_name = "INPUT FILE", required = true)]
+    in_path: Option<PathBuf>,
+
+    /// The format of the input file
+    #[arg(long, value_enum, default_value_t = InputFormat::Toml)]
+    format: InputFormat,
+
+    /// A TOML file mapping item labels to costs that replace their catalog costs
+    #[arg(long, value_name = "COST FILE")]
+    cost_override: Option<PathBuf>,
+
+    /// Which size a web log object gets when its reported size varies between requests
+    #[arg(long, value_enum, default_value_t = WeblogSize::First)]
+    weblog_size: WeblogSize,
+
+    /// Also write the resolved trace to this path as a web log for other simulators
+    #[arg(long, value_name = "WEBLOG FILE")]
+    export_weblog: Option<PathBuf>,
 
     /// The path to the TOML file we are saving to
-    #[arg(short, long, value_name = "OUTPUT FILE")]
-    out_path: String,
+    #[arg(short, long, value_name = "OUTPUT FILE", required = true)]
+    out_path: Option<String>,
 
-    /// The size of the caches we are running
-    #[arg(short, long, value_name = "CACHE SIZE")]
-    size: u32,
+    /// The size of the caches we are running, optionally with a unit such as 2GiB or 500MB. Falls
+    /// back on CSIM_SIZE
+    #[arg(short, long, value_name = "CACHE SIZE", value_parser = input::parse_size)]
+    size: Option<u32>,
 
-    /// The location in our trace where we should split prefix from suffix
-    #[arg(short, long, value_name = "PREFIX/SUFFIX DIVISION")]
-    div: u32,
+    /// Give the suffix cache its own size instead of the full cache's size
+    #[arg(long, value_name = "SUFFIX CACHE SIZE", value_parser = input::parse_size)]
+    suffix_size: Option<u32>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The index of the first request in the suffix; the suffix cache is idle before it. Several
+    /// comma-separated indices run a suffix cache from each, reporting each split. Falls back on
+    /// CSIM_DIV
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        value_name = "PREFIX/SUFFIX DIVISION"
+    )]
+    div: Vec<u32>,
+
+    /// The split between prefix and suffix as a fraction of the trace length
+    #[arg(long, value_name = "DIVISION FRACTION", conflicts_with = "div")]
+    div_frac: Option<f32>,
+
+    /// Only simulate the full cache, leaving the suffix cache and SCR out of the output
+    #[arg(long, conflicts_with_all = ["div", "div_frac", "suffix_size", "max_scr"])]
+    no_suffix: bool,
+
+    /// Run a suffix cache from every division point from min to max in steps of step, sharing a
+    /// single run of the full cache, and report how the SCR changes with the division point
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "MIN,MAX,STEP",
+        conflicts_with_all = [
+            "div",
+            "div_frac",
+            "no_suffix",
+            "matrix",
+            "compare_policies",
+            "repeat",
+            "target_hit_ratio",
+            "residency_csv",
+            "checkpoint",
+            "decision_log",
+            "reverse",
+            "cost_budget"
+        ]
+    )]
+    div_sweep: Vec<u32>,
 
-    /// The hit and tiebreaking policies for our caches
+    /// The hit and tiebreaking policies for our caches. Falls back on CSIM_POLICIES, which
+    /// separates them with a comma
     #[arg(short, long, num_args = 2, value_name = "HIT/TIEBREAKING POLICY")]
     policies: Vec<String>,
+
+    /// Search for the smallest cache size between --size and --max-size reaching this hit ratio
+    #[arg(long, value_name = "HIT RATIO", requires = "max_size")]
+    target_hit_ratio: Option<f32>,
+
+    /// The largest cache size to consider when searching for a target hit ratio
+    #[arg(long, value_name = "MAX CACHE SIZE", value_parser = input::parse_size)]
+    max_size: Option<u32>,
+
+    /// The decay between 0 (LFU) and 1 (LRU) used by the LRFU hit policy
+    #[arg(long, value_name = "LAMBDA", default_value_t = DEFAULT_LRFU_LAMBDA)]
+    lrfu_lambda: f32,
+
+    /// Comma-separated hit policies to run the trace with one after another, reporting each
+    #[arg(long, value_delimiter = ',', value_name = "HIT POLICIES")]
+    compare_policies: Vec<String>,
+
+    /// Run the trace this many times, each with a seed derived from --seed, reporting each run
+    #[arg(
+        long,
+        value_name = "RUNS",
+        conflicts_with = "compare_policies",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    repeat: Option<u32>,
+
+    /// Write a readable text report to the output file instead of TOML. Falls back on CSIM_HUMAN
+    #[arg(long)]
+    human: bool,
+
+    /// Round every float in the TOML output to this many decimal places
+    #[arg(
+        long,
+        value_name = "DIGITS",
+        value_parser = clap::value_parser!(u32).range(..=17),
+        conflicts_with = "human"
+    )]
+    precision: Option<u32>,
+
+    /// Run every combination of the cache sizes and policies listed in this TOML file over the
+    /// trace, each on its own thread, reporting each combination
+    #[arg(
+        long,
+        value_name = "MATRIX FILE",
+        conflicts_with_all = [
+            "size",
+            "policies",
+            "compare_policies",
+            "repeat",
+            "target_hit_ratio",
+            "residency_csv"
+        ]
+    )]
+    matrix: Option<PathBuf>,
+
+    /// Also report, for each pair of policies in a matrix, the cost the full cache saves per extra
+    /// cache line between consecutive sizes and the size past which that stops paying off
+    #[arg(long)]
+    marginal_value: bool,
+
+    /// The algorithm the caches evict with. Greedy-Dual ignores --pressure-mode
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = EvictionPolicy::Landlord,
+        conflicts_with = "pressure_mode"
+    )]
+    policy: EvictionPolicy,
+
+    /// How credit is decremented across resident items when the cache is under pressure
+    #[arg(long, value_enum, default_value_t = PressureMode::Uniform)]
+    pressure_mode: PressureMode,
+
+    /// Only simulate the last this many requests of the trace
+    #[arg(long, value_name = "REQUESTS")]
+    tail: Option<usize>,
+
+    /// Simulate the requests in reverse order. Divisions still split the trace between the same
+    /// two requests
+    #[arg(long)]
+    reverse: bool,
+
+    /// Only simulate requests for a deterministic sample of roughly this fraction of the items
+    #[arg(long, value_name = "RATE")]
+    sample_rate: Option<f32>,
+
+    /// Let the caches overflow their size by up to this many cache lines before evicting
+    #[arg(long, value_name = "MARGIN")]
+    soft_limit: Option<u32>,
+
+    /// The penalty charged per request for each cache line a soft-limited cache is over its size
+    #[arg(
+        long,
+        value_name = "PENALTY",
+        default_value_t = 1.0,
+        requires = "soft_limit"
+    )]
+    overflow_penalty: f32,
+
+    /// Find the item to evict with a priority queue instead of scanning the whole cache. Only
+    /// available under uniform pressure or Greedy-Dual
+    #[arg(long)]
+    credit_queue: bool,
+
+    /// Halve the credit an item is given every this many requests after it was first requested
+    #[arg(long, value_name = "REQUESTS")]
+    decay_half_life: Option<f32>,
+
+    /// Never charge more than this for a single request, reporting how much cost was cut off
+    #[arg(long, value_name = "COST", value_parser = clap::value_parser!(i32).range(1..))]
+    clip_cost: Option<i32>,
+
+    /// Also cap the credit items are given at the cost clip
+    #[arg(long, requires = "clip_cost")]
+    clip_credit: bool,
+
+    /// How the caches pay for requests the trace marks as writes
+    #[arg(long, value_enum, default_value_t = WritePolicy::Through)]
+    write_policy: WritePolicy,
+
+    /// How fractional costs, such as those of partial and victim cache hits, are rounded to the
+    /// whole cost that is charged
+    #[arg(long, value_enum, default_value_t = CostRounding::Truncate)]
+    cost_rounding: CostRounding,
+
+    /// Which faulting items are admitted into cache
+    #[arg(long, value_enum, default_value_t = AdmissionPolicy::Always)]
+    admission: AdmissionPolicy,
+
+    /// Count the faults that would have hit in a cache this many lines larger
+    #[arg(long, value_name = "GHOST SIZE")]
+    ghost_size: Option<u32>,
+
+    /// Keep this many lines of the most recently evicted items in a victim cache, promoting them
+    /// back on a fault for a fraction of their cost
+    #[arg(long, value_name = "VICTIM SIZE")]
+    victim_size: Option<u32>,
+
+    /// The fraction of an item's cost charged for promoting it back out of the victim cache
+    #[arg(
+        long,
+        value_name = "FRACTION",
+        default_value_t = DEFAULT_VICTIM_COST,
+        requires = "victim_size"
+    )]
+    victim_cost: f32,
+
+    /// Exempt items from eviction for this many requests after they are brought into cache. Items
+    /// may set their own with a min_residency field
+    #[arg(long, value_name = "REQUESTS", conflicts_with = "credit_queue")]
+    min_residency: Option<u32>,
+
+    /// Never cache items larger than this, so every request for them misses
+    #[arg(long, value_name = "SIZE", value_parser = input::parse_size)]
+    max_cacheable_size: Option<u32>,
+
+    /// Seed for the random hit and tiebreaking policies. Falls back on CSIM_SEED, and a random seed
+    /// is chosen if neither is given
+    #[arg(long, value_parser = clap::value_parser!(u64).range(..=MAX_SEED))]
+    seed: Option<u64>,
+
+    /// Refuse to run a random hit or tiebreaking policy unless --seed is given
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Comma-separated labels of the only items whose requests and statistics are output
+    #[arg(long, value_delimiter = ',', value_name = "LABELS")]
+    focus: Vec<String>,
+
+    /// Comma-separated labels of items to place in both caches before the trace starts
+    #[arg(long, value_delimiter = ',', value_name = "LABELS")]
+    preload: Vec<String>,
+
+    /// Report statistics for each consecutive epoch of this many requests
+    #[arg(
+        long,
+        value_name = "EPOCH LENGTH",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    epoch: Option<u32>,
+
+    /// Report the request and cost rates over buckets of this many seconds of arrival time, or of
+    /// this many requests if the trace has no timestamps
+    #[arg(long, value_name = "SECONDS")]
+    time_bucket: Option<f64>,
+
+    /// Only warn, instead of stopping, if the timestamps in the trace go back in time
+    #[arg(long)]
+    allow_unsorted: bool,
+
+    /// Include each item's fetch count and amortized fetch cost in the output
+    #[arg(long)]
+    amortized_cost: bool,
+
+    /// Include the running totals of the full and suffix costs in the output
+    #[arg(long)]
+    cumulative: bool,
+
+    /// Include the correlation between each cache's pressure and the running SCR in the output
+    #[arg(long)]
+    pressure_correlation: bool,
+
+    /// Include the min, mean and max gap between consecutive misses on each item in the output
+    #[arg(long)]
+    miss_gaps: bool,
+
+    /// Report the full cache as thrashing on items requested again within this many requests of
+    /// being evicted
+    #[arg(long, value_name = "REQUESTS", default_value_t = DEFAULT_THRASH_WINDOW)]
+    thrash_window: u32,
+
+    /// How many times an item must be requested again soon after being evicted to count as
+    /// thrashing
+    #[arg(long, value_name = "TIMES", default_value_t = DEFAULT_THRASH_THRESHOLD)]
+    thrash_threshold: u32,
+
+    /// Exit with code 3 after writing the output if the SCR is above this bound
+    #[arg(long, value_name = "SCR")]
+    max_scr: Option<f32>,
+
+    /// Stop servicing requests once the full cache has paid this much in total
+    #[arg(long, value_name = "COST")]
+    cost_budget: Option<u64>,
+
+    /// Write the intervals during which each item was resident in the full cache to this CSV file
+    #[arg(
+        long,
+        value_name = "CSV FILE",
+        conflicts_with_all = ["compare_policies", "repeat"]
+    )]
+    residency_csv: Option<PathBuf>,
+
+    /// Comma-separated request indices at which to snapshot the complete state of both caches, each
+    /// taken after exactly that many requests
+    #[arg(long, value_delimiter = ',', value_name = "INDICES")]
+    snapshot_at: Vec<u32>,
+
+    /// Sample the minimum, median and maximum credit of the items resident in both caches, along
+    /// with a histogram of how much of their cost they still hold, every this many requests
+    #[arg(long, value_name = "REQUESTS", value_parser = clap::value_parser!(u32).range(1..))]
+    credit_sample: Option<u32>,
+
+    /// Write the costs so far to <OUTPUT FILE>.partial every this many requests
+    #[arg(
+        long,
+        value_name = "REQUESTS",
+        conflicts_with_all = ["matrix", "compare_policies", "repeat", "target_hit_ratio"]
+    )]
+    checkpoint: Option<usize>,
+
+    /// Stream what both caches decided on every request to this file as JSON lines: the request,
+    /// whether it hit, what was evicted, the pressure applied and the size of the resident set
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["matrix", "compare_policies", "repeat"]
+    )]
+    decision_log: Option<PathBuf>,
+
+    /// Report an SCR of 0 until the full cost it is computed against reaches this floor
+    #[arg(long, value_name = "COST", default_value_t = 0)]
+    scr_floor: i32,
+
+    /// Include every item's full and suffix cost series in the output (this can be very large)
+    #[arg(long)]
+    emit_per_item_series: bool,
+
+    /// Keep only running totals instead of the per-request series, so memory does not grow with
+    /// the trace. The output holds the summary and per-item statistics but no series
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "epoch", "time_bucket", "cumulative", "pressure_correlation", "miss_gaps",
+            "emit_per_item_series", "focus", "residency_csv"
+        ]
+    )]
+    summary_only: bool,
+
+    /// Check after each run that the requests logged for the full and suffix caches add up for
+    /// the division point and trace length, stopping with an error if they do not
+    #[arg(long)]
+    validate: bool,
+
+    /// Include the reuse distance histogram of the trace in the output
+    #[arg(long)]
+    reuse_distance: bool,
+
+    /// Include the LRU miss ratio curve over every cache size, derived from the reuse distances
+    #[arg(long)]
+    mrc: bool,
+
+    /// Predict the hit ratio of an LRU cache of the full cache's size with the Che approximation
+    /// and compare it against the simulated hit ratio
+    #[arg(long)]
+    che: bool,
+
+    /// Fit a Zipf distribution to how often each item is requested and record its exponent
+    #[arg(long)]
+    fit_zipf: bool,
+
+    /// Also work out what a simple baseline cache pays for the trace and compare against it
+    #[arg(long, value_enum, value_name = "BASELINE")]
+    baseline: Option<Baseline>,
+
+    /// Import the decisions an optimal offline cache made for every request, as hits or costs,
+    /// and compare against it as the baseline
+    #[arg(long, value_name = "OPT FILE", conflicts_with = "baseline")]
+    opt_decisions: Option<PathBuf>,
+
+    /// Record the entropy of how the trace's requests are spread over its items
+    #[arg(long)]
+    entropy: bool,
+
+    /// Include histograms of the item sizes and costs in the catalog in the output
+    #[arg(long)]
+    catalog_histograms: bool,
+
+    /// Sync the output file to disk before exiting so the results survive a crash or power loss
+    #[arg(long)]
+    fsync: bool,
+
+    /// Print how long each phase of the run took to stderr
+    #[arg(long)]
+    profile: bool,
+
+    /// Do not print the summary table after the run
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+// Commands that do something other than running a simulation. These do not need any of the
+// simulation arguments.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print every available hit and tiebreaking policy
+    ListPolicies,
+    /// Print the JSON Schema describing the output file
+    Schema,
 }
 
 // This is the data structure that serde will deserialize the items.toml file into. The items must
 // be an exhaustive list of the costs and sizes of the items requested in our trace. Meanwhile, the
 // trace is just a vector of strings where each string is an item's label.
 
-// Converts our deserialized trace of strings into a trace of items
-fn strings_to_items(trace: &TraceInfo) -> VecDeque<&Item> {
+// Converts our deserialized trace of strings into a trace of items, resolving aliased labels to the
+// items they stand for. Also returns how many requests used an alias. A request for a label that
+// is not in the items table is an error naming the request, or the alias it was made under.
+fn strings_to_items(trace: &TraceInfo) -> Result<(VecDeque<Request<'_>>, u32), SimError> {
+    let items: BTreeMap<&String, &Item> = trace.items.iter().map(|n| (n.get_label(), n)).collect();
     let mut requests = VecDeque::new();
-    let mut counter = 0;
-    for request in trace.trace.iter() {
-        for item in trace.items.iter() {
-            if *item.get_label() == *request {
-                requests.push_back(item);
-                counter += 1;
+    let mut canonicalized = 0;
+    for (index, request) in trace.trace.iter().enumerate() {
+        let alias = trace.aliases.get_key_value(request.get_label());
+        let label = alias.map_or(request.get_label(), |(_, label)| label);
+        let item = match (items.get(label), alias) {
+            (Some(item), _) => *item,
+            (None, Some((alias, label))) => {
+                return Err(SimError::UnknownAlias {
+                    alias: alias.clone(),
+                    label: label.clone(),
+                })
+            }
+            (None, None) => {
+                return Err(SimError::UnknownItem {
+                    index,
+                    label: label.clone(),
+                })
+            }
+        };
+        canonicalized += u32::from(alias.is_some());
+        requests.push_back(
+            Request::new(item, request.get_cost())
+                .with_write(request.is_write())
+                .with_timestamp(request.get_timestamp()),
+        );
+    }
+    Ok((requests, canonicalized))
+}
+
+// The number of average-sized items below which we warn that the cache is too small for Landlord
+// to do anything but thrash.
+const MIN_AVERAGE_ITEMS: f32 = 2.0;
+
+// Checks that a cache of the given size can hold every item in our catalog, returning an error
+// message naming the first item that cannot fit. Items must also take up some space, since credit
+// is normalized by size and a zero-size item would divide by zero. We also warn if the cache can
+// hold fewer than MIN_AVERAGE_ITEMS items of average size since that is a degenerate regime for
+// Landlord. Items over the max cacheable size never enter the cache, so they do not need to fit.
+fn validate_size(items: &[Item], size: u32, max_cacheable_size: Option<u32>) -> Result<(), String> {
+    for item in items.iter() {
+        if item.get_size() == 0 {
+            return Err(format!("Item {} has size 0", item.get_label()));
+        }
+        if item.get_size() > size && max_cacheable_size.is_none_or(|max| item.get_size() <= max) {
+            return Err(format!(
+                "Item {} has size {} exceeding cache size of {}",
+                item.get_label(),
+                item.get_size(),
+                size
+            ));
+        }
+    }
+    if let Some(warning) = size_warning(items, size) {
+        println!("Warning: {}", warning);
+    }
+    Ok(())
+}
+
+// Gets the warning for a cache that holds fewer than MIN_AVERAGE_ITEMS items of average size, if
+// it is that small.
+fn size_warning(items: &[Item], size: u32) -> Option<String> {
+    if items.is_empty() {
+        return None;
+    }
+    let average = items.iter().map(|n| n.get_size() as f32).sum::<f32>() / items.len() as f32;
+    ((size as f32) < MIN_AVERAGE_ITEMS * average).then(|| {
+        format!(
+            "cache size {} holds fewer than {} items of average size {}",
+            size, MIN_AVERAGE_ITEMS, average
+        )
+    })
+}
+
+// Checks that the timestamps in a trace never go back in time, returning an error message naming
+// the first request that arrived before the one timestamped ahead of it. Requests without a
+// timestamp are skipped over.
+fn validate_timestamps(trace: &[TraceEntry]) -> Result<(), String> {
+    let mut latest: Option<f64> = None;
+    for (index, entry) in trace.iter().enumerate() {
+        let Some(timestamp) = entry.get_timestamp() else {
+            continue;
+        };
+        if let Some(latest) = latest.filter(|latest| timestamp < *latest) {
+            return Err(format!(
+                "Request {} for {} has timestamp {} before the earlier timestamp {}",
+                index,
+                entry.get_label(),
+                timestamp,
+                latest
+            ));
+        }
+        latest = Some(timestamp);
+    }
+    Ok(())
+}
+
+// The exit code when an SCR exceeds --max-scr. This is distinct from the exit code clap uses for
+// invalid arguments so that pipelines can tell the two apart.
+const SCR_EXCEEDED_EXIT_CODE: i32 = 3;
+
+// The largest seed we accept. Seeds are recorded in the output and TOML integers are signed, so we
+// keep them within the range of an i64.
+const MAX_SEED: u64 = i64::MAX as u64;
+
+// Derives the seed of one repetition of a run from the base seed with a splitmix64 step, so that
+// neighbouring runs get unrelated seeds. The result is kept within MAX_SEED.
+fn derive_seed(base: u64, run: u32) -> u64 {
+    let mut z = base.wrapping_add((run as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    (z ^ (z >> 31)) & MAX_SEED
+}
+
+// The number of buckets labels are hashed into when sampling. A label is sampled if its bucket
+// is below the sample rate times this many buckets.
+const SAMPLE_BUCKETS: u64 = 10_000;
+
+// Deciding whether an item is part of the sample by hashing its label. We use FNV-1a rather than
+// the standard library's hasher so that the same items are sampled across runs and Rust versions.
+fn is_sampled(label: &str, rate: f32) -> bool {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in label.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash % SAMPLE_BUCKETS < (rate as f64 * SAMPLE_BUCKETS as f64) as u64
+}
+
+// Narrows the resolved trace down to the requests we were asked to simulate, returning an error
+// message if the sample rate is out of range.
+fn select_requests(args: &Args, trace: &mut VecDeque<Request>) -> Result<(), String> {
+    // Keeping every request for a sample of the items and dropping all requests for the rest
+    if let Some(rate) = args.sample_rate {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err("Sample rate must be between 0 and 1".to_string());
+        }
+        trace.retain(|request| is_sampled(request.get_label(), rate));
+    }
+    // Dropping everything but the end of the trace if we only want to look at its tail
+    if let Some(tail) = args.tail {
+        let len = trace.len();
+        trace.drain(..len.saturating_sub(tail));
+    }
+    // Running the trace backward to see how much the results depend on the order of the requests
+    if args.reverse {
+        trace.make_contiguous().reverse();
+    }
+    Ok(())
+}
+
+// Works out the indices at which our trace is split into prefix and suffix, either directly from
+// --div or by scaling --div-frac by the length of the trace. Several splits must be in ascending
+// order so that each suffix is nested inside the one before it.
+fn resolve_divs(divs: &[u32], div_frac: Option<f32>, len: usize) -> Result<Vec<u32>, String> {
+    match (divs.is_empty(), div_frac) {
+        (false, Some(_)) => Err("Only one of --div and --div-frac may be supplied".to_string()),
+        (false, None) => {
+            for div in divs.iter() {
+                if *div as usize > len {
+                    return Err(format!(
+                        "Division {} is past the end of the trace of length {}",
+                        div, len
+                    ));
+                }
+            }
+            if let Some(pair) = divs.windows(2).find(|pair| pair[0] >= pair[1]) {
+                return Err(format!(
+                    "Divisions must be in ascending order, but {} comes before {}",
+                    pair[0], pair[1]
+                ));
+            }
+            Ok(divs.to_vec())
+        }
+        (true, Some(frac)) => {
+            if !(0.0..=1.0).contains(&frac) {
+                return Err(format!("Division fraction {} is not between 0 and 1", frac));
+            }
+            Ok(vec![(frac * len as f32) as u32])
+        }
+        (true, None) => Err("One of --div, --div-frac or CSIM_DIV must be supplied".to_string()),
+    }
+}
+
+// Works out the division points of a --div-sweep from its minimum, maximum and step. The maximum
+// is only included if a whole number of steps lands on it.
+fn resolve_div_sweep(sweep: &[u32], len: usize) -> Result<Vec<u32>, String> {
+    let [min, max, step] = sweep else {
+        return Err(format!(
+            "--div-sweep takes a minimum, maximum and step, but was given {} values",
+            sweep.len()
+        ));
+    };
+    if *step == 0 {
+        return Err("--div-sweep needs a step of at least 1".to_string());
+    }
+    if min > max {
+        return Err(format!(
+            "--div-sweep minimum {} is greater than its maximum {}",
+            min, max
+        ));
+    }
+    if *max as usize > len {
+        return Err(format!(
+            "Division {} is past the end of the trace of length {}",
+            max, len
+        ));
+    }
+    Ok((*min..=*max).step_by(*step as usize).collect())
+}
+
+// Reads the environment variable with the given name, treating one that is not set as absent.
+fn read_env(name: &str) -> Result<Option<String>, String> {
+    match std::env::var(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(std::env::VarError::NotUnicode(_)) => Err(format!("{} is not valid unicode", name)),
+    }
+}
+
+// Fills in the options left off the command line from their CSIM_* environment variables, so that
+// runs orchestrated from containers or CI can be configured without building up a command line.
+// A flag always takes precedence over its variable, and a variable is also ignored when a flag it
+// would conflict with was given instead, such as --div-frac in place of --div or a --matrix in
+// place of --size and --policies.
+fn apply_env_fallbacks(args: &mut Args) -> Result<(), String> {
+    if args.size.is_none() && args.matrix.is_none() {
+        if let Some(value) = read_env("CSIM_SIZE")? {
+            let size = input::parse_size(&value).map_err(|e| format!("CSIM_SIZE: {}", e))?;
+            args.size = Some(size);
+        }
+    }
+    if args.div.is_empty()
+        && args.div_frac.is_none()
+        && !args.no_suffix
+        && args.div_sweep.is_empty()
+    {
+        if let Some(value) = read_env("CSIM_DIV")? {
+            args.div = value
+                .split(',')
+                .map(|div| {
+                    div.trim()
+                        .parse()
+                        .map_err(|_| format!("CSIM_DIV: {} is not a valid division", div))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+    }
+    if args.policies.is_empty() && args.matrix.is_none() {
+        if let Some(value) = read_env("CSIM_POLICIES")? {
+            let names: Vec<String> = value.split(',').map(|n| n.trim().to_string()).collect();
+            if names.len() != 2 {
+                return Err(format!(
+                    "CSIM_POLICIES must be a hit and a tiebreaking policy separated by a comma, \
+                     but was {}",
+                    value
+                ));
+            }
+            args.policies = names;
+        }
+    }
+    if args.seed.is_none() {
+        if let Some(value) = read_env("CSIM_SEED")? {
+            match value.trim().parse() {
+                Ok(seed) if seed <= MAX_SEED => args.seed = Some(seed),
+                _ => {
+                    return Err(format!(
+                        "CSIM_SEED: {} is not a seed between 0 and {}",
+                        value, MAX_SEED
+                    ))
+                }
+            }
+        }
+    }
+    if !args.human && args.precision.is_none() {
+        if let Some(value) = read_env("CSIM_HUMAN")? {
+            args.human = match value.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" => true,
+                "0" | "false" | "" => false,
+                _ => return Err(format!("CSIM_HUMAN: {} is not true or false", value)),
+            };
+        }
+    }
+    Ok(())
+}
+
+// Binary searches the cache sizes between min and max for the smallest size at which the full
+// trace cache reaches the target hit ratio, re-running the simulation at each probed size. Every
+// probed cache is built with the same options as a single run, but only the full cache is
+// simulated since the suffix cache has no bearing on its hit ratio. Returns None if even the max
+// size falls short of the target.
+fn search_size(
+    args: &Args,
+    items: &[Item],
+    trace: &VecDeque<Request>,
+    (min, max): (u32, u32),
+    target: f32,
+    policies: (Arc<dyn ReplacementPolicy>, TiebreakingPolicy),
+    seed: u64,
+) -> Result<Option<u32>, String> {
+    let hit_ratio = |size| {
+        let (_, f) = build_caches(args, items, size, policies.clone(), seed)?;
+        let div = trace.len() as u32;
+        let result = Landlord::run(trace.clone(), div, None, f, Logger::new(trace), |_| {});
+        Ok::<_, String>(result.full_hit_ratio())
+    };
+    if min > max || hit_ratio(max)? < target {
+        return Ok(None);
+    }
+    let (mut lo, mut hi) = (min, max);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if hit_ratio(mid)? >= target {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    Ok(Some(lo))
+}
+
+// Creates the suffix and full caches with every option we were given, seeded with our initial
+// resident set. There is no suffix cache if we were asked to only simulate the full cache.
+fn build_caches<'a>(
+    args: &Args,
+    items: &'a [Item],
+    size: u32,
+    (hit_policy, tiebreaking_policy): (Arc<dyn ReplacementPolicy>, TiebreakingPolicy),
+    seed: u64,
+) -> Result<(Option<Landlord<'a>>, Landlord<'a>), String> {
+    let margin = args.soft_limit.unwrap_or(0);
+    let suffix_size = args.suffix_size.unwrap_or(size);
+    let mut s = Landlord::new(suffix_size, tiebreaking_policy, hit_policy.clone())
+        .with_pressure_mode(args.pressure_mode)
+        .with_soft_limit(margin, args.overflow_penalty)
+        .with_seed(seed)
+        .with_admission(args.admission)
+        .with_eviction_policy(args.policy)
+        .with_write_policy(args.write_policy)
+        .with_cost_rounding(args.cost_rounding);
+    let mut f = Landlord::new(size, tiebreaking_policy, hit_policy)
+        .with_pressure_mode(args.pressure_mode)
+        .with_soft_limit(margin, args.overflow_penalty)
+        .with_seed(seed)
+        .with_admission(args.admission)
+        .with_eviction_policy(args.policy)
+        .with_write_policy(args.write_policy)
+        .with_cost_rounding(args.cost_rounding);
+    if let Some(ghost_size) = args.ghost_size {
+        s = s.with_ghost(ghost_size);
+        f = f.with_ghost(ghost_size);
+    }
+    if let Some(victim_size) = args.victim_size {
+        s = s.with_victim(victim_size, args.victim_cost);
+        f = f.with_victim(victim_size, args.victim_cost);
+    }
+    // Items can ask for a minimum residency of their own even without a global one
+    if args.min_residency.is_some() || items.iter().any(|n| n.get_min_residency().is_some()) {
+        let min_residency = args.min_residency.unwrap_or(0);
+        s = s.with_min_residency(min_residency);
+        f = f.with_min_residency(min_residency);
+    }
+    if let Some(max_cacheable_size) = args.max_cacheable_size {
+        s = s.with_max_cacheable_size(max_cacheable_size);
+        f = f.with_max_cacheable_size(max_cacheable_size);
+    }
+    if let Some(half_life) = args.decay_half_life {
+        s = s.with_decay_half_life(half_life);
+        f = f.with_decay_half_life(half_life);
+    }
+    if let Some(clip_cost) = args.clip_cost {
+        s = s.with_clip_cost(clip_cost, args.clip_credit);
+        f = f.with_clip_cost(clip_cost, args.clip_credit);
+    }
+    if args.credit_queue {
+        s = s.with_credit_queue();
+        f = f.with_credit_queue();
+    }
+    // Seeding both caches with our initial resident set
+    for label in args.preload.iter() {
+        let item = match items.iter().find(|n| n.get_label() == label) {
+            Some(item) => item,
+            None => {
+                return Err(format!(
+                    "Preloaded item {} is not in the items table",
+                    label
+                ))
+            }
+        };
+        if !s.preload(item) || !f.preload(item) {
+            return Err(format!(
+                "Preloaded items do not fit in a cache of size {}",
+                size.min(suffix_size)
+            ));
+        }
+    }
+    Ok(((!args.no_suffix).then_some(s), f))
+}
+
+// Works out the hit and tiebreaking policies from their names, in that order, looking the hit
+// policy up in our registry.
+fn parse_policies(
+    names: &[String],
+    registry: &PolicyRegistry,
+) -> Result<(Arc<dyn ReplacementPolicy>, TiebreakingPolicy), String> {
+    let [hit, tiebreak] = names else {
+        return Err("Could not parse policy input".to_string());
+    };
+    let hit_policy = match registry.get(hit) {
+        Some(policy) => {
+            if policy
+                .crf_lambda()
+                .is_some_and(|lambda| !(0.0..=1.0).contains(&lambda))
+            {
+                return Err("LRFU lambda must be between 0 and 1".to_string());
             }
+            policy
+        }
+        None => {
+            return Err(format!(
+                "Invalid hit policy; select one of: {{{}}}",
+                policy_names(registry.policies().map(|policy| policy.name()))
+            ))
+        }
+    };
+    let tiebreaking_policy = match TiebreakingPolicy::from_name(tiebreak) {
+        Some(policy) => policy,
+        None => {
+            return Err(format!(
+                "Invalid tiebreaking policy; select one of: {{{}}}",
+                policy_names(
+                    TiebreakingPolicy::variants()
+                        .iter()
+                        .map(TiebreakingPolicy::name)
+                )
+            ))
         }
+    };
+    Ok((hit_policy, tiebreaking_policy))
+}
+
+// Creates a logger that records everything our options ask for.
+fn build_logger(
+    args: &Args,
+    items: &[Item],
+    trace: &VecDeque<Request>,
+    size: u32,
+    run_info: RunInfo,
+    baseline: Option<&BaselineCost>,
+) -> Logger {
+    let mut logger = Logger::new(trace);
+    let suffix_size = args.suffix_size.unwrap_or(size);
+    // The competitive bound compares the suffix cache against the full cache, so it needs both
+    if args.no_suffix {
+        logger.log_run_info(run_info);
+    } else {
+        logger.log_run_info(run_info.with_competitive_bound(suffix_size, size));
+    }
+    if let Some(epoch_length) = args.epoch {
+        logger.set_epoch_length(epoch_length);
+    }
+    if let Some(duration) = args.time_bucket {
+        logger.set_time_bucket(duration, trace);
+    }
+    logger.set_summary_only(args.summary_only);
+    logger.set_amortized_cost(args.amortized_cost);
+    logger.set_cumulative(args.cumulative);
+    logger.set_pressure_correlation(args.pressure_correlation);
+    logger.set_scr_floor(args.scr_floor);
+    if let Some(cost_budget) = args.cost_budget {
+        logger.set_cost_budget(cost_budget);
+    }
+    logger.set_miss_gaps(args.miss_gaps);
+    logger.set_thrash_detection(args.thrash_window, args.thrash_threshold);
+    if args.emit_per_item_series {
+        println!("Warning: per-item series repeat the cost of every request and can bloat output");
+        logger.set_emit_per_item_series(true);
+    }
+    if args.reuse_distance {
+        logger.log_reuse_distance(trace);
+    }
+    if args.mrc {
+        logger.log_miss_ratio_curve(trace);
+    }
+    if args.che {
+        logger.log_che(trace, size);
+    }
+    if let Some(baseline) = baseline {
+        logger.log_baseline(baseline.clone());
+    }
+    if args.catalog_histograms {
+        logger.log_catalog(items);
     }
-    if counter as usize != trace.trace.len() {
-        panic!("Invalid trace generation");
+    if !args.snapshot_at.is_empty() {
+        logger.set_snapshot_at(args.snapshot_at.iter().copied().collect());
+    }
+    if let Some(every) = args.credit_sample {
+        logger.set_credit_sample(every);
+    }
+    if !args.focus.is_empty() {
+        logger.set_focus(args.focus.iter().cloned().collect());
+    }
+    logger
+}
+
+// Reconciles what a run logged against the trace it ran over if we were asked to validate runs.
+fn validate_run(args: &Args, logger: &Logger, trace_len: usize) -> Result<(), String> {
+    if args.validate {
+        logger.reconcile(trace_len)
+    } else {
+        Ok(())
+    }
+}
+
+// Everything that stays the same across the runs over one resolved trace: our options, the items
+// and requests, and what we worked out about the trace before simulating any cache over it. When
+// the run is repeated, every repetition also records the base seed and the seeds derived from it.
+struct RunContext<'a> {
+    args: &'a Args,
+    items: &'a [Item],
+    trace: &'a VecDeque<Request<'a>>,
+    input: String,
+    canonicalized: Option<u32>,
+    zipf_fit: Option<ZipfFit>,
+    entropy: Option<AccessEntropy>,
+    derived_seeds: Option<(u64, Vec<u64>)>,
+    baseline: Option<BaselineCost>,
+}
+
+impl RunContext<'_> {
+    // Describes a run with the given seed for its output.
+    fn run_info(&self, seed: u64) -> RunInfo {
+        let run_info = match &self.derived_seeds {
+            Some((base_seed, seeds)) => {
+                RunInfo::new(&self.input, seed).with_derived_seeds(*base_seed, seeds.clone())
+            }
+            None => RunInfo::new(&self.input, seed),
+        };
+        run_info
+            .with_canonicalized(self.canonicalized)
+            .with_zipf_fit(self.zipf_fit)
+            .with_entropy(self.entropy)
+    }
+}
+
+// Runs the trace once through caches of the given size and policies split at the given division,
+// logging everything our options ask for and showing the observer every request. The run is
+// reconciled against the trace if we were asked to validate runs.
+fn run_once<'a>(
+    context: &RunContext<'a>,
+    size: u32,
+    div: u32,
+    policies: (Arc<dyn ReplacementPolicy>, TiebreakingPolicy),
+    seed: u64,
+    observer: impl FnMut(&RequestEvent),
+) -> Result<RunResult<'a>, SimError> {
+    let (args, items, trace) = (context.args, context.items, context.trace);
+    let (s, f) = build_caches(args, items, size, policies, seed).map_err(SimError::Setup)?;
+    let run_info = context.run_info(seed);
+    let logger = build_logger(
+        args,
+        items,
+        trace,
+        size,
+        run_info,
+        context.baseline.as_ref(),
+    );
+    let result = Landlord::run(trace.clone(), div, s, f, logger, observer);
+    validate_run(args, &result.logger, trace.len()).map_err(SimError::Reconcile)?;
+    Ok(result)
+}
+
+// Serializes the results of several runs, or lays them out as readable reports if we were asked
+// for a human-readable report.
+fn render_comparison(args: &Args, results: &BTreeMap<String, PrettyLogger>) -> String {
+    if args.human {
+        PrettyLogger::human_comparison(results)
+    } else {
+        let (output, replaced) = PrettyLogger::ser_comparison(results, args.precision);
+        warn_non_finite(replaced);
+        output
+    }
+}
+
+// Warns that some values in the output were NaN or infinite and were written as a sentinel.
+fn warn_non_finite(replaced: u32) {
+    if replaced > 0 {
+        println!(
+            "Warning: {} NaN or infinite values were written as {:e}",
+            replaced, NON_FINITE_SENTINEL
+        );
+    }
+}
+
+// How many times we retry a write that failed with a transient error, and how long we wait before
+// the first retry. The wait doubles after every retry.
+const WRITE_RETRIES: u32 = 3;
+const WRITE_BACKOFF: Duration = Duration::from_millis(100);
+
+// Writes all of the data, retrying with backoff if the write fails with an error that may go away
+// on its own, such as an interrupted call or a timeout on a network filesystem. A write may accept
+// part of the data before failing, so we keep track of how much was written and pick up from there
+// rather than writing any of it twice. Any other error, or a transient one that outlasts our
+// retries, is returned.
+fn write_with_retry(writer: &mut impl Write, data: &[u8]) -> Result<(), SimError> {
+    let mut backoff = WRITE_BACKOFF;
+    let mut retries = 0;
+    let mut written = 0;
+    loop {
+        let attempt = if written < data.len() {
+            match writer.write(&data[written..]) {
+                Ok(0) => Err(std::io::Error::new(
+                    ErrorKind::WriteZero,
+                    "failed to write the whole output",
+                )),
+                Ok(n) => {
+                    written += n;
+                    continue;
+                }
+                Err(e) => Err(e),
+            }
+        } else {
+            writer.flush()
+        };
+        match attempt {
+            Ok(()) => return Ok(()),
+            Err(e)
+                if retries < WRITE_RETRIES
+                    && matches!(
+                        e.kind(),
+                        ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut
+                    ) =>
+            {
+                std::thread::sleep(backoff);
+                backoff *= 2;
+                retries += 1;
+            }
+            Err(e) => return Err(SimError::IoError(e)),
+        }
     }
-    requests
+}
+
+// Records how much wall time each phase of a run takes so that --profile can report it.
+struct Profile {
+    last: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Profile {
+    fn new() -> Self {
+        Self {
+            last: Instant::now(),
+            phases: Vec::new(),
+        }
+    }
+    // Ends the current phase, attributing all of the time since the last phase ended to it.
+    fn lap(&mut self, phase: &'static str) {
+        let now = Instant::now();
+        self.phases.push((phase, now - self.last));
+        self.last = now;
+    }
+    fn report(&self) {
+        let total: Duration = self.phases.iter().map(|n| n.1).sum();
+        eprintln!("Profile:");
+        for (phase, time) in self.phases.iter() {
+            eprintln!(
+                "  {:<18} {:>12.3?} ({:.1}%)",
+                phase,
+                time,
+                100.0 * time.as_secs_f64() / total.as_secs_f64().max(f64::EPSILON)
+            );
+        }
+        eprintln!("  {:<18} {:>12.3?}", "total", total);
+    }
+}
+
+// Joins policy names into a comma-separated list for error messages.
+fn policy_names<'a>(names: impl Iterator<Item = &'a str>) -> String {
+    names.collect::<Vec<&str>>().join(", ")
+}
+
+// Lists every hit and tiebreaking policy and every baseline along with how to select them and
+// tune them from the command line.
+fn list_policies(registry: &PolicyRegistry) -> String {
+    let mut listing = String::from("Usage: --policies <HIT POLICY> <TIEBREAKING POLICY>\n\n");
+    listing.push_str("Hit policies:\n");
+    for policy in registry.policies() {
+        listing.push_str(&format!(
+            "  {:<8} {}\n",
+            policy.name(),
+            policy.description()
+        ));
+        for alias in policy.aliases() {
+            listing.push_str(&format!("  {:<8} alias for {}\n", alias, policy.name()));
+        }
+        for (option, description) in policy.parameters() {
+            listing.push_str(&format!("  {:<8} {}  {}\n", "", option, description));
+        }
+    }
+    listing.push_str("\nTiebreaking policies:\n");
+    for policy in TiebreakingPolicy::variants() {
+        listing.push_str(&format!(
+            "  {:<8} {}\n",
+            policy.name(),
+            policy.description()
+        ));
+    }
+    listing.push_str("\nBaselines:\n");
+    let baselines = Baseline::variants();
+    let width = baselines.iter().map(|n| n.usage().len()).max().unwrap_or(0);
+    for baseline in baselines {
+        listing.push_str(&format!(
+            "  {:<width$}  {}\n",
+            baseline.usage(),
+            baseline.description(),
+            width = width
+        ));
+    }
+    listing
 }
 
 fn main() {
-    let args = Args::parse();
-    // Parsing our data into a string
-    let data: &str = &fs::read_to_string(args.in_path).expect("Could not read file");
-    // Converting our string into a trace struct with the TOML crate
-    let raw_trace: TraceInfo = toml::from_str(data).expect("Could not convert TOML file");
+    let mut args = Args::parse();
+    // Every hit policy we can run, which is where policies beyond the built-in ones are registered
+    let registry = PolicyRegistry::with_builtins(args.lrfu_lambda);
+    match args.command {
+        Some(Command::ListPolicies) => {
+            print!("{}", list_policies(&registry));
+            return;
+        }
+        Some(Command::Schema) => {
+            println!("{}", PrettyLogger::schema());
+            return;
+        }
+        None => {}
+    }
+    if let Err(msg) = apply_env_fallbacks(&mut args) {
+        println!("{}", msg);
+        return;
+    }
+    // Clap guarantees these are present whenever we are not running a subcommand.
+    let in_path = args.in_path.clone().expect("Input path is required");
+    let out_path = args.out_path.clone().expect("Output path is required");
+    // A matrix lists the cache sizes and policies to run in place of --size and --policies
+    let matrix = match &args.matrix {
+        Some(matrix_path) => match input::read_matrix(matrix_path) {
+            Ok(matrix) => Some(matrix),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        },
+        None => None,
+    };
+    let sizes: Vec<u32> = match &matrix {
+        Some(matrix) => matrix.sizes.iter().map(|size| size.0).collect(),
+        None => match args.size {
+            Some(size) => vec![size],
+            None => {
+                println!("One of --size, --matrix or CSIM_SIZE must be supplied");
+                return;
+            }
+        },
+    };
+    if sizes.is_empty() || matrix.as_ref().is_some_and(|n| n.policies.is_empty()) {
+        println!("A matrix needs at least one size and one pair of policies");
+        return;
+    }
+    if args.marginal_value && matrix.is_none() {
+        println!("--marginal-value needs the sizes of a --matrix to compare");
+        return;
+    }
+    // Every run but a matrix uses a single size
+    let size = sizes[0];
+    let mut profile = Profile::new();
+    // Reading our trace struct out of the input file
+    let raw_trace = match args.format {
+        InputFormat::Toml => input::read_toml(&in_path),
+        InputFormat::Sqlite => input::read_sqlite(&in_path),
+        InputFormat::Weblog => input::read_weblog(&in_path, args.weblog_size),
+    };
+    let mut raw_trace: TraceInfo = match raw_trace {
+        Ok(raw_trace) => raw_trace,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    // Swapping in the costs from a separate cost model so the same catalog can be priced
+    // differently
+    if let Some(cost_path) = &args.cost_override {
+        let costs = match input::read_cost_override(cost_path) {
+            Ok(costs) => costs,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        for label in input::apply_cost_override(&mut raw_trace.items, &costs) {
+            println!("Warning: cost override for {} matches no item", label);
+        }
+    }
+    profile.lap("parsing");
+    // Time-based statistics assume requests arrive in order, so we check that before anything else
+    if let Err(msg) = validate_timestamps(&raw_trace.trace) {
+        if !args.allow_unsorted {
+            println!("{}", msg);
+            return;
+        }
+        println!("Warning: {}", msg);
+    }
     // Performing some input sanitzation to ensure we don't have any items too large to accomodate
-    for item in raw_trace.items.iter() {
-        if item.get_size() > args.size {
+    for size in sizes.iter() {
+        if let Err(msg) = validate_size(&raw_trace.items, *size, args.max_cacheable_size) {
+            println!("{}", msg);
+            return;
+        }
+    }
+    if let Some(suffix_size) = args.suffix_size {
+        if let Err(msg) = validate_size(&raw_trace.items, suffix_size, args.max_cacheable_size) {
+            println!("{}", msg);
+            return;
+        }
+    }
+    // A credit queue relies on every item losing credit at a fixed rate, which the recency and
+    // frequency pressure modes do not do.
+    if args.credit_queue
+        && args.policy == EvictionPolicy::Landlord
+        && !matches!(args.pressure_mode, PressureMode::Uniform)
+    {
+        println!("--credit-queue can only be used with uniform pressure");
+        return;
+    }
+    // The credit queue takes rent from every item at once, so it cannot pass over exempt items
+    if args.credit_queue
+        && raw_trace
+            .items
+            .iter()
+            .any(|n| n.get_min_residency().is_some())
+    {
+        println!("--credit-queue cannot be used with items that have a minimum residency");
+        return;
+    }
+    if !(0.0..=1.0).contains(&args.victim_cost) {
+        println!("Victim cost must be between 0 and 1");
+        return;
+    }
+    if args
+        .decay_half_life
+        .is_some_and(|half_life| half_life <= 0.0)
+    {
+        println!("Decay half-life must be positive");
+        return;
+    }
+    if args
+        .time_bucket
+        .is_some_and(|duration| !(duration > 0.0 && duration.is_finite()))
+    {
+        println!("Time bucket duration must be positive");
+        return;
+    }
+    // Making sure we are not asked to focus on items that do not exist
+    for label in args.focus.iter() {
+        if !raw_trace.items.iter().any(|n| n.get_label() == label) {
+            println!("Focused item {} is not in the items table", label);
+            return;
+        }
+    }
+    // Converting strings into items with our utility function
+    let (mut item_trace, canonicalized) = match strings_to_items(&raw_trace) {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    // Only traces with an aliases table report how many requests went through it
+    let canonicalized = (!raw_trace.aliases.is_empty()).then_some(canonicalized);
+    if let Err(msg) = select_requests(&args, &mut item_trace) {
+        println!("{}", msg);
+        return;
+    }
+    profile.lap("trace resolution");
+    // Fitting a Zipf distribution to the trace we are about to simulate if we were asked to
+    let zipf_fit = if args.fit_zipf {
+        let zipf_fit = ZipfFit::new(&item_trace);
+        if zipf_fit.is_none() {
             println!(
-                "Item {} has size {} exceeding cache size of {}",
-                item.get_label(),
-                item.get_size(),
-                args.size
+                "Warning: the trace requests too few distinct items to fit a Zipf distribution"
             );
+        }
+        zipf_fit
+    } else {
+        None
+    };
+    let entropy = if args.entropy {
+        AccessEntropy::new(&item_trace)
+    } else {
+        None
+    };
+    // Every run simulates the same trace, so the baseline it is compared against is only worked
+    // out once
+    let baseline = match (&args.opt_decisions, args.baseline) {
+        (Some(opt_path), _) => {
+            let baseline = input::read_opt_decisions(opt_path)
+                .map_err(|e| e.to_string())
+                .and_then(|decisions| BaselineCost::from_opt(&decisions, &item_trace));
+            match baseline {
+                Ok(baseline) => Some(baseline),
+                Err(msg) => {
+                    println!("{}", msg);
+                    return;
+                }
+            }
+        }
+        (None, Some(baseline)) => Some(BaselineCost::new(baseline, &item_trace)),
+        (None, None) => None,
+    };
+    // Writing out the requests we are about to simulate so other tools can be run on the same trace
+    if let Some(export_path) = &args.export_weblog {
+        if let Err(e) = input::write_weblog(export_path, &item_trace) {
+            println!("Could not export trace to {}: {}", export_path.display(), e);
             return;
         }
     }
-    // Converting strings into items with our utility function
-    let item_trace = strings_to_items(&raw_trace);
-    // Creating our two caches
-    if args.policies.len() > 2 {
-        println!("Could not parse policy input");
+    // Finding where our prefix ends and our suffix begins. Without a suffix, the whole trace is
+    // the prefix.
+    let divs = if args.no_suffix {
+        vec![item_trace.len() as u32]
+    } else if !args.div_sweep.is_empty() {
+        match resolve_div_sweep(&args.div_sweep, item_trace.len()) {
+            Ok(divs) => divs,
+            Err(msg) => {
+                println!("{}", msg);
+                return;
+            }
+        }
+    } else {
+        match resolve_divs(&args.div, args.div_frac, item_trace.len()) {
+            // In a reversed trace, the request that was n requests from the start is n requests
+            // from the end, so each division moves there to split the same two requests
+            Ok(divs) if args.reverse => divs
+                .iter()
+                .rev()
+                .map(|div| item_trace.len() as u32 - div)
+                .collect(),
+            Ok(divs) => divs,
+            Err(msg) => {
+                println!("{}", msg);
+                return;
+            }
+        }
+    };
+    // A snapshot can be taken anywhere from before the first request to after the last
+    if let Some(index) = args
+        .snapshot_at
+        .iter()
+        .find(|index| **index as usize > item_trace.len())
+    {
+        println!(
+            "Snapshot index {} is past the end of the trace of length {}",
+            index,
+            item_trace.len()
+        );
         return;
     }
-    // Generating our hit policy from the input
-    let hit_policy = match args.policies[0].to_ascii_uppercase().as_str() {
-        "LRU" => HitPolicy::Lru,
-        "FIFO" => HitPolicy::Fifo,
-        "RAND" => HitPolicy::Rand,
-        "HALF" => HitPolicy::Half,
-        _ => {
-            println!("Invalid hit policy; select one of: {{LRU, FIFO, RAND, HALF}}");
+    // Several divisions are reported as separate runs, so they cannot be combined with the other
+    // options that run the trace more than once or only make sense for a single run
+    if divs.len() > 1
+        && args.div_sweep.is_empty()
+        && (matrix.is_some()
+            || !args.compare_policies.is_empty()
+            || args.repeat.is_some()
+            || args.target_hit_ratio.is_some()
+            || args.residency_csv.is_some()
+            || args.checkpoint.is_some()
+            || args.decision_log.is_some())
+    {
+        println!(
+            "Several divisions cannot be combined with --matrix, --compare-policies, --repeat, \
+             --target-hit-ratio, --residency-csv, --checkpoint or --decision-log"
+        );
+        return;
+    }
+    let div = divs[0];
+    // Generating our hit and tiebreaking policies from the input. Every run but a matrix uses a
+    // single pair of them.
+    let names: Vec<&[String]> = match &matrix {
+        Some(matrix) => matrix
+            .policies
+            .iter()
+            .map(|names| names.as_slice())
+            .collect(),
+        None => vec![args.policies.as_slice()],
+    };
+    let policies: Vec<(Arc<dyn ReplacementPolicy>, TiebreakingPolicy)> = match names
+        .iter()
+        .map(|names| parse_policies(names, &registry))
+        .collect()
+    {
+        Ok(policies) => policies,
+        Err(msg) => {
+            println!("{}", msg);
             return;
         }
     };
-    // Generating our tiebreaking policy from the input
-    let tiebreaking_policy = match args.policies[1].to_ascii_uppercase().as_str() {
-        "LRU" => TiebreakingPolicy::Lru,
-        "FIFO" => TiebreakingPolicy::Fifo,
-        "RAND" => TiebreakingPolicy::Rand,
-        _ => {
-            println!("Invalid tiebreaking policy; select one of: {{LRU, FIFO, RAND}}");
+    let (hit_policy, tiebreaking_policy) = policies[0].clone();
+    // A deterministic run may only use random policies when we were told how to seed them, since
+    // a seed we pick ourselves could not be known before the run.
+    if args.deterministic && args.seed.is_none() {
+        let compared = args
+            .compare_policies
+            .iter()
+            .filter_map(|name| registry.get(name));
+        if policies
+            .iter()
+            .any(|(_, tiebreak)| matches!(tiebreak, TiebreakingPolicy::Rand))
+            || policies
+                .iter()
+                .map(|(hit, _)| hit.clone())
+                .chain(compared)
+                .any(|policy| policy.is_random())
+            || args.admission == AdmissionPolicy::Probabilistic
+        {
+            println!("A deterministic run needs --seed to use a random policy or admission");
             return;
         }
+    }
+    // Picking a seed for the random policies if we were not given one, so that it can be recorded
+    let seed = args
+        .seed
+        .unwrap_or_else(|| rand::random_range(0..=MAX_SEED));
+    // If we were given a target hit ratio, we search for the cache size that reaches it instead of
+    // running a single size.
+    if let (Some(target), Some(max_size)) = (args.target_hit_ratio, args.max_size) {
+        let bounds = (size, max_size);
+        match search_size(
+            &args,
+            &raw_trace.items,
+            &item_trace,
+            bounds,
+            target,
+            (hit_policy, tiebreaking_policy),
+            seed,
+        ) {
+            Ok(Some(size)) => println!(
+                "Smallest cache size reaching hit ratio {}: {}",
+                target, size
+            ),
+            Ok(None) => println!(
+                "Hit ratio {} is not reachable with a cache size of at most {}",
+                target, max_size
+            ),
+            Err(msg) => println!("{}", msg),
+        }
+        return;
+    }
+    let context = RunContext {
+        args: &args,
+        items: &raw_trace.items,
+        trace: &item_trace,
+        input: in_path.to_string_lossy().to_string(),
+        canonicalized,
+        zipf_fit,
+        entropy,
+        // Each repetition gets its own seed derived from our base seed so that the whole
+        // experiment can be reproduced from that one seed
+        derived_seeds: args.repeat.map(|repeat| {
+            (
+                seed,
+                (0..repeat).map(|run| derive_seed(seed, run)).collect(),
+            )
+        }),
+        baseline,
     };
-    // Creating our Landlord instances
-    let s = Landlord::new(args.size, tiebreaking_policy, hit_policy);
-    let f = Landlord::new(args.size, tiebreaking_policy, hit_policy);
-    // Creating our logger
-    let mut logger = Logger::new(&item_trace);
-    // Running the caches on our trace with the logger
-    Landlord::run(item_trace, args.div, s, f, &mut logger);
-    // Creating a pretty logger instance for serialization
-    let display = PrettyLogger::new(logger);
-    // Serializing our pretty logger into a string
-    let output = display.ser_logger();
-    // Creating the output file
-    let out_file = File::create(args.out_path);
+    // If we were asked to compare hit policies, we run the trace once per policy, keeping the
+    // tiebreaking policy fixed, and key each run's results by its policy's name.
+    let mut checkpoint = args
+        .checkpoint
+        .map(|every| Checkpoint::new(format!("{}.partial", out_path), every));
+    let (output, scrs) = if matrix.is_some() {
+        // Each run only reads the trace we already resolved, so the runs can share it while each
+        // one simulates its own caches on its own thread.
+        let width = sizes.iter().max().map_or(1, |size| size.to_string().len());
+        let context = &context;
+        let results = std::thread::scope(|scope| {
+            let handles: Vec<_> = sizes
+                .iter()
+                .flat_map(|size| {
+                    policies
+                        .iter()
+                        .map(move |policies| (*size, policies.clone()))
+                })
+                .map(|(size, policies)| {
+                    scope.spawn(move || {
+                        let key = format!(
+                            "size_{:0width$}_{}_{}",
+                            size,
+                            policies.0.name(),
+                            policies.1.name(),
+                            width = width
+                        );
+                        let group = format!("{}_{}", policies.0.name(), policies.1.name());
+                        let RunResult { logger, .. } =
+                            run_once(context, size, div, policies, seed, |_| {})?;
+                        Ok::<_, SimError>((key, (size, group, PrettyLogger::new(logger))))
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("Matrix run panicked"))
+                .collect::<Result<Vec<_>, SimError>>()
+        });
+        let runs = match results {
+            Ok(runs) => runs,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        profile.lap("simulation");
+        // Groups the runs by their pair of policies so each pair gets its own curve over the sizes
+        let mut marginal_value = BTreeMap::new();
+        if args.marginal_value {
+            let mut groups: BTreeMap<&str, Vec<(u32, &PrettyLogger)>> = BTreeMap::new();
+            for (_, (size, group, display)) in runs.iter() {
+                groups.entry(group).or_default().push((*size, display));
+            }
+            for (group, runs) in groups {
+                marginal_value.insert(group.to_string(), MarginalValue::new(&runs));
+            }
+        }
+        let results: BTreeMap<String, PrettyLogger> = runs
+            .into_iter()
+            .map(|(key, (_, _, display))| (key, display))
+            .collect();
+        if !args.quiet {
+            for (key, display) in results.iter() {
+                println!("{}", key);
+                print!("{}", display.summary_table());
+            }
+        }
+        let scrs = results
+            .iter()
+            .map(|(key, display)| (key.clone(), display.get_scr()))
+            .collect();
+        let output = if !args.marginal_value {
+            render_comparison(&args, &results)
+        } else if args.human {
+            PrettyLogger::human_matrix(&results, &marginal_value)
+        } else {
+            let (output, replaced) =
+                PrettyLogger::ser_matrix(&results, &marginal_value, args.precision);
+            warn_non_finite(replaced);
+            output
+        };
+        (output, scrs)
+    }
+    // If we were asked to sweep the division point, the full cache services the trace once and a
+    // fresh suffix cache services the rest of the trace from each division point. Neither needs a
+    // logger since only their total costs are reported.
+    else if !args.div_sweep.is_empty() {
+        let policies = (hit_policy.clone(), tiebreaking_policy);
+        let full_cost = match build_caches(&args, &raw_trace.items, size, policies.clone(), seed) {
+            Ok((_, mut f)) => f.service_all(item_trace.iter()),
+            Err(msg) => {
+                println!("{}", msg);
+                return;
+            }
+        };
+        let mut points = Vec::new();
+        for div in divs.iter() {
+            match build_caches(&args, &raw_trace.items, size, policies.clone(), seed) {
+                Ok((Some(mut s), _)) => {
+                    points.push((*div, s.service_all(item_trace.range(*div as usize..))))
+                }
+                Ok((None, _)) => unreachable!("A sweep always has a suffix cache"),
+                Err(msg) => {
+                    println!("{}", msg);
+                    return;
+                }
+            }
+        }
+        profile.lap("simulation");
+        let sweep = DivSweep::new(context.run_info(seed), full_cost, &points, args.scr_floor);
+        if !args.quiet {
+            print!("{}", sweep.human_report());
+        }
+        let output = if args.human {
+            sweep.human_report()
+        } else {
+            let (output, replaced) = sweep.ser(args.precision);
+            warn_non_finite(replaced);
+            output
+        };
+        (output, sweep.get_scrs())
+    }
+    // If we were given several divisions, we run the trace once per division with a suffix cache
+    // starting at it, and key each run's results by where its suffix starts.
+    else if divs.len() > 1 {
+        let width = divs.iter().max().map_or(1, |div| div.to_string().len());
+        let mut results = BTreeMap::new();
+        for div in divs.iter() {
+            let policies = (hit_policy.clone(), tiebreaking_policy);
+            let logger = match run_once(&context, size, *div, policies, seed, |_| {}) {
+                Ok(result) => result.logger,
+                Err(e) => {
+                    println!("{}", e);
+                    return;
+                }
+            };
+            let display = PrettyLogger::new(logger);
+            let key = format!("div_{:0width$}", div, width = width);
+            if !args.quiet {
+                println!("{}", key);
+                print!("{}", display.summary_table());
+            }
+            results.insert(key, display);
+        }
+        profile.lap("simulation");
+        let scrs = results
+            .iter()
+            .map(|(key, display)| (key.clone(), display.get_scr()))
+            .collect();
+        (render_comparison(&args, &results), scrs)
+    } else if !args.compare_policies.is_empty() {
+        let mut results = BTreeMap::new();
+        for name in args.compare_policies.iter() {
+            let hit_policy = match registry.get(name) {
+                Some(policy) => policy,
+                None => {
+                    println!(
+                        "Invalid hit policy {}; select one of: {{{}}}",
+                        name,
+                        policy_names(registry.policies().map(|policy| policy.name()))
+                    );
+                    return;
+                }
+            };
+            let policies = (hit_policy.clone(), tiebreaking_policy);
+            let logger = match run_once(&context, size, div, policies, seed, |_| {}) {
+                Ok(result) => result.logger,
+                Err(e) => {
+                    println!("{}", e);
+                    return;
+                }
+            };
+            let display = PrettyLogger::new(logger);
+            if !args.quiet {
+                println!("{}", hit_policy.name());
+                print!("{}", display.summary_table());
+            }
+            results.insert(hit_policy.name().to_string(), display);
+        }
+        profile.lap("simulation");
+        let scrs = results
+            .iter()
+            .map(|(key, display)| (key.clone(), display.get_scr()))
+            .collect();
+        (render_comparison(&args, &results), scrs)
+    }
+    // If we were asked to repeat the run, we run the trace once with each of the seeds derived from
+    // our base seed.
+    else if let Some((_, seeds)) = &context.derived_seeds {
+        let width = seeds.len().saturating_sub(1).to_string().len();
+        let mut results = BTreeMap::new();
+        for (run, run_seed) in seeds.iter().enumerate() {
+            let policies = (hit_policy.clone(), tiebreaking_policy);
+            let logger = match run_once(&context, size, div, policies, *run_seed, |_| {}) {
+                Ok(result) => result.logger,
+                Err(e) => {
+                    println!("{}", e);
+                    return;
+                }
+            };
+            let display = PrettyLogger::new(logger);
+            let key = format!("run_{:0width$}", run, width = width);
+            if !args.quiet {
+                println!("{}", key);
+                print!("{}", display.summary_table());
+            }
+            results.insert(key, display);
+        }
+        profile.lap("simulation");
+        let scrs = results
+            .iter()
+            .map(|(key, display)| (key.clone(), display.get_scr()))
+            .collect();
+        (render_comparison(&args, &results), scrs)
+    } else {
+        let mut decision_log = match args.decision_log.as_deref().map(DecisionLog::create) {
+            Some(Ok(log)) => Some(log),
+            Some(Err(e)) => {
+                println!("Could not create decision log: {}", e);
+                return;
+            }
+            None => None,
+        };
+        profile.lap("setup");
+        // Running the caches on our trace, checkpointing the costs and logging the decisions as we
+        // go if we were asked to
+        let policies = (hit_policy, tiebreaking_policy);
+        let result = run_once(&context, size, div, policies, seed, |event| {
+            if let Some(checkpoint) = checkpoint.as_mut() {
+                checkpoint.observe(event);
+            }
+            if let Some(decision_log) = decision_log.as_mut() {
+                decision_log.observe(event);
+            }
+        });
+        profile.lap("simulation");
+        if let Some(Err(e)) = decision_log.map(DecisionLog::finish) {
+            println!("Could not write decision log: {}", e);
+            return;
+        }
+        let logger = match result {
+            Ok(result) => result.logger,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        // Writing out when each item was resident if we were asked to
+        if let Some(csv_path) = &args.residency_csv {
+            let written = File::create(csv_path)
+                .map_err(SimError::IoError)
+                .and_then(|mut file| {
+                    write_with_retry(&mut file, logger.residency_csv().as_bytes())
+                });
+            if let Err(e) = written {
+                println!("Could not write residency to {}: {}", csv_path.display(), e);
+                return;
+            }
+        }
+        // Creating a pretty logger instance for serialization
+        let display = PrettyLogger::new(logger);
+        // Printing a summary of the run unless we were asked to keep quiet
+        if !args.quiet {
+            print!("{}", display.summary_table());
+        }
+        // Serializing our pretty logger into a string, or writing it up for people to read
+        let output = if args.human {
+            display.human_report()
+        } else {
+            let (output, replaced) = display.ser_logger(args.precision);
+            warn_non_finite(replaced);
+            output
+        };
+        (output, vec![("run".to_string(), display.get_scr())])
+    };
+    profile.lap("serialization");
+    // Creating the output file. We write to a temporary file and rename it into place so that the
+    // output path never holds half-written results. Anything that is not a regular file, such as
+    // /dev/stdout, is written to directly since renaming over it would replace it.
+    let atomic = std::fs::metadata(&out_path)
+        .map(|metadata| metadata.is_file())
+        .unwrap_or(true);
+    let temp_path = if atomic {
+        format!("{}.tmp", out_path)
+    } else {
+        out_path.clone()
+    };
+    let out_file = File::create(&temp_path);
     // If we get an error, the output path was already taken or we do not have permission.
     if out_file.is_err() {
         println!("Output file path already taken.");
@@ -142,5 +1917,606 @@ fn main() {
     // Unwrapping the file if we passed the error testing.
     let mut out_file = out_file.unwrap();
     // Writing our serialized data structure into the file.
-    let _ = out_file.write_all(output.as_bytes());
+    if let Err(e) = write_with_retry(&mut out_file, output.as_bytes()) {
+        println!("Could not write output to {}: {}", out_path, e);
+        return;
+    }
+    // Writing only hands our data to the OS, so when asked we wait for it to reach the disk before
+    // moving it into place. Devices and pipes cannot be synced and are left alone.
+    if args.fsync && atomic {
+        if let Err(e) = out_file.sync_all() {
+            println!("Could not sync output to {}: {}", out_path, e);
+            return;
+        }
+    }
+    if atomic && std::fs::rename(&temp_path, &out_path).is_err() {
+        println!("Could not move output into place at {}", out_path);
+        return;
+    }
+    // The rename itself is only durable once the directory holding the output is synced too.
+    if args.fsync && atomic {
+        let dir = match Path::new(&out_path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        if let Err(e) = File::open(dir).and_then(|dir| dir.sync_all()) {
+            println!("Could not sync directory of {}: {}", out_path, e);
+            return;
+        }
+    }
+    // The full results are safely written, so we no longer need the checkpoint
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.remove();
+    }
+    profile.lap("writing");
+    if args.profile {
+        profile.report();
+    }
+    // Failing the run if any SCR went over the largest one we were willing to accept. The output
+    // has already been written so that the offending run can be inspected.
+    if let Some(max_scr) = args.max_scr {
+        let exceeded: Vec<&(String, f32)> = scrs.iter().filter(|(_, scr)| *scr > max_scr).collect();
+        for (key, scr) in exceeded.iter() {
+            println!(
+                "SCR of {} for {} exceeds the maximum of {}",
+                scr, key, max_scr
+            );
+        }
+        if !exceeded.is_empty() {
+            std::process::exit(SCR_EXCEEDED_EXIT_CODE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::landlord::{HitPolicy, RequestResult};
+    use std::collections::BTreeSet;
+
+    // Parses the arguments of a single run with unit cache size, adding on any extra arguments.
+    fn parse_args(extra: &[&str]) -> Args {
+        let base = [
+            "csim", "-i", "in.toml", "-o", "out.toml", "-s", "1", "-d", "0",
+        ];
+        Args::try_parse_from(base.iter().chain(extra)).expect("Arguments should parse")
+    }
+
+    fn unit_items(labels: &[&str]) -> Vec<Item> {
+        labels
+            .iter()
+            .map(|label| Item::new(label.to_string(), 1, 1))
+            .collect()
+    }
+
+    fn lru_policies() -> (Arc<dyn ReplacementPolicy>, TiebreakingPolicy) {
+        let registry = PolicyRegistry::with_builtins(DEFAULT_LRFU_LAMBDA);
+        (registry.get("LRU").unwrap(), TiebreakingPolicy::Lru)
+    }
+
+    // A trace cycling through every item ten times.
+    fn cyclic_trace(items: &[Item]) -> VecDeque<Request<'_>> {
+        (0..10)
+            .flat_map(|_| items.iter())
+            .map(|item| Request::new(item, None))
+            .collect()
+    }
+
+    #[test]
+    fn cache_size_is_checked_against_the_items() {
+        let items = vec![
+            Item::new("a".to_string(), 1, 2),
+            Item::new("b".to_string(), 1, 6),
+        ];
+        // Every item fitting is a hard requirement unless the oversized ones are never cached
+        assert!(validate_size(&items, 5, None).is_err());
+        assert!(validate_size(&items, 5, Some(5)).is_ok());
+        assert!(validate_size(&items, 6, None).is_ok());
+        let empty = vec![Item::new("z".to_string(), 1, 0)];
+        assert!(validate_size(&empty, 6, None).is_err());
+        // The average item has size 4, so the warning stops at a cache holding two of them
+        assert!(size_warning(&items, 6).is_some());
+        assert!(size_warning(&items, 7).is_some());
+        assert!(size_warning(&items, 8).is_none());
+        assert!(size_warning(&[], 1).is_none());
+    }
+
+    #[test]
+    fn division_fraction_splits_the_trace_proportionally() {
+        assert_eq!(resolve_divs(&[], Some(0.5), 100), Ok(vec![50]));
+        assert_eq!(resolve_divs(&[], Some(0.0), 100), Ok(vec![0]));
+        assert_eq!(resolve_divs(&[], Some(1.0), 100), Ok(vec![100]));
+        assert!(resolve_divs(&[], Some(1.5), 100).is_err());
+        assert!(resolve_divs(&[50], Some(0.5), 100).is_err());
+        assert!(resolve_divs(&[], None, 100).is_err());
+    }
+
+    // Builds a trace requesting the items with the given single-character labels in order.
+    fn label_trace<'a>(items: &'a [Item], labels: &str) -> VecDeque<Request<'a>> {
+        labels
+            .chars()
+            .map(|label| {
+                let item = items.iter().find(|n| *n.get_label() == label.to_string());
+                Request::new(item.unwrap(), None)
+            })
+            .collect()
+    }
+
+    // Gets the labels of the requests in a trace, in order.
+    fn labels(trace: &VecDeque<Request>) -> String {
+        trace.iter().map(|n| n.get_label().as_str()).collect()
+    }
+
+    #[test]
+    fn aliases_share_residency_with_their_item() {
+        let info: TraceInfo = toml::from_str(
+            "items = [{ label = 'a', cost = 2, size = 1 }, { label = 'b', cost = 1, size = 1 }]
+            trace = ['a', 'a?v=2', 'b', 'cdn/a', 'a']
+            aliases = { 'a?v=2' = 'a', 'cdn/a' = 'a' }",
+        )
+        .unwrap();
+        let (trace, canonicalized) = strings_to_items(&info).unwrap();
+        assert_eq!(canonicalized, 2);
+        assert_eq!(labels(&trace), "aabaa");
+        // Only the first request for a misses, whichever name later requests use
+        let mut cache = Landlord::new(2, TiebreakingPolicy::Lru, lru_policies().0).with_seed(0);
+        let hits: Vec<bool> = trace
+            .iter()
+            .map(|n| matches!(cache.request(*n), RequestResult::Hit))
+            .collect();
+        assert_eq!(hits, vec![false, true, false, true, true]);
+        assert_eq!(cache.get_cache_state().len(), 2);
+    }
+
+    #[test]
+    fn requests_for_unknown_labels_are_errors() {
+        let resolve = |trace: &str, aliases: &str| {
+            let info: TraceInfo = toml::from_str(&format!(
+                "items = [{{ label = 'a', cost = 1, size = 1 }}]
+                trace = [{}]
+                aliases = {{ {} }}",
+                trace, aliases
+            ))
+            .unwrap();
+            strings_to_items(&info).map(|(trace, _)| trace.len())
+        };
+        assert_eq!(resolve("'a', 'cdn/a'", "'cdn/a' = 'a'").unwrap(), 2);
+        // An alias for an item that is not in the catalog names both the alias and the item
+        match resolve("'a', 'cdn/b'", "'cdn/b' = 'b'") {
+            Err(e @ SimError::UnknownAlias { .. }) => assert_eq!(
+                e.to_string(),
+                "Alias cdn/b refers to item b which is not in the items table"
+            ),
+            other => panic!("Expected an unknown alias, got {:?}", other),
+        }
+        // A label that is neither an item nor an alias names the request that made it
+        match resolve("'a', 'a', 'c'", "'cdn/a' = 'a'") {
+            Err(e @ SimError::UnknownItem { .. }) => assert_eq!(
+                e.to_string(),
+                "Request 2 is for item c which is not in the items table"
+            ),
+            other => panic!("Expected an unknown item, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn timestamps_going_back_in_time_are_rejected() {
+        let trace = |entries: &str| {
+            let info: TraceInfo = toml::from_str(&format!(
+                "items = [{{ label = 'a', cost = 1, size = 1 }}]
+                trace = [{}]",
+                entries
+            ))
+            .unwrap();
+            info.trace
+        };
+        // Equal timestamps and requests without one are in order
+        let sorted = trace(
+            "{ label = 'a', timestamp = 1.0 }, 'a', { label = 'a', timestamp = 1.0 },
+            { label = 'a', timestamp = 2.5 }",
+        );
+        assert_eq!(validate_timestamps(&sorted), Ok(()));
+        // The untimestamped request between them does not hide the step back
+        let unsorted = trace(
+            "{ label = 'a', timestamp = 1.0 }, { label = 'a', timestamp = 3.0 }, 'a',
+            { label = 'a', timestamp = 2.0 }",
+        );
+        assert_eq!(
+            validate_timestamps(&unsorted),
+            Err("Request 3 for a has timestamp 2 before the earlier timestamp 3".to_string())
+        );
+    }
+
+    #[test]
+    fn tail_keeps_the_end_of_the_trace() {
+        let items = unit_items(&["a", "b", "c", "d"]);
+        // A tail as long as the trace or longer keeps all of it
+        for (tail, expected) in [("3", "abd"), ("7", "abcdabd"), ("100", "abcdabd")] {
+            let mut trace = label_trace(&items, "abcdabd");
+            select_requests(&parse_args(&["--tail", tail]), &mut trace).unwrap();
+            assert_eq!(labels(&trace), expected);
+        }
+    }
+
+    #[test]
+    fn reverse_runs_the_requests_back_to_front() {
+        let items = unit_items(&["a", "b", "c", "d"]);
+        let reverse = |extra: &[&str], requested: &str| {
+            let mut trace = label_trace(&items, requested);
+            select_requests(
+                &parse_args(&[&["--reverse"][..], extra].concat()),
+                &mut trace,
+            )
+            .unwrap();
+            labels(&trace)
+        };
+        assert_eq!(reverse(&[], "abcdabd"), "dbadcba");
+        // The tail is taken from the trace as written and then reversed
+        assert_eq!(reverse(&["--tail", "3"], "abcdabd"), "dba");
+        // A palindrome reads the same either way
+        assert_eq!(reverse(&[], "abcdcba"), "abcdcba");
+        assert_eq!(reverse(&[], "abba"), "abba");
+    }
+
+    #[test]
+    fn sampling_is_stable_and_keeps_whole_items() {
+        let items: Vec<Item> = (0..200)
+            .map(|n| Item::new(format!("item{}", n), 1, 1))
+            .collect();
+        let trace: VecDeque<Request> = (0..3)
+            .flat_map(|_| items.iter())
+            .map(|item| Request::new(item, None))
+            .collect();
+        let args = parse_args(&["--sample-rate", "0.5"]);
+        let sample = |args: &Args| {
+            let mut sampled = trace.clone();
+            select_requests(args, &mut sampled).unwrap();
+            sampled
+                .iter()
+                .map(|n| n.get_label().clone())
+                .collect::<Vec<String>>()
+        };
+        let first = sample(&args);
+        assert_eq!(sample(&args), first);
+        // Every item keeps all three of its requests or none of them
+        let kept: BTreeSet<&String> = first.iter().collect();
+        assert_eq!(first.len(), 3 * kept.len());
+        assert!(
+            (60..140).contains(&kept.len()),
+            "Sampled {} items",
+            kept.len()
+        );
+        // A lower rate keeps a subset of the items a higher one does
+        for label in sample(&parse_args(&["--sample-rate", "0.25"])).iter() {
+            assert!(kept.contains(label));
+        }
+        assert!(sample(&parse_args(&["--sample-rate", "0"])).is_empty());
+        assert_eq!(
+            sample(&parse_args(&["--sample-rate", "1"])).len(),
+            trace.len()
+        );
+        let mut rejected = trace.clone();
+        assert!(select_requests(&parse_args(&["--sample-rate", "1.5"]), &mut rejected).is_err());
+    }
+
+    #[test]
+    fn search_size_finds_the_knee_of_a_cyclic_trace() {
+        // LRU misses on every request of a loop over more items than it holds, and only misses
+        // the first request for each item once it holds all of them.
+        let items = unit_items(&["a", "b", "c"]);
+        let trace = cyclic_trace(&items);
+        let args = parse_args(&[]);
+        let found = search_size(&args, &items, &trace, (1, 8), 0.5, lru_policies(), 0);
+        assert_eq!(found, Ok(Some(3)));
+        let found = search_size(&args, &items, &trace, (1, 8), 0.95, lru_policies(), 0);
+        assert_eq!(found, Ok(None));
+    }
+
+    #[test]
+    fn search_size_builds_caches_with_every_option() {
+        // Preloading every item removes the cold misses, so only the configured cache reaches a
+        // perfect hit ratio.
+        let items = unit_items(&["a", "b", "c"]);
+        let trace = cyclic_trace(&items);
+        let plain = parse_args(&[]);
+        let preloaded = parse_args(&["--preload", "a,b,c"]);
+        let found = search_size(&plain, &items, &trace, (3, 8), 1.0, lru_policies(), 0);
+        assert_eq!(found, Ok(None));
+        let found = search_size(&preloaded, &items, &trace, (3, 8), 1.0, lru_policies(), 0);
+        assert_eq!(found, Ok(Some(3)));
+    }
+
+    #[test]
+    fn run_once_sets_up_every_run_the_same_way() {
+        let items = unit_items(&["a", "b", "c"]);
+        let trace = cyclic_trace(&items);
+        let args = parse_args(&["--preload", "a,b,c", "--validate"]);
+        let context = RunContext {
+            args: &args,
+            items: &items,
+            trace: &trace,
+            input: "in.toml".to_string(),
+            canonicalized: None,
+            zipf_fit: None,
+            entropy: None,
+            derived_seeds: None,
+            baseline: None,
+        };
+        // The preloaded items never miss, in the suffix cache as much as the full one
+        let result = run_once(&context, 3, 12, lru_policies(), 0, |_| {}).unwrap();
+        assert_eq!((result.full_hits, result.full_faults), (30, 0));
+        assert_eq!((result.suff_hits, result.suff_faults), (18, 0));
+        assert!(result.logger.reconcile(trace.len()).is_ok());
+        // Caches that cannot be set up as asked are reported rather than run
+        match run_once(&context, 2, 12, lru_policies(), 0, |_| {}) {
+            Err(SimError::Setup(msg)) => {
+                assert_eq!(msg, "Preloaded items do not fit in a cache of size 2")
+            }
+            other => panic!("Expected a setup error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn policy_listing_names_every_variant() {
+        // Matching exhaustively makes adding a variant without listing it fail to compile here
+        let hit_index = |policy: &HitPolicy| match policy {
+            HitPolicy::Lru => 0,
+            HitPolicy::Fifo => 1,
+            HitPolicy::Rand => 2,
+            HitPolicy::Half => 3,
+            HitPolicy::Static => 4,
+            HitPolicy::Lrfu(_) => 5,
+        };
+        let tiebreak_index = |policy: &TiebreakingPolicy| match policy {
+            TiebreakingPolicy::Lru => 0,
+            TiebreakingPolicy::Fifo => 1,
+            TiebreakingPolicy::Rand => 2,
+            TiebreakingPolicy::Lfu => 3,
+            TiebreakingPolicy::ExpectedValue => 4,
+        };
+        let hit = HitPolicy::variants();
+        let tiebreak = TiebreakingPolicy::variants();
+        assert_eq!(
+            hit.iter().map(hit_index).collect::<Vec<_>>(),
+            (0..6).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            tiebreak.iter().map(tiebreak_index).collect::<Vec<_>>(),
+            (0..5).collect::<Vec<_>>()
+        );
+        let listing = list_policies(&PolicyRegistry::with_builtins(DEFAULT_LRFU_LAMBDA));
+        let (hits, tiebreaks) = listing.split_once("Tiebreaking policies:").unwrap();
+        let listed = |section: &str, name: &str, description: &str| {
+            section.lines().any(|line| {
+                line.split_whitespace().next() == Some(name) && line.ends_with(description)
+            })
+        };
+        for policy in hit.iter() {
+            assert!(listed(hits, policy.name(), policy.description()));
+            for alias in policy.aliases() {
+                assert!(listed(hits, alias, &format!("alias for {}", policy.name())));
+            }
+        }
+        for policy in tiebreak.iter() {
+            assert!(listed(tiebreaks, policy.name(), policy.description()));
+        }
+    }
+
+    #[test]
+    fn policy_listing_gives_the_options_for_parameters_and_baselines() {
+        let listing = list_policies(&PolicyRegistry::with_builtins(DEFAULT_LRFU_LAMBDA));
+        let (policies, baselines) = listing.split_once("Baselines:").unwrap();
+        let listed = |section: &str, usage: &str, description: &str| {
+            section
+                .lines()
+                .any(|line| line.trim_start().starts_with(usage) && line.ends_with(description))
+        };
+        // Every option listed is one we accept once its placeholder is filled in
+        let accepted = |usage: &str| {
+            let (option, value) = usage.split_once(' ').unwrap();
+            let value = if value.starts_with('<') { "0.3" } else { value };
+            let base = [
+                "csim", "-i", "in.toml", "-o", "out.toml", "-s", "1", "-d", "0",
+            ];
+            Args::try_parse_from(base.iter().chain(&[option, value])).is_ok()
+        };
+        let lrfu = HitPolicy::Lrfu(DEFAULT_LRFU_LAMBDA);
+        assert_eq!(lrfu.parameters()[0].0, "--lrfu-lambda <LAMBDA>");
+        for policy in HitPolicy::variants() {
+            for (usage, description) in policy.parameters() {
+                assert!(listed(policies, usage, description));
+                assert!(accepted(usage), "{} is not accepted", usage);
+            }
+        }
+        assert!(HitPolicy::Lru.parameters().is_empty());
+        for baseline in Baseline::variants() {
+            assert!(listed(baselines, baseline.usage(), baseline.description()));
+            assert!(
+                accepted(baseline.usage()),
+                "{} is not accepted",
+                baseline.usage()
+            );
+        }
+        assert!(listed(
+            baselines,
+            "--baseline infinite",
+            "first request for each item"
+        ));
+        assert!(listed(
+            baselines,
+            "--opt-decisions <OPT FILE>",
+            "another tool made"
+        ));
+    }
+
+    #[test]
+    fn checkpoint_conflicts_with_runs_it_does_not_observe() {
+        // Only a single run feeds the checkpoint, so asking for one anywhere else is an error
+        // rather than a checkpoint that never gets written.
+        let base = [
+            "csim",
+            "-i",
+            "in.toml",
+            "-o",
+            "out.toml",
+            "--checkpoint",
+            "10",
+        ];
+        for extra in [
+            &["--matrix", "matrix.toml"][..],
+            &["-s", "1", "--target-hit-ratio", "0.5", "--max-size", "8"],
+            &["-s", "1", "--compare-policies", "LRU,FIFO"],
+            &["-s", "1", "--repeat", "2"],
+        ] {
+            assert!(Args::try_parse_from(base.iter().chain(extra)).is_err());
+        }
+        assert!(Args::try_parse_from(base.iter().chain(&["-s", "1"])).is_ok());
+    }
+
+    #[test]
+    fn repeat_needs_at_least_one_run() {
+        // Zero runs would write an empty report and exit as if it had succeeded
+        let base = [
+            "csim", "-i", "in.toml", "-o", "out.toml", "-s", "1", "-d", "0",
+        ];
+        assert!(Args::try_parse_from(base.iter().chain(&["--repeat", "0"])).is_err());
+        assert_eq!(parse_args(&["--repeat", "1"]).repeat, Some(1));
+    }
+
+    #[test]
+    fn epochs_need_at_least_one_request() {
+        let base = [
+            "csim", "-i", "in.toml", "-o", "out.toml", "-s", "1", "-d", "0",
+        ];
+        assert!(Args::try_parse_from(base.iter().chain(&["--epoch", "0"])).is_err());
+        assert_eq!(parse_args(&["--epoch", "1"]).epoch, Some(1));
+    }
+
+    #[test]
+    fn environment_fills_in_only_the_options_left_off() {
+        // This is the only test that touches the CSIM_* variables, so setting them cannot race
+        // with any other test
+        let vars = [
+            ("CSIM_SIZE", "2KiB"),
+            ("CSIM_DIV", "3, 5"),
+            ("CSIM_POLICIES", "HALF, FIFO"),
+            ("CSIM_SEED", "7"),
+            ("CSIM_HUMAN", "true"),
+        ];
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
+        let parse = |extra: &[&str]| {
+            let base = ["csim", "-i", "in.toml", "-o", "out.toml"];
+            let mut args = Args::try_parse_from(base.iter().chain(extra)).unwrap();
+            apply_env_fallbacks(&mut args).map(|_| args)
+        };
+
+        let args = parse(&[]).unwrap();
+        assert_eq!(args.size, Some(2048));
+        assert_eq!(args.div, vec![3, 5]);
+        assert_eq!(args.policies, vec!["HALF", "FIFO"]);
+        assert_eq!(args.seed, Some(7));
+        assert!(args.human);
+
+        // Flags take precedence over their variables, as do flags the variables would conflict
+        // with
+        let flags = [
+            &["-s", "4", "-d", "1", "-p", "LRU", "LRU"][..],
+            &["--seed", "9", "--precision", "2"],
+        ];
+        let args = parse(&flags.concat()).unwrap();
+        assert_eq!(args.size, Some(4));
+        assert_eq!(args.div, vec![1]);
+        assert_eq!(args.policies, vec!["LRU", "LRU"]);
+        assert_eq!(args.seed, Some(9));
+        assert!(!args.human);
+        let args = parse(&["--div-frac", "0.5"]).unwrap();
+        assert!(args.div.is_empty());
+
+        // A variable that does not parse is an error naming it
+        std::env::set_var("CSIM_SEED", "-1");
+        assert!(parse(&[]).unwrap_err().starts_with("CSIM_SEED"));
+        for (name, _) in vars {
+            std::env::remove_var(name);
+        }
+        assert_eq!(parse(&[]).unwrap().size, None);
+    }
+
+    // A writer whose writes fail with the given kind of error a number of times, recording what it
+    // was given once they stop failing. It can be made to accept only part of the first buffer it is
+    // given before the failures start, as a write interrupted partway through would.
+    struct FailingWriter {
+        failures: u32,
+        kind: ErrorKind,
+        partial: Option<usize>,
+        attempts: u32,
+        written: Vec<u8>,
+    }
+
+    impl FailingWriter {
+        fn new(failures: u32, kind: ErrorKind) -> Self {
+            Self {
+                failures,
+                kind,
+                partial: None,
+                attempts: 0,
+                written: Vec::new(),
+            }
+        }
+        fn with_partial(mut self, accepted: usize) -> Self {
+            self.partial = Some(accepted);
+            self
+        }
+    }
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.attempts += 1;
+            if let Some(accepted) = self.partial.take() {
+                let accepted = accepted.min(buf.len());
+                self.written.extend_from_slice(&buf[..accepted]);
+                return Ok(accepted);
+            }
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(std::io::Error::new(self.kind, "mock failure"));
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_failures_are_surfaced_or_retried() {
+        // A permanent error is returned straight away
+        let mut writer = FailingWriter::new(u32::MAX, ErrorKind::PermissionDenied);
+        let result = write_with_retry(&mut writer, b"data");
+        assert!(
+            matches!(result, Err(SimError::IoError(e)) if e.kind() == ErrorKind::PermissionDenied)
+        );
+        assert_eq!(writer.attempts, 1);
+
+        // A transient error that clears up is retried until the data is written
+        let mut writer = FailingWriter::new(2, ErrorKind::TimedOut);
+        assert!(write_with_retry(&mut writer, b"data").is_ok());
+        assert_eq!(writer.written, b"data");
+
+        // A transient error that outlasts every retry is still returned
+        let mut writer = FailingWriter::new(u32::MAX, ErrorKind::TimedOut);
+        let result = write_with_retry(&mut writer, b"data");
+        assert!(matches!(result, Err(SimError::IoError(e)) if e.kind() == ErrorKind::TimedOut));
+        assert_eq!(writer.attempts, WRITE_RETRIES + 1);
+        assert!(writer.written.is_empty());
+
+        // A write that fails after accepting part of the data resumes from where it stopped
+        // instead of writing the accepted part again
+        let mut writer = FailingWriter::new(2, ErrorKind::TimedOut).with_partial(3);
+        assert!(write_with_retry(&mut writer, b"abcdefgh").is_ok());
+        assert_eq!(writer.written, b"abcdefgh");
+        assert_eq!(writer.attempts, 4);
+    }
 }
@@ -0,0 +1,253 @@
+use crate::landlord::{HitPolicy, Item};
+// Rand is required for the random choices a policy may make
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::fmt;
+use std::sync::Arc;
+
+// What a replacement policy does with a resident item that was just requested. Refresh sets the
+// item's credit, keep leaves its credit alone but still lets the tiebreaking policy see the access
+// and ignore leaves both its credit and the tiebreaking order untouched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HitAction {
+    Refresh(f32),
+    Keep,
+    Ignore,
+}
+
+// What a policy is told about a resident item that was hit. The credit and cost are the item's
+// current credit and what it would be refreshed to in full. The CRF weight is the item's LRFU score
+// relative to the highest score in cache, and is one for policies that do not keep LRFU scores.
+#[derive(Debug, Clone, Copy)]
+pub struct HitContext<'c> {
+    pub item: &'c Item,
+    pub credit: f32,
+    pub cost: f32,
+    pub accesses: u32,
+    pub crf_weight: f32,
+}
+
+// A policy deciding how a cache refreshes credit when it hits, how much credit it gives items it
+// brings in and, if it wants to, which of the items tied for the least credit it evicts instead of
+// leaving that to the tiebreaking policy. Policies are selected by name from a PolicyRegistry, so a
+// new one only has to implement this trait and be registered.
+pub trait ReplacementPolicy: fmt::Debug + Send + Sync {
+    // The name used to select this policy, which is matched ignoring case.
+    fn name(&self) -> &str;
+    // Other names that also select this policy.
+    fn aliases(&self) -> &[&str] {
+        &[]
+    }
+    // A short description of what this policy does.
+    fn description(&self) -> &str;
+    // The command-line options that tune this policy, each with what it sets.
+    fn parameters(&self) -> &[(&str, &str)] {
+        &[]
+    }
+    // Decides what happens to an item's credit when it is hit.
+    fn on_hit(&self, hit: &HitContext, rng: &mut StdRng) -> HitAction;
+    // The credit an item is given when it is brought into cache, which is its cost by default.
+    fn on_insert(&self, _item: &Item, cost: f32) -> f32 {
+        cost
+    }
+    // Picks the index of the item to evict from those tied for the least credit, or None to let
+    // the tiebreaking policy decide.
+    fn choose_eviction(&self, _tied: &[&Item], _rng: &mut StdRng) -> Option<usize> {
+        None
+    }
+    // The decay of the combined recency and frequency scores the cache keeps for this policy, or
+    // None if it does not use them.
+    fn crf_lambda(&self) -> Option<f32> {
+        None
+    }
+    // Whether this policy makes random choices, so that a run using it is only reproducible with
+    // a seed.
+    fn is_random(&self) -> bool {
+        false
+    }
+}
+
+impl ReplacementPolicy for HitPolicy {
+    fn name(&self) -> &str {
+        HitPolicy::name(self)
+    }
+    fn aliases(&self) -> &[&str] {
+        HitPolicy::aliases(self)
+    }
+    fn description(&self) -> &str {
+        HitPolicy::description(self)
+    }
+    fn parameters(&self) -> &[(&str, &str)] {
+        HitPolicy::parameters(self)
+    }
+    fn on_hit(&self, hit: &HitContext, rng: &mut StdRng) -> HitAction {
+        match self {
+            // Refreshes it to its full cost.
+            Self::Lru => HitAction::Refresh(hit.cost),
+            // Does not refresh at all.
+            Self::Fifo => HitAction::Keep,
+            // Refreshes to a random value between current credit and cost. An item whose credit
+            // has not fallen yet keeps it, since there is nothing to draw from.
+            Self::Rand if hit.credit >= hit.cost => HitAction::Refresh(hit.credit),
+            Self::Rand => HitAction::Refresh(rng.random_range(hit.credit..=hit.cost)),
+            // Refreshes it to half its current credit.
+            Self::Half => HitAction::Refresh(hit.credit + (hit.cost - hit.credit) / 2.0),
+            // Does not refresh at all, and hides the hit from the tiebreaking policy.
+            Self::Static => HitAction::Ignore,
+            // Refreshes it by the item's share of the highest LRFU score, never lowering it.
+            Self::Lrfu(_) => HitAction::Refresh((hit.cost * hit.crf_weight).max(hit.credit)),
+        }
+    }
+    fn crf_lambda(&self) -> Option<f32> {
+        match self {
+            Self::Lrfu(lambda) => Some(*lambda),
+            _ => None,
+        }
+    }
+    fn is_random(&self) -> bool {
+        matches!(self, Self::Rand)
+    }
+}
+
+// The replacement policies that can be selected by name, in the order they are listed to users.
+// Lookups ignore case and also match each policy's aliases.
+#[derive(Debug, Clone, Default)]
+pub struct PolicyRegistry {
+    policies: Vec<Arc<dyn ReplacementPolicy>>,
+}
+
+impl PolicyRegistry {
+    // Creates a registry holding every built-in hit policy, with LRFU using the given decay.
+    pub fn with_builtins(lrfu_lambda: f32) -> Self {
+        let mut registry = Self::default();
+        for policy in HitPolicy::variants() {
+            registry.register(match policy {
+                HitPolicy::Lrfu(_) => HitPolicy::Lrfu(lrfu_lambda),
+                policy => policy,
+            });
+        }
+        registry
+    }
+    // Adds a policy, replacing any policy already registered under the same name.
+    pub fn register(&mut self, policy: impl ReplacementPolicy + 'static) {
+        self.policies
+            .retain(|n| !n.name().eq_ignore_ascii_case(policy.name()));
+        self.policies.push(Arc::new(policy));
+    }
+    // Finds the policy with the given name or alias, ignoring case.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn ReplacementPolicy>> {
+        self.policies
+            .iter()
+            .find(|n| {
+                n.name().eq_ignore_ascii_case(name)
+                    || n.aliases()
+                        .iter()
+                        .any(|alias| alias.eq_ignore_ascii_case(name))
+            })
+            .cloned()
+    }
+    // Every registered policy.
+    pub fn policies(&self) -> impl Iterator<Item = &Arc<dyn ReplacementPolicy>> {
+        self.policies.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::landlord::{Landlord, Request, RequestResult, TiebreakingPolicy};
+    use rand::SeedableRng;
+
+    // Gives every item the same credit whatever it costs and evicts the tied item whose label sorts
+    // last.
+    #[derive(Debug)]
+    struct LastLabel;
+
+    impl ReplacementPolicy for LastLabel {
+        fn name(&self) -> &str {
+            "LAST"
+        }
+        fn aliases(&self) -> &[&str] {
+            &["last-label"]
+        }
+        fn description(&self) -> &str {
+            "Evicts the tied item whose label sorts last"
+        }
+        fn on_hit(&self, _hit: &HitContext, _rng: &mut StdRng) -> HitAction {
+            HitAction::Refresh(1.0)
+        }
+        fn on_insert(&self, _item: &Item, _cost: f32) -> f32 {
+            1.0
+        }
+        fn choose_eviction(&self, tied: &[&Item], _rng: &mut StdRng) -> Option<usize> {
+            (0..tied.len()).max_by_key(|n| tied[*n].get_label())
+        }
+    }
+
+    #[test]
+    fn registered_policy_is_found_by_name_and_run() {
+        let mut registry = PolicyRegistry::with_builtins(0.5);
+        let builtins = registry.policies().count();
+        registry.register(LastLabel);
+        assert_eq!(registry.policies().count(), builtins + 1);
+        assert_eq!(registry.get("last").unwrap().name(), "LAST");
+        assert_eq!(registry.get("Last-Label").unwrap().name(), "LAST");
+        registry.register(LastLabel);
+        assert_eq!(registry.policies().count(), builtins + 1);
+
+        // c costs the most, but the policy gives every item the same credit so all three tie and
+        // it picks c over the LRU item a
+        let items = [
+            Item::new("a".to_string(), 1, 1),
+            Item::new("b".to_string(), 1, 1),
+            Item::new("c".to_string(), 9, 1),
+            Item::new("d".to_string(), 1, 1),
+        ];
+        let policy = registry.get("LAST").unwrap();
+        let mut cache = Landlord::new(3, TiebreakingPolicy::Lru, policy).with_seed(0);
+        for item in items.iter() {
+            cache.request(Request::new(item, None));
+        }
+        let resident: Vec<String> = cache.get_cache_state().into_keys().collect();
+        assert_eq!(resident, vec!["a", "b", "d"]);
+        let credits: Vec<f32> = cache.get_credits().iter().map(|n| n.0).collect();
+        assert!(credits.iter().all(|credit| *credit <= 1.0));
+    }
+
+    #[test]
+    fn random_hits_refresh_between_credit_and_cost() {
+        let item = Item::new("a".to_string(), 4, 1);
+        let mut rng = StdRng::seed_from_u64(5);
+        let hit = |credit: f32| HitContext {
+            item: &item,
+            credit,
+            cost: 4.0,
+            accesses: 1,
+            crf_weight: 1.0,
+        };
+        // An item still at full credit has nothing to draw from and keeps it
+        assert_eq!(HitPolicy::Rand.on_hit(&hit(4.0), &mut rng), HitAction::Refresh(4.0));
+        for _ in 0..32 {
+            match HitPolicy::Rand.on_hit(&hit(1.0), &mut rng) {
+                HitAction::Refresh(credit) => assert!((1.0..=4.0).contains(&credit)),
+                action => panic!("Random hit did not refresh: {:?}", action),
+            }
+        }
+
+        // A cache that hits straight after bringing items in, and again after they lost credit
+        let items = [
+            Item::new("a".to_string(), 1, 1),
+            Item::new("b".to_string(), 2, 1),
+            Item::new("c".to_string(), 3, 1),
+        ];
+        let policy = PolicyRegistry::with_builtins(0.5).get("RAND").unwrap();
+        let mut cache = Landlord::new(2, TiebreakingPolicy::Lru, policy).with_seed(5);
+        let mut hits = 0;
+        for n in [0, 0, 1, 1, 2, 1, 0, 1, 2] {
+            if matches!(cache.request(Request::new(&items[n], None)), RequestResult::Hit) {
+                hits += 1;
+            }
+        }
+        assert!(hits >= 3);
+    }
+}
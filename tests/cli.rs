@@ -0,0 +1,403 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A directory under the system temp dir that is removed when dropped.
+struct Scratch {
+    dir: PathBuf,
+}
+
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("csim-cli-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Scratch { dir }
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        self.dir.join(file)
+    }
+
+    fn write(&self, file: &str, contents: &str) -> PathBuf {
+        let path = self.path(file);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+const TRACE: &str = r#"
+items = [
+  { label = "a", cost = 1, size = 1 },
+  { label = "b", cost = 2, size = 1 },
+  { label = "c", cost = 3, size = 1 },
+]
+trace = ["a", "b", "c", "a", "b", "a", "c", "a", "b", "c"]
+"#;
+
+fn csim(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_csim"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Runs the simulator from `input` to `output` with the whitespace-separated
+/// flags in `flags`.
+fn simulate(input: &Path, output: &Path, flags: &str) -> Output {
+    let mut args = vec![
+        "-i",
+        input.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+    ];
+    args.extend(flags.split_whitespace());
+    csim(&args)
+}
+
+fn read_toml(path: &Path) -> toml::Table {
+    toml::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+/// Drops the run info, which records the command line and so differs between
+/// otherwise identical runs.
+fn without_run_info(mut table: toml::Table) -> toml::Table {
+    table.remove("run_info");
+    table
+}
+
+#[test]
+fn compared_policies_match_their_standalone_runs() {
+    let scratch = Scratch::new("compare");
+    let input = scratch.write("in.toml", TRACE);
+    let compared = scratch.path("compared.toml");
+    let flags = "-s 2 -d 3 -p LRU LRU --compare-policies LRU,FIFO";
+    assert!(simulate(&input, &compared, flags).status.success());
+    let compared = read_toml(&compared);
+    assert_eq!(compared.len(), 2);
+
+    for policy in ["LRU", "FIFO"] {
+        let single = scratch.path(&format!("{}.toml", policy));
+        let flags = format!("-s 2 -d 3 -p {} LRU", policy);
+        assert!(simulate(&input, &single, &flags).status.success());
+        let entry = compared[policy].as_table().unwrap().clone();
+        assert_eq!(
+            without_run_info(entry),
+            without_run_info(read_toml(&single))
+        );
+    }
+}
+
+#[test]
+fn smaller_suffix_cache_pays_more() {
+    let scratch = Scratch::new("suffix-size");
+    let input = scratch.write("in.toml", TRACE);
+    let output = scratch.path("out.toml");
+    let summary = |flags: &str| {
+        assert!(simulate(&input, &output, flags).status.success());
+        read_toml(&output)["summary"].as_table().unwrap().clone()
+    };
+
+    // Both caches start together, so with equal sizes they pay the same
+    let equal = summary("-s 2 -d 0 -p LRU LRU --suffix-size 2");
+    assert_eq!(equal["full_cost"], equal["suff_cost"]);
+    assert_eq!(equal["scr"].as_float(), Some(1.0));
+
+    // A single slot cannot hold any item until its next request, so every request misses
+    let smaller = summary("-s 2 -d 0 -p LRU LRU --suffix-size 1");
+    assert_eq!(smaller["full_cost"], equal["full_cost"]);
+    assert_eq!(smaller["suff_cost"].as_integer(), Some(19));
+    assert_eq!(smaller["suff_hit_ratio"].as_float(), Some(0.0));
+    assert!(smaller["scr"].as_float().unwrap() > 1.0);
+}
+
+#[test]
+fn repeated_runs_reproduce_from_the_base_seed() {
+    let scratch = Scratch::new("repeat");
+    let input = scratch.write(
+        "in.toml",
+        r#"
+items = [
+  { label = "a", cost = 1, size = 1 },
+  { label = "b", cost = 1, size = 1 },
+  { label = "c", cost = 1, size = 1 },
+  { label = "d", cost = 1, size = 1 },
+  { label = "e", cost = 1, size = 1 },
+]
+trace = ["a", "b", "c", "d", "e", "a", "c", "e", "b", "d", "a", "b", "c", "d", "e", "e", "d", "c", "b", "a"]
+"#,
+    );
+    let flags = "-s 3 -d 5 -p LRU LRU --admission probabilistic --repeat 4 --seed 42";
+    let first = scratch.path("first.toml");
+    let second = scratch.path("second.toml");
+    assert!(simulate(&input, &first, flags).status.success());
+    assert!(simulate(&input, &second, flags).status.success());
+    let first = read_toml(&first);
+    let second = read_toml(&second);
+    assert_eq!(first.len(), 4);
+
+    let info = first["run_0"]["run_info"].as_table().unwrap();
+    assert_eq!(info["base_seed"].as_integer(), Some(42));
+    let seeds = info["derived_seeds"].as_array().unwrap();
+    assert_eq!(seeds.len(), 4);
+    for (run, seed) in seeds.iter().enumerate() {
+        let key = format!("run_{}", run);
+        assert_eq!(&first[&key]["run_info"]["seed"], seed);
+        assert_eq!(
+            first[&key]["run_info"]["seed"],
+            second[&key]["run_info"]["seed"]
+        );
+        assert_eq!(
+            without_run_info(first[&key].as_table().unwrap().clone()),
+            without_run_info(second[&key].as_table().unwrap().clone())
+        );
+    }
+    // The runs themselves draw from different seeds, so they do not all admit the same items
+    let costs: Vec<_> = first
+        .values()
+        .map(|run| &run["summary"]["full_cost"])
+        .collect();
+    assert!(costs.iter().any(|cost| *cost != costs[0]));
+}
+
+#[test]
+fn max_scr_fails_the_run_only_when_exceeded() {
+    let scratch = Scratch::new("max-scr");
+    let input = scratch.write("in.toml", TRACE);
+    let output = scratch.path("out.toml");
+    // The suffix cache pays 9 against the full cache's 15, an SCR of 0.6
+    let passed = simulate(&input, &output, "-s 2 -d 3 -p LRU LRU --max-scr 0.7");
+    assert_eq!(passed.status.code(), Some(0));
+    let scr = read_toml(&output)["summary"]["scr"].as_float().unwrap();
+    assert!((scr - 0.6).abs() < 1e-6);
+
+    fs::remove_file(&output).unwrap();
+    let failed = simulate(&input, &output, "-s 2 -d 3 -p LRU LRU --max-scr 0.5");
+    assert_eq!(failed.status.code(), Some(3));
+    let stdout = String::from_utf8_lossy(&failed.stdout);
+    assert!(stdout.contains("exceeds the maximum of 0.5"));
+    // The results are still written before failing
+    assert!((read_toml(&output)["summary"]["scr"].as_float().unwrap() - scr).abs() < 1e-6);
+}
+
+#[test]
+fn output_is_complete_once_the_run_returns() {
+    let scratch = Scratch::new("fsync");
+    let labels: Vec<String> = (0..200).map(|n| format!("\"i{}\"", n % 10)).collect();
+    let items: Vec<String> = (0..10)
+        .map(|n| {
+            format!(
+                "  {{ label = \"i{}\", cost = {}, size = 1 }},",
+                n,
+                n % 5 + 1
+            )
+        })
+        .collect();
+    let input = scratch.write(
+        "in.toml",
+        &format!(
+            "items = [\n{}\n]\ntrace = [{}]\n",
+            items.join("\n"),
+            labels.join(", ")
+        ),
+    );
+    for flags in ["-s 4 -d 100 -p LRU LRU", "-s 4 -d 100 -p LRU LRU --fsync"] {
+        let output = scratch.path("out.toml");
+        assert!(simulate(&input, &output, flags).status.success());
+        // The whole of the output is there to be parsed, up to the last request's state
+        let written = read_toml(&output);
+        assert_eq!(written["full_costs"].as_array().unwrap().len(), 200);
+        assert_eq!(written["full_states"].as_array().unwrap().len(), 200);
+        fs::remove_file(&output).unwrap();
+    }
+}
+
+#[test]
+fn deterministic_runs_need_a_seed_for_randomness() {
+    let scratch = Scratch::new("deterministic");
+    let input = scratch.write("in.toml", TRACE);
+    let output = scratch.path("out.toml");
+    let message = "A deterministic run needs --seed";
+    for random in [
+        "-p RAND LRU",
+        "-p LRU RAND",
+        "-p LRU LRU --admission probabilistic",
+    ] {
+        let flags = format!("-s 2 -d 3 --deterministic {}", random);
+        let rejected = simulate(&input, &output, &flags);
+        assert!(String::from_utf8_lossy(&rejected.stdout).contains(message));
+        assert!(!output.exists(), "{} ran without a seed", random);
+    }
+
+    // With a seed the run goes ahead, and running it again gives the same results
+    let flags = "-s 2 -d 3 --deterministic --seed 5 -p LRU LRU --admission probabilistic";
+    let mut runs = Vec::new();
+    for _ in 0..2 {
+        let accepted = simulate(&input, &output, flags);
+        assert!(accepted.status.success());
+        assert!(!String::from_utf8_lossy(&accepted.stdout).contains(message));
+        runs.push(without_run_info(read_toml(&output)));
+        fs::remove_file(&output).unwrap();
+    }
+    assert_eq!(runs[0], runs[1]);
+    // Random hit and tiebreaking policies both run with a seed, and again give the same results
+    for random in ["-p RAND LRU", "-p LRU RAND"] {
+        let flags = format!("-s 2 -d 3 --deterministic --seed 5 {}", random);
+        let mut runs = Vec::new();
+        for _ in 0..2 {
+            assert!(simulate(&input, &output, &flags).status.success());
+            runs.push(without_run_info(read_toml(&output)));
+            fs::remove_file(&output).unwrap();
+        }
+        assert_eq!(runs[0], runs[1], "{} is not reproducible", random);
+    }
+    // Without the flag a random policy is free to pick its own seed
+    for random in ["-p RAND LRU", "-p LRU RAND"] {
+        let flags = format!("-s 2 -d 3 {}", random);
+        assert!(simulate(&input, &output, &flags).status.success());
+        assert!(output.exists());
+        fs::remove_file(&output).unwrap();
+    }
+}
+
+#[test]
+fn no_suffix_run_matches_the_full_cache_of_a_normal_run() {
+    let scratch = Scratch::new("no-suffix");
+    let input = scratch.write("in.toml", TRACE);
+    let normal = scratch.path("normal.toml");
+    let alone = scratch.path("alone.toml");
+    assert!(simulate(&input, &normal, "-s 2 -d 3 -p LRU LRU")
+        .status
+        .success());
+    assert!(simulate(&input, &alone, "-s 2 -p LRU LRU --no-suffix")
+        .status
+        .success());
+    let normal = read_toml(&normal);
+    let alone = read_toml(&alone);
+
+    // Every full cache series and statistic is the same, and nothing of the suffix is left
+    fn assert_full_only(alone: &toml::Table, normal: &toml::Table) {
+        for (key, value) in alone.iter().filter(|(key, _)| *key != "run_info") {
+            assert!(!key.starts_with("suff") && key != "scr" && key != "ind_scr");
+            match value.as_table() {
+                Some(table) => assert_full_only(table, normal[key].as_table().unwrap()),
+                None => assert_eq!(&normal[key], value, "{} differs", key),
+            }
+        }
+    }
+    assert_full_only(&alone, &normal);
+    assert!(alone.contains_key("full_states"));
+    assert!(!alone.contains_key("ind_scr"));
+}
+
+#[test]
+fn matrix_runs_match_their_individual_runs() {
+    let scratch = Scratch::new("matrix");
+    let input = scratch.write("in.toml", TRACE);
+    let matrix = scratch.write(
+        "matrix.toml",
+        "sizes = [1, 2]\npolicies = [[\"LRU\", \"LRU\"], [\"FIFO\", \"LRU\"]]\n",
+    );
+    let combined = scratch.path("combined.toml");
+    let flags = format!("-d 3 --matrix {}", matrix.to_str().unwrap());
+    assert!(simulate(&input, &combined, &flags).status.success());
+    let combined = read_toml(&combined);
+    assert_eq!(combined.len(), 4);
+
+    for size in [1, 2] {
+        for hit in ["LRU", "FIFO"] {
+            let key = format!("size_{}_{}_LRU", size, hit);
+            let single = scratch.path(&format!("{}.toml", key));
+            let flags = format!("-s {} -d 3 -p {} LRU", size, hit);
+            assert!(simulate(&input, &single, &flags).status.success());
+            let entry = combined[&key].as_table().unwrap().clone();
+            assert_eq!(
+                without_run_info(entry),
+                without_run_info(read_toml(&single)),
+                "{} differs",
+                key
+            );
+        }
+    }
+}
+
+#[test]
+fn two_splits_give_two_suffix_cost_series() {
+    let scratch = Scratch::new("splits");
+    let input = scratch.write("in.toml", TRACE);
+    let split = scratch.path("split.toml");
+    assert!(simulate(&input, &split, "-s 2 -d 3,6 -p LRU LRU")
+        .status
+        .success());
+    let split = read_toml(&split);
+    assert_eq!(split.len(), 2);
+    let series = |div: &str| split[&format!("div_{}", div)]["suff_costs"].clone();
+    assert_ne!(series("3"), series("6"));
+    // The suffix cache starting later pays nothing before it starts
+    let later = series("6");
+    let later = later.as_array().unwrap();
+    assert!(later[..6].iter().all(|n| n.as_integer() == Some(0)));
+
+    // Each split is the same as a run divided there alone
+    for div in ["3", "6"] {
+        let single = scratch.path(&format!("{}.toml", div));
+        let flags = format!("-s 2 -d {} -p LRU LRU", div);
+        assert!(simulate(&input, &single, &flags).status.success());
+        let entry = split[&format!("div_{}", div)].as_table().unwrap().clone();
+        assert_eq!(
+            without_run_info(entry),
+            without_run_info(read_toml(&single))
+        );
+    }
+
+    // Splits out of order or past the end of the trace are rejected
+    let output = scratch.path("rejected.toml");
+    for (divs, message) in [("6,3", "ascending order"), ("3,11", "past the end")] {
+        let flags = format!("-s 2 -d {} -p LRU LRU", divs);
+        let rejected = simulate(&input, &output, &flags);
+        assert!(String::from_utf8_lossy(&rejected.stdout).contains(message));
+        assert!(!output.exists());
+    }
+}
+
+#[test]
+fn swept_divisions_match_their_standalone_runs() {
+    let scratch = Scratch::new("sweep");
+    let input = scratch.write("in.toml", TRACE);
+    let swept = scratch.path("swept.toml");
+    assert!(
+        simulate(&input, &swept, "-s 2 -p LRU LRU --div-sweep 0,10,2")
+            .status
+            .success()
+    );
+    let swept = read_toml(&swept);
+    let points = swept["points"].as_array().unwrap();
+    let divs: Vec<i64> = points
+        .iter()
+        .map(|n| n["div"].as_integer().unwrap())
+        .collect();
+    assert_eq!(divs, vec![0, 2, 4, 6, 8, 10]);
+
+    // Every point has the SCR and costs of a run divided there alone
+    for point in points.iter() {
+        let div = point["div"].as_integer().unwrap();
+        let single = scratch.path(&format!("{}.toml", div));
+        let flags = format!("-s 2 -d {} -p LRU LRU", div);
+        assert!(simulate(&input, &single, &flags).status.success());
+        let summary = read_toml(&single)["summary"].as_table().unwrap().clone();
+        assert_eq!(
+            point["scr"].as_float(),
+            summary["scr"].as_float(),
+            "div {}",
+            div
+        );
+        assert_eq!(point["suff_cost"], summary["suff_cost"]);
+        assert_eq!(swept["full_cost"], summary["full_cost"]);
+    }
+}